@@ -48,11 +48,11 @@ fn simulate_rag(file: &PathBuf) -> String {
     let dir = file.parent().unwrap();
     for entry in std::fs::read_dir(dir).unwrap() {
         let path = entry.unwrap().path();
-        if path.extension().map_or(false, |e| e == "rs") {
-            if let Ok(content) = std::fs::read_to_string(&path) {
-                context.push_str("\n\n");
-                context.push_str(&content);
-            }
+        if path.extension().is_some_and(|e| e == "rs")
+            && let Ok(content) = std::fs::read_to_string(&path)
+        {
+            context.push_str("\n\n");
+            context.push_str(&content);
         }
     }
     
@@ -0,0 +1,588 @@
+use crate::graph::{CodeNode, DependencyGraph, Edge, EdgeType, NodeId};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// A graph with every cycle collapsed into a single super-node
+pub struct Condensation {
+    pub graph: DependencyGraph,
+    /// Original node -> the node standing for its component in `graph`
+    pub component_of: HashMap<NodeId, NodeId>,
+    /// Representative -> component members, in position order
+    pub members: HashMap<NodeId, Vec<NodeId>>,
+}
+
+/// Callbacks for `DependencyGraph::walk`, a depth-first traversal.
+/// All methods have no-op defaults; implement only what an analysis needs.
+pub trait GraphVisitor {
+    /// Called the first time a node is reached; return false to not descend into it
+    fn enter_node(&mut self, _node: &CodeNode, _depth: usize) -> bool {
+        true
+    }
+
+    /// Called once all of a node's descendants are done
+    fn leave_node(&mut self, _node: &CodeNode, _depth: usize) {}
+
+    /// Called for every edge out of an entered node, including back edges to visited nodes
+    fn visit_edge(&mut self, _edge: &Edge) {}
+}
+
+/// Immediate dominators of nodes reachable from a root: every path from the root
+/// to a node passes through each of its dominators.
+pub struct DominatorTree {
+    root: NodeId,
+    idom: HashMap<NodeId, NodeId>,
+}
+
+impl DominatorTree {
+    /// The closest node every path from the root to `id` must pass through.
+    /// None for the root and for unreachable nodes.
+    pub fn immediate_dominator(&self, id: &NodeId) -> Option<&NodeId> {
+        self.idom.get(id)
+    }
+
+    /// All strict dominators of `id`, nearest first, ending with the root
+    pub fn dominators_of(&self, id: &NodeId) -> Vec<NodeId> {
+        let mut chain = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.idom.get(current) {
+            chain.push(parent.clone());
+            current = parent;
+        }
+        chain
+    }
+
+    /// True if every path from the root to `b` goes through `a` (a node dominates itself)
+    pub fn dominates(&self, a: &NodeId, b: &NodeId) -> bool {
+        if a == b {
+            return b == &self.root || self.idom.contains_key(b);
+        }
+        self.dominators_of(b).contains(a)
+    }
+}
+
+/// Records postorder positions for `dominators`
+struct Postorder(Vec<NodeId>);
+
+impl GraphVisitor for Postorder {
+    fn leave_node(&mut self, node: &CodeNode, _depth: usize) {
+        self.0.push(node.id.clone());
+    }
+}
+
+/// Records preorder positions for `dfs_from`
+struct Preorder(Vec<(NodeId, usize)>);
+
+impl GraphVisitor for Preorder {
+    fn enter_node(&mut self, node: &CodeNode, depth: usize) -> bool {
+        self.0.push((node.id.clone(), depth));
+        true
+    }
+}
+
+impl DependencyGraph {
+    /// Nodes reachable from `root` in depth-first preorder, with their tree depth
+    pub fn dfs_from(&self, root: &NodeId) -> Vec<(NodeId, usize)> {
+        let mut preorder = Preorder(Vec::new());
+        self.walk(root, &mut preorder);
+        preorder.0
+    }
+
+    /// Depth-first traversal from `root`, following edges in insertion order
+    pub fn walk(&self, root: &NodeId, visitor: &mut impl GraphVisitor) {
+        let Some(root_node) = self.nodes.get(root) else { return };
+        let mut visited: HashSet<&NodeId> = HashSet::from([&root_node.id]);
+
+        // Explicit stack of (node, depth, outgoing edges, next edge) so deep graphs can't overflow
+        let mut stack = Vec::new();
+        if visitor.enter_node(root_node, 0) {
+            stack.push((root_node, 0, self.outgoing_edges(&root_node.id).collect::<Vec<_>>(), 0));
+        } else {
+            visitor.leave_node(root_node, 0);
+        }
+
+        while let Some(frame) = stack.last_mut() {
+            let (node, depth) = (frame.0, frame.1);
+            let Some(edge) = frame.2.get(frame.3).copied() else {
+                stack.pop();
+                visitor.leave_node(node, depth);
+                continue;
+            };
+            frame.3 += 1;
+
+            visitor.visit_edge(edge);
+            let Some(next) = self.nodes.get(&edge.to) else { continue };
+            if !visited.insert(&next.id) {
+                continue;
+            }
+            if visitor.enter_node(next, depth + 1) {
+                stack.push((next, depth + 1, self.outgoing_edges(&next.id).collect(), 0));
+            } else {
+                visitor.leave_node(next, depth + 1);
+            }
+        }
+    }
+
+    /// Dominator tree of the nodes reachable from `root`
+    /// (Cooper, Harvey & Kennedy, "A Simple, Fast Dominance Algorithm")
+    pub fn dominators(&self, root: &NodeId) -> DominatorTree {
+        let mut postorder = Postorder(Vec::new());
+        self.walk(root, &mut postorder);
+        let order = postorder.0;
+        let number: HashMap<&NodeId, usize> = order.iter().enumerate().map(|(i, id)| (id, i)).collect();
+
+        // idom by postorder number; the root is last in postorder and is its own idom
+        let mut idom: Vec<Option<usize>> = vec![None; order.len()];
+        let Some(root_number) = order.len().checked_sub(1) else {
+            return DominatorTree { root: root.clone(), idom: HashMap::new() };
+        };
+        idom[root_number] = Some(root_number);
+
+        let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while a < b {
+                    a = idom[a].expect("processed node has an idom");
+                }
+                while b < a {
+                    b = idom[b].expect("processed node has an idom");
+                }
+            }
+            a
+        };
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            // Reverse postorder, skipping the root
+            for b in (0..root_number).rev() {
+                let mut new_idom = None;
+                for edge in self.incoming_edges(&order[b]) {
+                    let Some(&p) = number.get(&edge.from) else { continue };
+                    if idom[p].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => p,
+                        Some(current) => intersect(&idom, p, current),
+                    });
+                }
+                if new_idom.is_some() && idom[b] != new_idom {
+                    idom[b] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+
+        let idom = (0..root_number)
+            .filter_map(|b| idom[b].map(|d| (order[b].clone(), order[d].clone())))
+            .collect();
+        DominatorTree { root: root.clone(), idom }
+    }
+
+    /// Dependency targets of `node` (skipping structural edges and unknown nodes), sorted by
+    /// position so traversals don't depend on edge insertion order
+    fn successors<'a>(&'a self, node: &'a NodeId) -> Vec<&'a NodeId> {
+        let mut successors: Vec<&NodeId> = self
+            .outgoing_edges(node)
+            .filter(|e| !e.edge_type.is_structural())
+            .map(|e| &e.to)
+            .filter(|to| self.nodes.contains_key(*to))
+            .collect();
+        successors.sort();
+        successors
+    }
+
+    /// Tarjan's algorithm over dependency (non-structural) edges. Each component is sorted by position; components come
+    /// out in reverse topological order (dependencies before their dependents).
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        let mut ids: Vec<&NodeId> = self.nodes.keys().collect();
+        ids.sort();
+
+        let mut next_index = 0;
+        let mut index: HashMap<&NodeId, usize> = HashMap::new();
+        let mut lowlink: HashMap<&NodeId, usize> = HashMap::new();
+        let mut on_stack: HashSet<&NodeId> = HashSet::new();
+        let mut stack: Vec<&NodeId> = Vec::new();
+        let mut components = Vec::new();
+
+        for start in ids {
+            if index.contains_key(start) {
+                continue;
+            }
+
+            // Explicit frames of (node, successors, next successor) so deep call chains can't overflow
+            let mut frames = vec![(start, self.successors(start), 0)];
+            index.insert(start, next_index);
+            lowlink.insert(start, next_index);
+            next_index += 1;
+            stack.push(start);
+            on_stack.insert(start);
+
+            while let Some(frame) = frames.last_mut() {
+                let node = frame.0;
+                let successor = frame.1.get(frame.2).copied();
+                frame.2 += 1;
+
+                if let Some(successor) = successor {
+                    if !index.contains_key(successor) {
+                        index.insert(successor, next_index);
+                        lowlink.insert(successor, next_index);
+                        next_index += 1;
+                        stack.push(successor);
+                        on_stack.insert(successor);
+                        frames.push((successor, self.successors(successor), 0));
+                    } else if on_stack.contains(successor) {
+                        let low = lowlink[node].min(index[successor]);
+                        lowlink.insert(node, low);
+                    }
+                    continue;
+                }
+
+                frames.pop();
+                if let Some(parent) = frames.last() {
+                    let low = lowlink[parent.0].min(lowlink[node]);
+                    lowlink.insert(parent.0, low);
+                }
+
+                if lowlink[node] == index[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack.remove(member);
+                        component.push(member.clone());
+                        if member == node {
+                            break;
+                        }
+                    }
+                    component.sort();
+                    components.push(component);
+                }
+            }
+        }
+
+        components
+    }
+
+    /// Nodes reachable from `root` with dependencies before their users, so `root` comes
+    /// last. Members of a cycle stay together, in position order.
+    pub fn topo_order(&self, root: &NodeId) -> Vec<NodeId> {
+        let reachable: HashSet<NodeId> = self.bfs_from(root).into_iter().map(|(id, _)| id).collect();
+        self.strongly_connected_components()
+            .into_iter()
+            .flatten()
+            .filter(|id| reachable.contains(id))
+            .collect()
+    }
+
+    /// Whether a call from `from` to `to` would close a cycle of `Calls` edges, i.e.
+    /// `to` is `from` or already calls it, directly or transitively
+    pub fn closes_call_cycle(&self, from: &NodeId, to: &NodeId) -> bool {
+        let (from, to) = (self.resolve(from), self.resolve(to));
+        from == to || self.bfs_from_filtered(to, &[EdgeType::Calls]).iter().any(|(id, _)| id == from)
+    }
+
+    /// Fewest-edges path from `from` to `to`, both ends included
+    pub fn shortest_path(&self, from: &NodeId, to: &NodeId) -> Option<Vec<NodeId>> {
+        let mut parent: HashMap<&NodeId, &NodeId> = HashMap::new();
+        let mut visited: HashSet<&NodeId> = HashSet::from([from]);
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(node) = queue.pop_front() {
+            if node == to {
+                let mut path = vec![node.clone()];
+                let mut current = node;
+                while let Some(&previous) = parent.get(current) {
+                    path.push(previous.clone());
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for edge in self.outgoing_edges(node) {
+                if visited.insert(&edge.to) {
+                    parent.insert(&edge.to, node);
+                    queue.push_back(&edge.to);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Every simple path from `from` to `to` with at most `max_len` edges
+    pub fn all_paths(&self, from: &NodeId, to: &NodeId, max_len: usize) -> Vec<Vec<NodeId>> {
+        let mut paths = Vec::new();
+        let mut path = vec![from];
+        self.collect_paths(to, max_len, &mut path, &mut paths);
+        paths
+    }
+
+    fn collect_paths<'a>(&'a self, to: &NodeId, max_len: usize, path: &mut Vec<&'a NodeId>, paths: &mut Vec<Vec<NodeId>>) {
+        let node = path[path.len() - 1];
+        if node == to {
+            paths.push(path.iter().map(|id| (*id).clone()).collect());
+            return;
+        }
+        if path.len() > max_len {
+            return;
+        }
+        for edge in self.outgoing_edges(node) {
+            if !path.contains(&&edge.to) {
+                path.push(&edge.to);
+                self.collect_paths(to, max_len, path, paths);
+                path.pop();
+            }
+        }
+    }
+
+    /// PageRank over dependency edges: code that many (important) nodes depend on
+    /// scores high. Scores are scaled so the top node has 1.0.
+    pub fn pagerank(&self) -> HashMap<NodeId, f64> {
+        const DAMPING: f64 = 0.85;
+        const ITERATIONS: usize = 20;
+
+        let mut ids: Vec<&NodeId> = self.nodes.keys().collect();
+        ids.sort();
+        let n = ids.len() as f64;
+        let mut rank: HashMap<&NodeId, f64> = ids.iter().map(|id| (*id, 1.0 / n)).collect();
+
+        for _ in 0..ITERATIONS {
+            // Rank of nodes without dependencies is spread evenly
+            let dangling: f64 = ids
+                .iter()
+                .filter(|id| self.successors(id).is_empty())
+                .map(|id| rank[*id])
+                .sum();
+            let mut next: HashMap<&NodeId, f64> =
+                ids.iter().map(|id| (*id, (1.0 - DAMPING) / n + DAMPING * dangling / n)).collect();
+
+            for id in &ids {
+                let successors = self.successors(id);
+                let share = rank[*id] / successors.len().max(1) as f64;
+                for successor in successors {
+                    if let Some(score) = next.get_mut(successor) {
+                        *score += DAMPING * share;
+                    }
+                }
+            }
+            rank = next;
+        }
+
+        let max = rank.values().cloned().fold(0.0, f64::max);
+        rank.into_iter()
+            .map(|(id, score)| (id.clone(), if max > 0.0 { score / max } else { 0.0 }))
+            .collect()
+    }
+
+    /// Store `pagerank` scores on each node's `importance`
+    pub fn score_importance(&mut self) {
+        for (id, score) in self.pagerank() {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                node.importance = score;
+            }
+        }
+    }
+
+    /// Collapse each cycle (e.g. mutually recursive functions) into one "cycle" node
+    /// holding the members' code. Acyclic nodes are copied unchanged; edges are
+    /// remapped onto representatives, with self-loops and duplicates dropped.
+    pub fn condense(&self) -> Condensation {
+        let mut condensation = Condensation {
+            graph: DependencyGraph::new(),
+            component_of: HashMap::new(),
+            members: HashMap::new(),
+        };
+
+        for component in self.strongly_connected_components() {
+            let representative = component[0].clone();
+            let node = if component.len() == 1 {
+                self.nodes[&representative].clone()
+            } else {
+                let code = component
+                    .iter()
+                    .map(|id| self.nodes[id].text().into_owned())
+                    .collect::<Vec<_>>()
+                    .join("\n\n");
+                CodeNode::new(representative.clone(), code, "cycle")
+            };
+            condensation.graph.add_node(node);
+
+            for member in &component {
+                condensation.component_of.insert(member.clone(), representative.clone());
+            }
+            condensation.members.insert(representative, component);
+        }
+
+        for edge in &self.edges {
+            let (Some(from), Some(to)) = (condensation.component_of.get(&edge.from), condensation.component_of.get(&edge.to)) else {
+                continue;
+            };
+            if from == to || condensation.graph.contains_edge(from, to, &edge.edge_type) {
+                continue;
+            }
+            let mut condensed = Edge::new(from.clone(), to.clone(), edge.edge_type.clone());
+            condensed.metadata = edge.metadata.clone();
+            condensation.graph.add_edge(condensed);
+        }
+
+        condensation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::test_id;
+
+    #[test]
+    fn test_closes_call_cycle() {
+        // 1 -> 2 -> 3, 3 -> 1 would close the loop
+        let mut graph = DependencyGraph::new();
+        for line in 1..=3 {
+            graph.add_node(CodeNode::new(test_id(line), format!("fn f{}() {{}}", line), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+
+        assert!(graph.closes_call_cycle(&test_id(3), &test_id(1)));
+        assert!(graph.closes_call_cycle(&test_id(2), &test_id(2)));
+        assert!(!graph.closes_call_cycle(&test_id(1), &test_id(3)));
+    }
+
+    #[test]
+    fn test_condense_mutual_recursion() {
+        // 1 -> 2 <-> 3 -> 4
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), format!("fn f{}() {{}}", line), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(4), EdgeType::Calls));
+
+        let components = graph.strongly_connected_components();
+        assert_eq!(components, vec![vec![test_id(4)], vec![test_id(2), test_id(3)], vec![test_id(1)]]);
+
+        let condensation = graph.condense();
+        assert_eq!(condensation.graph.nodes.len(), 3);
+        assert_eq!(condensation.graph.edges.len(), 2);
+        assert_eq!(condensation.component_of[&test_id(3)], test_id(2));
+        assert_eq!(condensation.graph.nodes[&test_id(2)].node_type, "cycle");
+        let depths: Vec<usize> = condensation.graph.bfs_from(&test_id(1)).into_iter().map(|(_, d)| d).collect();
+        assert_eq!(depths, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_topo_order_puts_dependencies_first() {
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(2), test_id(1), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(1), EdgeType::Calls));
+
+        assert_eq!(graph.topo_order(&test_id(2)), vec![test_id(1), test_id(3), test_id(2)]);
+    }
+
+    #[test]
+    fn test_topo_order_ignores_edge_insertion_order() {
+        let orders = [[2, 3], [3, 2]].map(|callees| {
+            let mut graph = DependencyGraph::new();
+            for line in 1..=3 {
+                graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+            }
+            for callee in callees {
+                graph.add_edge(Edge::new(test_id(1), test_id(callee), EdgeType::Calls));
+            }
+            graph.topo_order(&test_id(1))
+        });
+        assert_eq!(orders[0], vec![test_id(2), test_id(3), test_id(1)]);
+        assert_eq!(orders[0], orders[1]);
+    }
+
+    #[test]
+    fn test_pagerank_favors_shared_dependencies() {
+        // 1, 2 and 3 all call 4; 1 also calls 2
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        for from in 1..=3 {
+            graph.add_edge(Edge::new(test_id(from), test_id(4), EdgeType::Calls));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+
+        graph.score_importance();
+        assert_eq!(graph.nodes[&test_id(4)].importance, 1.0);
+        assert!(graph.nodes[&test_id(2)].importance > graph.nodes[&test_id(3)].importance);
+    }
+
+    #[test]
+    fn test_dfs_and_visitor() {
+        // 1 -> 2 -> 3, 1 -> 4, 3 -> 1
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(1), test_id(4), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(1), EdgeType::Calls));
+
+        let order: Vec<(u32, usize)> = graph.dfs_from(&test_id(1)).into_iter().map(|(n, d)| (n.line, d)).collect();
+        assert_eq!(order, vec![(1, 0), (2, 1), (3, 2), (4, 1)]);
+
+        struct LeaveOrder(Vec<u32>, usize);
+        impl GraphVisitor for LeaveOrder {
+            fn leave_node(&mut self, node: &CodeNode, _depth: usize) {
+                self.0.push(node.id.line);
+            }
+            fn visit_edge(&mut self, _edge: &Edge) {
+                self.1 += 1;
+            }
+        }
+        let mut postorder = LeaveOrder(Vec::new(), 0);
+        graph.walk(&test_id(1), &mut postorder);
+        assert_eq!(postorder.0, vec![3, 2, 4, 1]);
+        assert_eq!(postorder.1, 4);
+    }
+
+    #[test]
+    fn test_dominators() {
+        // 1 -> 2 -> 3 -> 5, 2 -> 4 -> 5: every path to 5 goes through 2, not 3 or 4
+        let mut graph = DependencyGraph::new();
+        for line in 1..=6 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(4), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(5), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(4), test_id(5), EdgeType::Calls));
+
+        let tree = graph.dominators(&test_id(1));
+        assert_eq!(tree.immediate_dominator(&test_id(5)), Some(&test_id(2)));
+        assert_eq!(tree.dominators_of(&test_id(5)), vec![test_id(2), test_id(1)]);
+        assert!(tree.dominates(&test_id(2), &test_id(5)));
+        assert!(!tree.dominates(&test_id(3), &test_id(5)));
+        assert_eq!(tree.immediate_dominator(&test_id(1)), None);
+        assert!(!tree.dominates(&test_id(1), &test_id(6)));
+    }
+
+    #[test]
+    fn test_paths() {
+        // 1 -> 2 -> 4, 1 -> 3 -> 2
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(4), EdgeType::Calls));
+
+        assert_eq!(graph.shortest_path(&test_id(1), &test_id(4)), Some(vec![test_id(1), test_id(2), test_id(4)]));
+        assert_eq!(graph.shortest_path(&test_id(4), &test_id(1)), None);
+        assert_eq!(graph.all_paths(&test_id(1), &test_id(4), 3).len(), 2);
+        assert_eq!(graph.all_paths(&test_id(1), &test_id(4), 2), vec![vec![test_id(1), test_id(2), test_id(4)]]);
+    }
+}
@@ -0,0 +1,10 @@
+; Containers that Extractor::extract_block returns whole. Each pattern captures the
+; container as @block; the innermost match around a position wins.
+
+(function_item) @block
+(struct_item) @block
+(enum_item) @block
+(impl_item) @block
+(trait_item) @block
+(mod_item) @block
+(macro_definition) @block
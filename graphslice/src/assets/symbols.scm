@@ -0,0 +1,18 @@
+; Definitions that Extractor::get_defined_symbols reports. Each pattern captures the
+; definition as @symbol. Only the top level, inline modules, and impl and trait bodies
+; are searched, so items nested in function bodies never count.
+
+(function_item) @symbol
+(struct_item) @symbol
+(enum_item) @symbol
+(trait_item) @symbol
+(mod_item) @symbol
+(macro_definition) @symbol
+
+; Associated items, reported as `Owner::name`
+(impl_item
+  body: (declaration_list
+    [(function_signature_item) (const_item) (type_item) (associated_type)] @symbol))
+(trait_item
+  body: (declaration_list
+    [(function_signature_item) (const_item) (type_item) (associated_type)] @symbol))
@@ -0,0 +1,140 @@
+use crate::graph::{CodeNode, DependencyGraph, Edge, NodeId};
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Identifies files written by `SliceBundle::save`
+const BUNDLE_FORMAT: &str = "graphslice-bundle";
+
+/// Bumped on incompatible changes to the bundle layout
+pub const BUNDLE_FORMAT_VERSION: u32 = 2;
+
+/// A slice packaged to be shared or replayed elsewhere: the graph, the context rendered
+/// from it, the sources it was cut from and the settings that shaped it.
+/// Written by `Slicer::export_bundle`.
+pub struct SliceBundle {
+    pub workspace_root: PathBuf,
+    /// Slicer configuration, reference options and extraction rule sources, as JSON.
+    /// Version 1 bundles hold a string here instead.
+    pub settings: serde_json::Value,
+    /// Context rendered from the graph, as handed to its consumer
+    pub context: String,
+    /// Contents of every file with a node in the graph, unsaved overlays included
+    pub files: BTreeMap<PathBuf, String>,
+    /// Nodes carry their code and provenance, so the bundle reads without the sources
+    pub graph: DependencyGraph,
+}
+
+/// On-disk layout, with nodes as a list like `DependencyGraph::save`
+#[derive(Serialize, Deserialize)]
+struct BundleFile {
+    format: String,
+    version: u32,
+    workspace_root: PathBuf,
+    settings: serde_json::Value,
+    context: String,
+    files: BTreeMap<PathBuf, String>,
+    nodes: Vec<CodeNode>,
+    edges: Vec<Edge>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    truncated: Option<Vec<NodeId>>,
+}
+
+impl SliceBundle {
+    /// Write the bundle to `path` as a single JSON file. Lazy nodes are written with
+    /// their code, and everything is sorted so equal bundles produce identical files.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let nodes = self
+            .graph
+            .sorted_nodes()
+            .into_iter()
+            .map(|node| {
+                let mut node = node.clone();
+                node.load_code();
+                node
+            })
+            .collect();
+        let file = BundleFile {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_FORMAT_VERSION,
+            workspace_root: self.workspace_root.clone(),
+            settings: self.settings.clone(),
+            context: self.context.clone(),
+            files: self.files.clone(),
+            nodes,
+            edges: self.graph.sorted_edges(),
+            truncated: self.graph.truncated.clone(),
+        };
+
+        let json = serde_json::to_string_pretty(&file)?;
+        fs::write(path, json).with_context(|| format!("Failed to write bundle to {}", path.display()))
+    }
+
+    /// Read a bundle written by `save`, rejecting foreign or newer formats
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path).with_context(|| format!("Failed to read bundle from {}", path.display()))?;
+
+        let header: serde_json::Value = serde_json::from_str(&json)?;
+        if header.get("format").and_then(|f| f.as_str()) != Some(BUNDLE_FORMAT) {
+            return Err(anyhow!("{} is not a graphslice bundle", path.display()));
+        }
+        let version = header.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        if version == 0 || version > BUNDLE_FORMAT_VERSION as u64 {
+            return Err(anyhow!(
+                "Unsupported bundle format version {} in {} (supported: 1..={})",
+                version,
+                path.display(),
+                BUNDLE_FORMAT_VERSION
+            ));
+        }
+
+        let file: BundleFile = serde_json::from_value(header)?;
+        let mut graph = DependencyGraph::from_parts(file.nodes, file.edges);
+        graph.truncated = file.truncated;
+        Ok(Self {
+            workspace_root: file.workspace_root,
+            settings: file.settings,
+            context: file.context,
+            files: file.files,
+            graph,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{EdgeType, Provenance, test_id};
+
+    #[test]
+    fn test_bundle_round_trip() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), "fn a() { b() }".to_string(), "target").with_provenance(Provenance::root("slice target")));
+        graph.add_node(CodeNode::new(test_id(5), "fn b() {}".to_string(), "call"));
+        graph.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+        graph.truncated = Some(vec![test_id(5)]);
+        let bundle = SliceBundle {
+            workspace_root: PathBuf::from("/src"),
+            settings: serde_json::json!({ "config": { "max_depth": 3 } }),
+            context: "fn a() { b() }".to_string(),
+            files: BTreeMap::from([(PathBuf::from("/src/lib.rs"), "fn a() { b() }\nfn b() {}\n".to_string())]),
+            graph,
+        };
+
+        let path = std::env::temp_dir().join(format!("graphslice_bundle_{}.json", std::process::id()));
+        bundle.save(&path).unwrap();
+        let loaded = SliceBundle::load(&path).unwrap();
+        assert!(DependencyGraph::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.files, bundle.files);
+        assert_eq!(loaded.context, bundle.context);
+        assert_eq!(loaded.settings["config"]["max_depth"], 3);
+        assert_eq!(loaded.graph.nodes.len(), 2);
+        assert!(loaded.graph.contains_edge(&test_id(1), &test_id(5), &EdgeType::Calls));
+        assert_eq!(loaded.graph.nodes[&test_id(1)].provenance, Some(Provenance::root("slice target")));
+        assert_eq!(loaded.graph.truncated, Some(vec![test_id(5)]));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::graph::{DependencyGraph, NodeId, content_hash};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What a cached graph was built from
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    target: NodeId,
+    config: String,
+    /// Every file with a node in the graph -> sha256 of its contents at build time
+    files: BTreeMap<PathBuf, String>,
+}
+
+/// On-disk store of slices keyed by (target, config). An entry stays valid while
+/// every file contributing a node hashes the same as when it was stored.
+/// Files that would newly join the slice (e.g. a fresh caller) are not tracked.
+pub struct GraphCache {
+    dir: PathBuf,
+}
+
+impl GraphCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create cache directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    /// The cached graph for `target` under `config`, if none of its files changed.
+    /// Stale entries are removed.
+    pub fn get(&self, target: &NodeId, config: &str) -> Option<DependencyGraph> {
+        let (meta_path, graph_path) = self.paths(target, config);
+        let entry: CacheEntry = serde_json::from_str(&fs::read_to_string(&meta_path).ok()?).ok()?;
+
+        if &entry.target != target || entry.config != config || !is_fresh(&entry) {
+            self.remove(&meta_path, &graph_path);
+            return None;
+        }
+
+        DependencyGraph::load(&graph_path).ok()
+    }
+
+    /// Store `graph` along with the current hashes of the files it covers
+    pub fn put(&self, target: &NodeId, config: &str, graph: &DependencyGraph) -> Result<()> {
+        let mut files = BTreeMap::new();
+        for id in graph.nodes.keys() {
+            if !files.contains_key(&id.file) {
+                let text = fs::read_to_string(&id.file)
+                    .with_context(|| format!("Failed to hash {}", id.file.display()))?;
+                files.insert(id.file.clone(), content_hash(&text));
+            }
+        }
+
+        let entry = CacheEntry {
+            target: target.clone(),
+            config: config.to_string(),
+            files,
+        };
+        let (meta_path, graph_path) = self.paths(target, config);
+        graph.save(&graph_path)?;
+        fs::write(&meta_path, serde_json::to_string_pretty(&entry)?)
+            .with_context(|| format!("Failed to write cache entry {}", meta_path.display()))?;
+        Ok(())
+    }
+
+    /// Drop every entry whose graph includes `file`. Returns how many were removed.
+    pub fn invalidate_file(&self, file: &Path) -> Result<usize> {
+        self.remove_where(|entry| entry.files.contains_key(file))
+    }
+
+    /// Drop every entry whose files changed since it was stored
+    pub fn prune(&self) -> Result<usize> {
+        self.remove_where(|entry| !is_fresh(entry))
+    }
+
+    pub fn clear(&self) -> Result<usize> {
+        self.remove_where(|_| true)
+    }
+
+    fn remove_where(&self, stale: impl Fn(&CacheEntry) -> bool) -> Result<usize> {
+        let mut removed = 0;
+        for dir_entry in fs::read_dir(&self.dir)? {
+            let meta_path = dir_entry?.path();
+            let Some(name) = meta_path.file_name().and_then(|n| n.to_str()) else { continue };
+            let Some(key) = name.strip_suffix(".meta.json") else { continue };
+
+            let entry: Option<CacheEntry> = fs::read_to_string(&meta_path)
+                .ok()
+                .and_then(|json| serde_json::from_str(&json).ok());
+            // Unreadable entries are useless; drop them too
+            if entry.as_ref().is_none_or(&stale) {
+                self.remove(&meta_path, &self.dir.join(format!("{}.graph.json", key)));
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn paths(&self, target: &NodeId, config: &str) -> (PathBuf, PathBuf) {
+        let key = content_hash(&format!("{}:{}:{}\n{}", target.file.display(), target.line, target.column, config));
+        (
+            self.dir.join(format!("{}.meta.json", key)),
+            self.dir.join(format!("{}.graph.json", key)),
+        )
+    }
+
+    fn remove(&self, meta_path: &Path, graph_path: &Path) {
+        fs::remove_file(meta_path).ok();
+        fs::remove_file(graph_path).ok();
+    }
+}
+
+fn is_fresh(entry: &CacheEntry) -> bool {
+    entry
+        .files
+        .iter()
+        .all(|(file, hash)| fs::read_to_string(file).is_ok_and(|text| &content_hash(&text) == hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::CodeNode;
+
+    #[test]
+    fn test_cache_invalidates_on_change() {
+        let dir = std::env::temp_dir().join(format!("graphslice_cache_{}", std::process::id()));
+        let source = dir.join("lib.rs");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&source, "fn a() {}\n").unwrap();
+
+        let target = NodeId { file: source.clone(), line: 0, column: 3 };
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(target.clone(), "fn a() {}".to_string(), "target"));
+
+        let cache = GraphCache::new(dir.join("cache")).unwrap();
+        cache.put(&target, "default", &graph).unwrap();
+        assert!(cache.get(&target, "default").is_some());
+        assert!(cache.get(&target, "other").is_none());
+
+        fs::write(&source, "fn a() { b() }\n").unwrap();
+        assert!(cache.get(&target, "default").is_none());
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
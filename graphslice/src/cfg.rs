@@ -0,0 +1,129 @@
+use std::collections::HashSet;
+
+/// The cfg options a build is compiled with, e.g. `unix`, `feature = "serde"` or
+/// `target_os = "linux"`, for deciding which `#[cfg(...)]` code is compiled in
+#[derive(Debug, Clone, Default)]
+pub struct CfgSet {
+    /// (name, value) pairs; name-only options have no value
+    options: HashSet<(String, Option<String>)>,
+}
+
+impl CfgSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable a name-only option like `unix` or `test`
+    pub fn with_flag(mut self, name: &str) -> Self {
+        self.options.insert((name.to_string(), None));
+        self
+    }
+
+    /// Enable a key-value option like `feature = "serde"` or `target_os = "linux"`
+    pub fn with_value(mut self, key: &str, value: &str) -> Self {
+        self.options.insert((key.to_string(), Some(value.to_string())));
+        self
+    }
+
+    /// Enable an option written as for `rustc --cfg`: `unix` or `feature="serde"`
+    pub fn enable(&mut self, spec: &str) {
+        let option = match spec.split_once('=') {
+            Some((key, value)) => (key.trim().to_string(), Some(value.trim().trim_matches('"').to_string())),
+            None => (spec.trim().to_string(), None),
+        };
+        self.options.insert(option);
+    }
+
+    /// Whether a cfg predicate such as `all(unix, not(feature = "serde"))` holds.
+    /// Predicates that fail to parse count as true, so unknown code is kept.
+    pub fn eval(&self, predicate: &str) -> bool {
+        match self.eval_at(predicate) {
+            Some((value, rest)) if rest.trim().is_empty() => value,
+            _ => true,
+        }
+    }
+
+    /// Evaluate the predicate at the start of `input`, returning what follows it
+    fn eval_at<'a>(&self, input: &'a str) -> Option<(bool, &'a str)> {
+        let input = input.trim_start();
+        let end = input.find(|c: char| !(c.is_alphanumeric() || c == '_')).unwrap_or(input.len());
+        let (name, rest) = input.split_at(end);
+        if name.is_empty() {
+            return None;
+        }
+        let rest = rest.trim_start();
+
+        if let Some(mut rest) = rest.strip_prefix('(') {
+            let mut values = Vec::new();
+            loop {
+                rest = rest.trim_start();
+                if let Some(after) = rest.strip_prefix(')') {
+                    rest = after;
+                    break;
+                }
+                let (value, after) = self.eval_at(rest)?;
+                values.push(value);
+                rest = after.trim_start();
+                rest = rest.strip_prefix(',').unwrap_or(rest);
+            }
+            let value = match name {
+                "all" => values.iter().all(|v| *v),
+                "any" => values.iter().any(|v| *v),
+                "not" if values.len() == 1 => !values[0],
+                _ => return None,
+            };
+            return Some((value, rest));
+        }
+
+        if let Some(rest) = rest.strip_prefix('=') {
+            let quoted = rest.trim_start().strip_prefix('"')?;
+            let close = quoted.find('"')?;
+            let option = (name.to_string(), Some(quoted[..close].to_string()));
+            return Some((self.options.contains(&option), &quoted[close + 1..]));
+        }
+
+        Some((self.options.contains(&(name.to_string(), None)), rest))
+    }
+}
+
+/// The predicate of a `#[cfg(...)]` or `#![cfg(...)]` attribute
+pub fn cfg_predicate(attribute: &str) -> Option<&str> {
+    let inner = attribute
+        .trim()
+        .trim_start_matches('#')
+        .trim_start_matches('!')
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .trim();
+    let predicate = inner.strip_prefix("cfg")?.trim_start().strip_prefix('(')?.strip_suffix(')')?;
+    Some(predicate.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_predicates() {
+        let mut cfg = CfgSet::new().with_flag("unix").with_value("target_os", "linux");
+        cfg.enable("feature=\"serde\"");
+
+        assert!(cfg.eval("unix"));
+        assert!(!cfg.eval("windows"));
+        assert!(cfg.eval("feature = \"serde\""));
+        assert!(!cfg.eval("feature = \"tokio\""));
+        assert!(cfg.eval("all(unix, target_os = \"linux\", not(test),)"));
+        assert!(!cfg.eval("any(windows, all(unix, feature = \"tokio\"))"));
+        assert!(cfg.eval("all()") && !cfg.eval("any()"));
+        // Unparseable predicates keep the code
+        assert!(cfg.eval("version(\"1.80\")"));
+    }
+
+    #[test]
+    fn test_cfg_predicate() {
+        assert_eq!(cfg_predicate("#[cfg(feature = \"serde\")]"), Some("feature = \"serde\""));
+        assert_eq!(cfg_predicate("#![cfg(unix)]"), Some("unix"));
+        assert_eq!(cfg_predicate("#[cfg_attr(test, derive(Debug))]"), None);
+        assert_eq!(cfg_predicate("#[derive(Debug)]"), None);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::export::format_tags;
+use crate::extractor::Extractor;
 use crate::graph::{DependencyGraph, NodeId};
 use std::collections::HashMap;
 
@@ -10,6 +12,10 @@ pub enum InclusionLevel {
 
 pub struct HierarchicalContext {
     pub sections: HashMap<NodeId, (String, InclusionLevel)>,
+    /// Render order: dependencies before their users
+    order: Vec<NodeId>,
+    /// Formatted annotations of tagged nodes
+    tags: HashMap<NodeId, String>,
 }
 
 impl Default for HierarchicalContext {
@@ -22,6 +28,8 @@ impl HierarchicalContext {
     pub fn new() -> Self {
         Self {
             sections: HashMap::new(),
+            order: Vec::new(),
+            tags: HashMap::new(),
         }
     }
 
@@ -33,35 +41,43 @@ impl HierarchicalContext {
     ) -> Self {
         let mut context = Self::new();
         let mut current_tokens = 0;
+        let mut extractor = Extractor::new().ok();
 
-        for (node_id, depth) in graph.bfs_from(root) {
+        for (node_id, depth) in cycle_aware_depths(graph, root) {
             if current_tokens >= max_tokens {
                 break;
             }
 
             let node = graph.nodes.get(&node_id).unwrap();
+            let code = node.text();
+            let documented = with_docs(node.docs.as_deref(), &code, code.to_string());
+            // Full source carries the imports it needs to resolve
+            let full_source = match node.imports.is_empty() {
+                true => documented,
+                false => format!("{}\n\n{}", node.imports.join("\n"), documented),
+            };
 
             let (content, level) = match depth {
                 0 => {
                     // Target: always full source
-                    (node.code.clone(), InclusionLevel::FullSource)
+                    (full_source, InclusionLevel::FullSource)
                 }
                 1 => {
                     // Direct dependencies: full source if budget allows
-                    let tokens = estimate_tokens(&node.code);
+                    let tokens = estimate_tokens(&full_source);
                     if current_tokens + tokens <= max_tokens {
                         current_tokens += tokens;
-                        (node.code.clone(), InclusionLevel::FullSource)
+                        (full_source, InclusionLevel::FullSource)
                     } else {
                         // Compress to interface
-                        let summary = extract_interface(&node.code);
+                        let summary = with_docs(node.docs.as_deref(), &code, extract_interface(extractor.as_mut(), &code));
                         current_tokens += estimate_tokens(&summary);
                         (summary, InclusionLevel::InterfaceSummary)
                     }
                 }
                 2.. => {
                     // Transitive: interface summary only
-                    let summary = extract_interface(&node.code);
+                    let summary = with_docs(node.docs.as_deref(), &code, extract_interface(extractor.as_mut(), &code));
                     let tokens = estimate_tokens(&summary);
                     
                     if current_tokens + tokens <= max_tokens {
@@ -79,9 +95,18 @@ impl HierarchicalContext {
                 }
             };
 
+            if !node.tags.is_empty() {
+                context.tags.insert(node_id.clone(), format_tags(&node.tags));
+            }
             context.sections.insert(node_id, (content, level));
         }
 
+        context.order = graph
+            .topo_order(root)
+            .into_iter()
+            .filter(|id| context.sections.contains_key(id))
+            .collect();
+
         context
     }
 
@@ -89,16 +114,19 @@ impl HierarchicalContext {
     pub fn render(&self) -> String {
         let mut output = String::new();
 
-        for (node_id, (content, level)) in &self.sections {
+        for node_id in &self.order {
+            let Some((content, level)) = self.sections.get(node_id) else { continue };
             let marker = match level {
                 InclusionLevel::FullSource => "FULL",
                 InclusionLevel::InterfaceSummary => "INTERFACE",
                 InclusionLevel::Reference => "REF",
             };
 
+            let tags = self.tags.get(node_id).map(|tags| format!(" {{{}}}", tags)).unwrap_or_default();
+
             output.push_str(&format!(
-                "\n// [{}] {}:{}:{}\n",
-                marker, node_id.file.display(), node_id.line, node_id.column
+                "\n// [{}] {}:{}:{}{}\n",
+                marker, node_id.file.display(), node_id.line, node_id.column, tags
             ));
             output.push_str(content);
             output.push('\n');
@@ -108,8 +136,54 @@ impl HierarchicalContext {
     }
 }
 
-/// Extract function signature from implementation
-fn extract_interface(code: &str) -> String {
+/// BFS depths where every member of a cycle shares the depth of the cycle as a whole,
+/// so mutually recursive functions are compressed together rather than split across levels
+fn cycle_aware_depths(graph: &DependencyGraph, root: &NodeId) -> Vec<(NodeId, usize)> {
+    let condensation = graph.condense();
+    let Some(root_component) = condensation.component_of.get(root) else {
+        return graph.bfs_from(root);
+    };
+
+    let mut order = Vec::new();
+    for (representative, depth) in condensation.graph.bfs_from(root_component) {
+        for member in &condensation.members[&representative] {
+            // Cycle partners of the target are still dependencies, not the target
+            let depth = if member != root { depth.max(1) } else { depth };
+            order.push((member.clone(), depth));
+        }
+    }
+    // Keep the target first; within a level, central code claims the budget first,
+    // with ties going by position so the same graph always compresses the same way
+    let importance = graph.pagerank();
+    let score = |id: &NodeId| importance.get(id).copied().unwrap_or(0.0);
+    order.sort_by(|(a, a_depth), (b, b_depth)| {
+        a_depth.cmp(b_depth).then(score(b).total_cmp(&score(a))).then_with(|| a.cmp(b))
+    });
+    order
+}
+
+/// Put the item's doc comment above `content`, unless its code already carries it
+fn with_docs(docs: Option<&str>, code: &str, content: String) -> String {
+    let Some(docs) = docs.filter(|docs| !docs.is_empty()) else {
+        return content;
+    };
+    let first = docs.lines().next().unwrap_or_default();
+    if code.contains(first) {
+        return content;
+    }
+    let comment: Vec<String> = docs
+        .lines()
+        .map(|line| if line.is_empty() { "///".to_string() } else { format!("/// {}", line) })
+        .collect();
+    format!("{}\n{}", comment.join("\n"), content)
+}
+
+/// Interface of `code` from its syntax tree, or by the line filter below for code the
+/// extractor finds no items in (e.g. a single statement)
+fn extract_interface(extractor: Option<&mut Extractor>, code: &str) -> String {
+    if let Some(interface) = extractor.and_then(|extractor| extractor.extract_interface(code)) {
+        return interface;
+    }
     // Simple heuristic: keep lines with fn/struct/impl/pub
     let lines: Vec<&str> = code
         .lines()
@@ -133,6 +207,6 @@ fn extract_interface(code: &str) -> String {
 }
 
 /// Estimate tokens (rough: 1 token ≈ 4 chars)
-fn estimate_tokens(text: &str) -> usize {
+pub(crate) fn estimate_tokens(text: &str) -> usize {
     text.len() / 4
 }
\ No newline at end of file
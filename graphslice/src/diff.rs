@@ -0,0 +1,83 @@
+use crate::graph::{DependencyGraph, Edge, EdgeType, NodeId};
+use std::collections::HashSet;
+
+/// Difference between two slices, as returned by `DependencyGraph::diff`
+#[derive(Debug, Default, Clone)]
+pub struct GraphDelta {
+    pub added_nodes: Vec<NodeId>,
+    pub removed_nodes: Vec<NodeId>,
+    /// Nodes present in both graphs whose code differs
+    pub changed_nodes: Vec<NodeId>,
+    pub added_edges: Vec<Edge>,
+    pub removed_edges: Vec<Edge>,
+}
+
+impl GraphDelta {
+    pub fn is_empty(&self) -> bool {
+        self.added_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.changed_nodes.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+    }
+}
+
+impl DependencyGraph {
+    /// Compare this graph (old) against `other` (new).
+    /// Nodes are matched by `NodeId` and compared by content hash; edges by (from, to, type).
+    pub fn diff(&self, other: &DependencyGraph) -> GraphDelta {
+        let mut delta = GraphDelta::default();
+
+        for node in other.sorted_nodes() {
+            let id = &node.id;
+            match self.nodes.get(id) {
+                None => delta.added_nodes.push(id.clone()),
+                Some(old) if old.content_hash != node.content_hash => delta.changed_nodes.push(id.clone()),
+                Some(_) => {}
+            }
+        }
+        for node in self.sorted_nodes() {
+            if !other.nodes.contains_key(&node.id) {
+                delta.removed_nodes.push(node.id.clone());
+            }
+        }
+
+        let old_edges: HashSet<(&NodeId, &NodeId, &EdgeType)> = self.edges.iter().map(edge_key).collect();
+        let new_edges: HashSet<(&NodeId, &NodeId, &EdgeType)> = other.edges.iter().map(edge_key).collect();
+        delta.added_edges = other.sorted_edges().into_iter().filter(|e| !old_edges.contains(&edge_key(e))).collect();
+        delta.removed_edges = self.sorted_edges().into_iter().filter(|e| !new_edges.contains(&edge_key(e))).collect();
+
+        delta
+    }
+}
+
+fn edge_key(edge: &Edge) -> (&NodeId, &NodeId, &EdgeType) {
+    (&edge.from, &edge.to, &edge.edge_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CodeNode, test_id};
+
+    #[test]
+    fn test_diff() {
+        let mut old = DependencyGraph::new();
+        old.add_node(CodeNode::new(test_id(1), "fn a() { b() }".to_string(), "target"));
+        old.add_node(CodeNode::new(test_id(5), "fn b() {}".to_string(), "call"));
+        old.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+
+        let mut new = DependencyGraph::new();
+        new.add_node(CodeNode::new(test_id(1), "fn a() { c() }".to_string(), "target"));
+        new.add_node(CodeNode::new(test_id(9), "fn c() {}".to_string(), "call"));
+        new.add_edge(Edge::new(test_id(1), test_id(9), EdgeType::Calls));
+
+        let delta = old.diff(&new);
+        assert_eq!(delta.added_nodes, vec![test_id(9)]);
+        assert_eq!(delta.removed_nodes, vec![test_id(5)]);
+        assert_eq!(delta.changed_nodes, vec![test_id(1)]);
+        assert_eq!(delta.added_edges.len(), 1);
+        assert_eq!(delta.removed_edges.len(), 1);
+        assert!(old.diff(&old).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::fuzzy_slicer::FuzzySlicer;
+use crate::graph::{DependencyGraph, NodeId};
+use crate::slicer::Slicer;
+use anyhow::Result;
+use std::future::Future;
+use std::pin::Pin;
+
+/// Future returned by `SliceEngine::slice`
+pub type SliceFuture<'a> = Pin<Box<dyn Future<Output = Result<DependencyGraph>> + 'a>>;
+
+/// Something that builds a slice around a target position: the LSP-backed `Slicer`,
+/// the `FuzzySlicer`, or another engine (e.g. one driving rustc). Hosts hold a
+/// `Box<dyn SliceEngine>` to swap or combine engines.
+pub trait SliceEngine: Send {
+    /// Shown in status messages, e.g. "lsp" or "fuzzy"
+    fn name(&self) -> &str;
+
+    /// Slice around `target`, which should come out as a "target" node
+    fn slice<'a>(&'a mut self, target: &'a NodeId) -> SliceFuture<'a>;
+}
+
+impl SliceEngine for Slicer {
+    fn name(&self) -> &str {
+        "lsp"
+    }
+
+    fn slice<'a>(&'a mut self, target: &'a NodeId) -> SliceFuture<'a> {
+        Box::pin(self.build_graph(target.file.clone(), target.line, target.column))
+    }
+}
+
+impl SliceEngine for FuzzySlicer {
+    fn name(&self) -> &str {
+        "fuzzy"
+    }
+
+    fn slice<'a>(&'a mut self, target: &'a NodeId) -> SliceFuture<'a> {
+        Box::pin(FuzzySlicer::slice(self, target.file.clone(), target.line, target.column))
+    }
+}
+
+/// Slices with `primary`, falling back to `secondary` when `primary` fails or finds
+/// nothing beyond the target
+pub struct Fallback {
+    pub primary: Box<dyn SliceEngine>,
+    pub secondary: Box<dyn SliceEngine>,
+}
+
+impl SliceEngine for Fallback {
+    fn name(&self) -> &str {
+        "fallback"
+    }
+
+    fn slice<'a>(&'a mut self, target: &'a NodeId) -> SliceFuture<'a> {
+        Box::pin(async move {
+            match self.primary.slice(target).await {
+                Ok(graph) if graph.nodes.len() > 1 => return Ok(graph),
+                Ok(_) => eprintln!("↪️  {} found nothing; trying {}", self.primary.name(), self.secondary.name()),
+                Err(e) => eprintln!("↪️  {} failed ({}); trying {}", self.primary.name(), e, self.secondary.name()),
+            }
+            self.secondary.slice(target).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CodeNode, test_id};
+    use anyhow::anyhow;
+
+    /// Returns the target plus `extra` nodes, or fails when `extra` is `None`
+    struct Fixed {
+        extra: Option<u32>,
+    }
+
+    impl SliceEngine for Fixed {
+        fn name(&self) -> &str {
+            "fixed"
+        }
+
+        fn slice<'a>(&'a mut self, target: &'a NodeId) -> SliceFuture<'a> {
+            Box::pin(async move {
+                let extra = self.extra.ok_or_else(|| anyhow!("no server"))?;
+                let mut graph = DependencyGraph::new();
+                graph.add_node(CodeNode::new(target.clone(), String::new(), "target"));
+                for line in 1..=extra {
+                    graph.add_node(CodeNode::new(test_id(target.line + line), String::new(), "call"));
+                }
+                Ok(graph)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fallback_uses_secondary_when_primary_fails_or_is_empty() {
+        for primary in [None, Some(0)] {
+            let mut engine = Fallback {
+                primary: Box::new(Fixed { extra: primary }),
+                secondary: Box::new(Fixed { extra: Some(2) }),
+            };
+            assert_eq!(engine.slice(&test_id(10)).await.unwrap().nodes.len(), 3);
+        }
+
+        let mut engine = Fallback {
+            primary: Box::new(Fixed { extra: Some(1) }),
+            secondary: Box::new(Fixed { extra: None }),
+        };
+        assert_eq!(engine.slice(&test_id(10)).await.unwrap().nodes.len(), 2);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::graph::{DependencyGraph, EdgeType, NodeId};
+use anyhow::{Context, Result};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+const HTML_TEMPLATE: &str = include_str!("assets/graph.html");
+
+impl DependencyGraph {
+    /// Render the graph in Graphviz DOT format.
+    /// Nodes are labelled `file:line` (1-based) plus their kind; edges are styled per `EdgeType`.
+    pub fn to_dot(&self) -> String {
+        let (ids, index) = ordered_ids(self);
+
+        let mut dot = String::from("digraph slice {\n");
+        dot.push_str("    rankdir=LR;\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+        for id in &ids {
+            let node = &self.nodes[*id];
+            let file_name = id
+                .file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| id.file.display().to_string());
+            let label = format!(
+                "{}:{}\\n({})",
+                escape_dot(&file_name),
+                id.line + 1,
+                escape_dot(&node.node_type)
+            );
+            let fill = match node.node_type.as_str() {
+                "target" => ", style=filled, fillcolor=\"#ffd966\"",
+                "test" => ", style=filled, fillcolor=\"#d9ead3\"",
+                _ => "",
+            };
+            let mut tooltip = id.file.display().to_string();
+            if !node.tags.is_empty() {
+                tooltip.push_str(&format!(" [{}]", format_tags(&node.tags)));
+            }
+            dot.push_str(&format!(
+                "    n{} [label=\"{}\", tooltip=\"{}\"{}];\n",
+                index[*id],
+                label,
+                escape_dot(&tooltip),
+                fill
+            ));
+        }
+
+        for edge in &self.sorted_edges() {
+            let (Some(from), Some(to)) = (index.get(&edge.from), index.get(&edge.to)) else {
+                continue;
+            };
+            let mut style = dot_edge_style(&edge.edge_type).to_string();
+            if edge.metadata.occurrences > 1 {
+                style.push_str(&format!(", taillabel=\"x{}\"", edge.metadata.occurrences));
+            }
+            if edge.metadata.conditional {
+                style.push_str(", arrowhead=empty");
+            }
+            if edge.metadata.inferred {
+                style.push_str(", label=\"inferred\", fontcolor=gray");
+            } else if edge.metadata.cycle {
+                style.push_str(", label=\"cycle\", fontcolor=gray");
+            }
+            dot.push_str(&format!("    n{} -> n{} [{}];\n", from, to, style));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Render the graph as GraphML (Cytoscape, networkx, yEd).
+    /// Node position, kind, module and code are exported as data keys; `line` is 1-based.
+    pub fn to_graphml(&self) -> String {
+        let (ids, index) = ordered_ids(self);
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+        xml.push_str("  <key id=\"file\" for=\"node\" attr.name=\"file\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"line\" for=\"node\" attr.name=\"line\" attr.type=\"int\"/>\n");
+        xml.push_str("  <key id=\"column\" for=\"node\" attr.name=\"column\" attr.type=\"int\"/>\n");
+        xml.push_str("  <key id=\"node_type\" for=\"node\" attr.name=\"node_type\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"module_path\" for=\"node\" attr.name=\"module_path\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"code\" for=\"node\" attr.name=\"code\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"tags\" for=\"node\" attr.name=\"tags\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"edge_type\" for=\"edge\" attr.name=\"edge_type\" attr.type=\"string\"/>\n");
+        xml.push_str("  <key id=\"occurrences\" for=\"edge\" attr.name=\"occurrences\" attr.type=\"int\"/>\n");
+        xml.push_str("  <key id=\"conditional\" for=\"edge\" attr.name=\"conditional\" attr.type=\"boolean\"/>\n");
+        xml.push_str("  <key id=\"inferred\" for=\"edge\" attr.name=\"inferred\" attr.type=\"boolean\"/>\n");
+        xml.push_str("  <key id=\"cycle\" for=\"edge\" attr.name=\"cycle\" attr.type=\"boolean\"/>\n");
+        xml.push_str("  <graph id=\"slice\" edgedefault=\"directed\">\n");
+
+        for id in &ids {
+            let node = &self.nodes[*id];
+            xml.push_str(&format!("    <node id=\"n{}\">\n", index[*id]));
+            xml.push_str(&format!("      <data key=\"file\">{}</data>\n", escape_xml(&id.file.display().to_string())));
+            xml.push_str(&format!("      <data key=\"line\">{}</data>\n", id.line + 1));
+            xml.push_str(&format!("      <data key=\"column\">{}</data>\n", id.column));
+            xml.push_str(&format!("      <data key=\"node_type\">{}</data>\n", escape_xml(&node.node_type)));
+            if let Some(module_path) = &node.module_path {
+                xml.push_str(&format!("      <data key=\"module_path\">{}</data>\n", escape_xml(module_path)));
+            }
+            xml.push_str(&format!("      <data key=\"code\">{}</data>\n", escape_xml(&node.text())));
+            if !node.tags.is_empty() {
+                xml.push_str(&format!("      <data key=\"tags\">{}</data>\n", escape_xml(&format_tags(&node.tags))));
+            }
+            xml.push_str("    </node>\n");
+        }
+
+        for (i, edge) in self.sorted_edges().iter().enumerate() {
+            let (Some(from), Some(to)) = (index.get(&edge.from), index.get(&edge.to)) else {
+                continue;
+            };
+            xml.push_str(&format!("    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">\n", i, from, to));
+            xml.push_str(&format!("      <data key=\"edge_type\">{}</data>\n", edge.edge_type.as_str()));
+            xml.push_str(&format!("      <data key=\"occurrences\">{}</data>\n", edge.metadata.occurrences));
+            xml.push_str(&format!("      <data key=\"conditional\">{}</data>\n", edge.metadata.conditional));
+            xml.push_str(&format!("      <data key=\"inferred\">{}</data>\n", edge.metadata.inferred));
+            xml.push_str(&format!("      <data key=\"cycle\">{}</data>\n", edge.metadata.cycle));
+            xml.push_str("    </edge>\n");
+        }
+
+        xml.push_str("  </graph>\n</graphml>\n");
+        xml
+    }
+
+    /// Render the graph as GEXF 1.3 (Gephi), with 1-based lines in labels and attributes
+    pub fn to_gexf(&self) -> String {
+        let (ids, index) = ordered_ids(self);
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str("<gexf xmlns=\"http://gexf.net/1.3\" version=\"1.3\">\n");
+        xml.push_str("  <graph defaultedgetype=\"directed\">\n");
+        xml.push_str("    <attributes class=\"node\">\n");
+        xml.push_str("      <attribute id=\"0\" title=\"file\" type=\"string\"/>\n");
+        xml.push_str("      <attribute id=\"1\" title=\"line\" type=\"integer\"/>\n");
+        xml.push_str("      <attribute id=\"2\" title=\"node_type\" type=\"string\"/>\n");
+        xml.push_str("      <attribute id=\"3\" title=\"module_path\" type=\"string\"/>\n");
+        xml.push_str("      <attribute id=\"4\" title=\"tags\" type=\"string\"/>\n");
+        xml.push_str("    </attributes>\n");
+        xml.push_str("    <nodes>\n");
+
+        for id in &ids {
+            let node = &self.nodes[*id];
+            let file_name = id
+                .file
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            xml.push_str(&format!(
+                "      <node id=\"n{}\" label=\"{}\">\n",
+                index[*id],
+                escape_xml(&format!("{}:{}", file_name, id.line + 1))
+            ));
+            xml.push_str("        <attvalues>\n");
+            xml.push_str(&format!("          <attvalue for=\"0\" value=\"{}\"/>\n", escape_xml(&id.file.display().to_string())));
+            xml.push_str(&format!("          <attvalue for=\"1\" value=\"{}\"/>\n", id.line + 1));
+            xml.push_str(&format!("          <attvalue for=\"2\" value=\"{}\"/>\n", escape_xml(&node.node_type)));
+            if let Some(module_path) = &node.module_path {
+                xml.push_str(&format!("          <attvalue for=\"3\" value=\"{}\"/>\n", escape_xml(module_path)));
+            }
+            if !node.tags.is_empty() {
+                xml.push_str(&format!("          <attvalue for=\"4\" value=\"{}\"/>\n", escape_xml(&format_tags(&node.tags))));
+            }
+            xml.push_str("        </attvalues>\n");
+            xml.push_str("      </node>\n");
+        }
+
+        xml.push_str("    </nodes>\n");
+        xml.push_str("    <edges>\n");
+
+        for (i, edge) in self.sorted_edges().iter().enumerate() {
+            let (Some(from), Some(to)) = (index.get(&edge.from), index.get(&edge.to)) else {
+                continue;
+            };
+            xml.push_str(&format!(
+                "      <edge id=\"e{}\" source=\"n{}\" target=\"n{}\" label=\"{}\"/>\n",
+                i,
+                from,
+                to,
+                edge.edge_type.as_str()
+            ));
+        }
+
+        xml.push_str("    </edges>\n");
+        xml.push_str("  </graph>\n</gexf>\n");
+        xml
+    }
+
+    /// Render the graph as a self-contained HTML page: a force-directed layout
+    /// with code tooltips and per-edge-type filters, no external scripts
+    pub fn to_html(&self) -> String {
+        let (ids, index) = ordered_ids(self);
+
+        let nodes: Vec<_> = ids
+            .iter()
+            .map(|id| {
+                let node = &self.nodes[*id];
+                let file_name = id
+                    .file
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                json!({
+                    "label": format!("{}:{}", file_name, id.line + 1),
+                    "file": id.file.display().to_string(),
+                    "line": id.line + 1,
+                    "type": node.node_type,
+                    "module": node.module_path,
+                    "tags": format_tags(&node.tags),
+                    "code": node.text(),
+                })
+            })
+            .collect();
+        let edges: Vec<_> = self
+            .sorted_edges()
+            .iter()
+            .filter_map(|edge| {
+                let (from, to) = (index.get(&edge.from)?, index.get(&edge.to)?);
+                Some(json!({
+                    "from": from,
+                    "to": to,
+                    "type": edge.edge_type.as_str(),
+                    "occurrences": edge.metadata.occurrences,
+                    "conditional": edge.metadata.conditional,
+                    "inferred": edge.metadata.inferred,
+                    "cycle": edge.metadata.cycle,
+                }))
+            })
+            .collect();
+
+        // `</` would let code inside the data close the surrounding <script>
+        let data = json!({ "nodes": nodes, "edges": edges }).to_string().replace("</", "<\\/");
+        HTML_TEMPLATE.replace("/*GRAPH_DATA*/", &data)
+    }
+
+    /// Write `to_html` to `path`
+    pub fn export_html(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_html()).with_context(|| format!("Failed to write graph HTML to {}", path.display()))
+    }
+}
+
+/// Node ids in `sorted_nodes` order plus each node's position in that order
+fn ordered_ids(graph: &DependencyGraph) -> (Vec<&NodeId>, HashMap<&NodeId, usize>) {
+    let ids: Vec<&NodeId> = graph.sorted_nodes().into_iter().map(|node| &node.id).collect();
+    let index = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    (ids, index)
+}
+
+/// Render annotations as `key=value; key=value`
+pub(crate) fn format_tags(tags: &BTreeMap<String, String>) -> String {
+    tags.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join("; ")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn dot_edge_style(edge_type: &EdgeType) -> &'static str {
+    match edge_type {
+        EdgeType::Defines => "label=\"defines\", color=\"#2e7d32\", style=bold",
+        EdgeType::Calls => "label=\"calls\", color=\"#1565c0\"",
+        EdgeType::Reads => "label=\"reads\", color=\"#6a1b9a\", style=dashed",
+        EdgeType::Writes => "label=\"writes\", color=\"#c62828\", style=dashed",
+        EdgeType::References => "label=\"references\", color=\"#757575\", style=dotted",
+        EdgeType::Implements => "label=\"implements\", color=\"#ef6c00\", arrowhead=onormal",
+        EdgeType::Contains => "label=\"contains\", color=\"#9e9e9e\", arrowhead=diamond",
+        EdgeType::Imports => "label=\"imports\", color=\"#00838f\", style=dashed",
+        EdgeType::TypeOf => "label=\"type_of\", color=\"#ad1457\", style=dotted",
+    }
+}
+
+/// Escape a string for use inside a double-quoted DOT attribute.
+/// Backslashes are escaped first so they can't swallow the quote escapes.
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CodeNode, Edge, test_id};
+
+    #[test]
+    fn test_to_dot() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), "fn a() { b() }".to_string(), "target"));
+        graph.add_node(CodeNode::new(test_id(5), "fn b() {}".to_string(), "call"));
+        graph.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph slice {"));
+        assert!(dot.contains("n0 [label=\"lib.rs:2\\n(target)\""));
+        assert!(dot.contains("n0 -> n1 [label=\"calls\""));
+    }
+
+    #[test]
+    fn test_xml_exports_use_one_based_lines() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(0), "fn a() {}".to_string(), "target"));
+
+        assert!(graph.to_graphml().contains("<data key=\"line\">1</data>"));
+        let gexf = graph.to_gexf();
+        assert!(gexf.contains("label=\"lib.rs:1\""));
+        assert!(gexf.contains("<attvalue for=\"1\" value=\"1\"/>"));
+    }
+
+    #[test]
+    fn test_escape_dot_escapes_backslashes_before_quotes() {
+        assert_eq!(escape_dot(r#"a\"b"#), r#"a\\\"b"#);
+        assert_eq!(escape_dot(r"C:\src\"), r"C:\\src\\");
+    }
+
+    #[test]
+    fn test_to_html_embeds_escaped_graph() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), "fn a() { \"</script>\" }".to_string(), "target"));
+        graph.add_node(CodeNode::new(test_id(5), "fn b() {}".to_string(), "call"));
+        graph.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+
+        let html = graph.to_html();
+        assert!(!html.contains("/*GRAPH_DATA*/"));
+        assert_eq!(html.matches("</script>").count(), 1);
+        assert!(html.contains("\"type\":\"calls\""));
+        assert!(html.contains("\"label\":\"lib.rs:2\""));
+        assert!(html.contains("\"line\":2"));
+    }
+}
@@ -1,16 +1,188 @@
+use crate::cfg::cfg_predicate;
+use crate::graph::{SymbolKind, content_hash};
+use crate::rules::ExtractionRules;
 use anyhow::Result;
-use tree_sitter::{Parser, Point, Node};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tree_sitter::{InputEdit, Parser, Point, Node, Tree};
 use tree_sitter_rust;
 
+#[derive(Clone)]
 pub struct SymbolInfo {
     pub name: String,
     pub kind: String,
     pub code: String,
+    /// 0-based start of the item; the column is in bytes
     pub line: usize,
+    pub column: usize,
+    /// 0-based end of the item, exclusive; the column is in bytes
+    pub end_line: usize,
+    pub end_column: usize,
+    /// Byte offsets of `code` in the file
+    pub byte_range: Range<usize>,
+    /// 0-based line and byte column of the name, where a server places the definition
+    pub name_position: (usize, usize),
+    /// Outer doc comment with the markers stripped
+    pub docs: Option<String>,
+    /// Outer attributes in source order, e.g. `#[derive(Debug, Clone)]`
+    pub attributes: Vec<String>,
 }
 
+impl SymbolInfo {
+    /// Traits named in `#[derive(...)]` attributes
+    pub fn derives(&self) -> Vec<String> {
+        self.attributes
+            .iter()
+            .filter_map(|attribute| {
+                let inner: String = attribute.trim_start_matches(['#', '[']).split_whitespace().collect();
+                inner.strip_prefix("derive(")?.strip_suffix(")]").map(str::to_string)
+            })
+            .flat_map(|list| list.split(',').filter(|name| !name.is_empty()).map(str::to_string).collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Whether the item is a test function (`#[test]`, `#[tokio::test]` and the like)
+    pub fn is_test(&self) -> bool {
+        self.attributes.iter().any(|attribute| is_test_attribute(attribute) && cfg_predicate(attribute).is_none())
+    }
+}
+
+/// A function marked `#[test]`, `#[tokio::test]` or the like
+pub struct TestFunction {
+    pub name: String,
+    /// Inline `mod` blocks around it, outermost first
+    pub modules: Vec<String>,
+    /// 0-based line and byte column of the function's name
+    pub name_position: (usize, usize),
+}
+
+/// A module-level `use` declaration and the names it brings into scope
+pub struct UseDeclaration {
+    pub text: String,
+    pub line: usize,
+    /// Names usable after the import (the last segment, or the `as` alias)
+    pub names: Vec<String>,
+    /// Whether it is a glob (`use foo::*`), whose names are unknown
+    pub glob: bool,
+}
+
+/// One path brought in by a module-level `use` declaration, with `{...}` lists flattened
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// Full path, e.g. ["std", "collections", "HashMap"]; for a glob, the module globbed
+    pub path: Vec<String>,
+    /// Name given with `as`, including `_` for anonymous trait imports
+    pub alias: Option<String>,
+    /// Whether it is a glob (`use foo::*`)
+    pub glob: bool,
+    /// Visibility of the declaration, e.g. "pub" or "pub(crate)" for re-exports
+    pub visibility: Option<String>,
+    /// 0-based line of the `use` declaration
+    pub line: usize,
+}
+
+impl Import {
+    /// Name usable after the import: the alias or the last segment. `None` for globs.
+    pub fn name(&self) -> Option<&str> {
+        if self.glob {
+            return None;
+        }
+        self.alias.as_deref().or(self.path.last().map(String::as_str))
+    }
+}
+
+/// A function or method call found by `Extractor::get_call_sites`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    /// Callee as written, e.g. `Config::load` or `helper`; for a method call, the method name
+    pub callee: String,
+    /// Receiver of a method call, e.g. `self.config` for `self.config.load()`
+    pub receiver: Option<String>,
+    /// 0-based line and byte column of the callee's last segment
+    pub position: (usize, usize),
+    /// Byte range of the argument list, parentheses included
+    pub arguments: Range<usize>,
+}
+
+/// Whether a statement assigns a variable or only uses it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// A statement touching a local variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableAccess {
+    pub access: Access,
+    /// Start of the statement (0-based line, byte column)
+    pub line: usize,
+    pub column: usize,
+    pub statement: String,
+}
+
+/// Where a local variable is declared, assigned and used within its function
+pub struct DataFlow {
+    pub variable: String,
+    /// The `let` or parameter introducing the variable
+    pub declaration: VariableAccess,
+    /// Later assignments and uses, in source order
+    pub accesses: Vec<VariableAccess>,
+}
+
+/// How one occurrence of a variable touches it
+#[derive(PartialEq)]
+enum Occurrence {
+    Declare,
+    Write,
+    Read,
+}
+
+/// Structured description of the item enclosing a position
+pub struct ItemDetails {
+    pub name: Option<String>,
+    /// First line of the item (0-based)
+    pub line: usize,
+    /// Line and byte column of `name` (0-based)
+    pub name_position: Option<(usize, usize)>,
+    pub kind: SymbolKind,
+    pub signature: String,
+    pub docs: Option<String>,
+}
+
+/// Signature of an item, read from the syntax tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// Tree-sitter kind, e.g. "function_item" or "impl_item"
+    pub kind: String,
+    /// Item name; for an impl, the implementing type
+    pub name: Option<String>,
+    /// Generic parameters, e.g. `<T: Clone>`
+    pub generics: Option<String>,
+    /// Parameter list of a function, e.g. `(&self, by: u32)`
+    pub parameters: Option<String>,
+    /// Return type of a function, without the arrow
+    pub return_type: Option<String>,
+    pub where_clause: Option<String>,
+    /// Everything before the body, e.g. `pub fn bump(&mut self, by: u32) -> u32`
+    pub text: String,
+}
+
+/// Parsed trees kept before the tree cache is flushed
+const MAX_CACHED_TREES: usize = 64;
+
+/// Files whose last version is kept for incremental reparsing
+const MAX_TRACKED_FILES: usize = 1024;
+
 pub struct Extractor {
     parser: Parser,
+    /// Content hash -> parse tree, so repeated queries on a file parse it once
+    trees: HashMap<String, Tree>,
+    /// Last known source and tree of each file passed to `update_file`
+    files: HashMap<PathBuf, (String, Tree)>,
+    /// What counts as a block or a symbol
+    rules: ExtractionRules,
 }
 
 impl Extractor {
@@ -19,13 +191,70 @@ impl Extractor {
         parser
             .set_language(&tree_sitter_rust::LANGUAGE.into())
             .map_err(|e| anyhow::anyhow!("Failed to set language: {}", e))?;
-        Ok(Self { parser })
+        Ok(Self { parser, trees: HashMap::new(), files: HashMap::new(), rules: ExtractionRules::default() })
+    }
+
+    /// Decide blocks and symbols by `rules` instead of the built-in ones
+    pub fn set_rules(&mut self, rules: ExtractionRules) {
+        self.rules = rules;
+    }
+
+    pub fn rules(&self) -> &ExtractionRules {
+        &self.rules
+    }
+
+    /// Record the current content of `file`, reparsing incrementally from its previous
+    /// version when there is one. Later queries on `source` reuse the resulting tree.
+    pub fn update_file(&mut self, file: &Path, source: &str) -> Option<Tree> {
+        let tree = match self.files.get(file) {
+            Some((old_source, tree)) if old_source.as_str() == source => return Some(tree.clone()),
+            Some((old_source, old_tree)) => {
+                // Edit a copy, so a failed reparse can't leave an edited tree beside the old source
+                let mut edited = old_tree.clone();
+                edited.edit(&input_edit(old_source, source));
+                self.parser.parse(source, Some(&edited))
+            }
+            None => self.parse(source),
+        };
+        let Some(tree) = tree else {
+            self.files.remove(file);
+            return None;
+        };
+
+        if self.files.len() >= MAX_TRACKED_FILES && !self.files.contains_key(file) {
+            self.files.clear();
+        }
+        self.files.insert(file.to_path_buf(), (source.to_string(), tree.clone()));
+        if self.trees.len() >= MAX_CACHED_TREES {
+            self.trees.clear();
+        }
+        self.trees.insert(content_hash(source), tree.clone());
+        Some(tree)
+    }
+
+    /// Stop tracking `file`, e.g. once it is deleted
+    pub fn forget_file(&mut self, file: &Path) {
+        self.files.remove(file);
+    }
+
+    fn parse(&mut self, source_code: &str) -> Option<Tree> {
+        let hash = content_hash(source_code);
+        if let Some(tree) = self.trees.get(&hash) {
+            return Some(tree.clone());
+        }
+        let tree = self.parser.parse(source_code, None)?;
+        if self.trees.len() >= MAX_CACHED_TREES {
+            self.trees.clear();
+        }
+        self.trees.insert(hash, tree.clone());
+        Some(tree)
     }
 
     /// Extract the full code block surrounding a given position.
     /// Walks up the AST to find relevant containers (function, struct, impl, etc.).
+    /// `column` is a byte offset; convert LSP columns with `position::to_byte_column`.
     pub fn extract_block(&mut self, source_code: &str, line: usize, column: usize) -> Option<String> {
-        let tree = self.parser.parse(source_code, None)?;
+        let tree = self.parse(source_code)?;
         let root = tree.root_node();
 
         // tree-sitter uses 0-indexed lines and columns
@@ -33,21 +262,12 @@ impl Extractor {
 
         // Find the smallest named node containing the point
         let mut node = root.descendant_for_point_range(target_point, target_point)?;
+        let blocks = self.rules.blocks_at(root, source_code, target_point);
 
         // Walk up to find a significant node
         while let Some(parent) = node.parent() {
-            let kind = node.kind();
-
-            // List of nodes we consider "blocks" worth extracting entirely
-            if matches!(kind,
-                "function_item" |
-                "struct_item" |
-                "enum_item" |
-                "impl_item" |
-                "trait_item" |
-                "mod_item" |
-                "macro_definition"
-            ) {
+            // Nodes the block rules consider worth extracting entirely
+            if blocks.contains(&node.id()) {
                 return Some(self.get_node_text(source_code, &node));
             }
 
@@ -82,44 +302,518 @@ impl Extractor {
         None
     }
 
-    /// Scan source code for top-level definitions
+    /// Code of the innermost function containing `(line, column)` (`column` in bytes).
+    /// Unlike `extract_block`, never widens to the surrounding impl or module.
+    pub fn extract_enclosing_function(&mut self, source_code: &str, line: usize, column: usize) -> Option<String> {
+        let tree = self.parse(source_code)?;
+        let target_point = Point::new(line, column);
+        let mut curr = tree.root_node().descendant_for_point_range(target_point, target_point);
+        while let Some(node) = curr {
+            if node.kind() == "function_item" {
+                return Some(self.get_node_text(source_code, &node));
+            }
+            curr = node.parent();
+        }
+        None
+    }
+
+    /// Describe the innermost item (fn, struct, impl, ...) containing a position (`column` in bytes)
+    pub fn describe_item_at(&mut self, source_code: &str, line: usize, column: usize) -> Option<ItemDetails> {
+        let tree = self.parse(source_code)?;
+        let target_point = Point::new(line, column);
+        let mut node = tree.root_node().descendant_for_point_range(target_point, target_point)?;
+
+        let kind = loop {
+            if let Some(kind) = symbol_kind(&node) {
+                break kind;
+            }
+            node = node.parent()?;
+        };
+
+        let name_node = node.child_by_field_name("name").or_else(|| node.child_by_field_name("type"));
+        let name = name_node.map(|n| self.get_node_text(source_code, &n));
+        let name_position = name_node.map(|n| (n.start_position().row, n.start_position().column));
+
+        let signature = header(source_code, &node);
+
+        Some(ItemDetails {
+            name,
+            line: node.start_position().row,
+            name_position,
+            kind,
+            signature,
+            docs: self.doc_comment(source_code, &node),
+        })
+    }
+
+    /// Signature of the first item in `code`, e.g. a node's code
+    pub fn extract_signature(&mut self, code: &str) -> Option<Signature> {
+        let tree = self.parse(code)?;
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+        let item = root.named_children(&mut cursor).find(|child| symbol_kind(child).is_some())?;
+
+        let field = |name: &str| item.child_by_field_name(name).map(|n| self.get_node_text(code, &n));
+        let mut where_cursor = item.walk();
+        let where_clause = item
+            .children(&mut where_cursor)
+            .find(|child| child.kind() == "where_clause")
+            .map(|n| self.get_node_text(code, &n));
+        Some(Signature {
+            kind: item.kind().to_string(),
+            name: field("name").or_else(|| field("type")),
+            generics: field("type_parameters"),
+            parameters: field("parameters"),
+            return_type: field("return_type"),
+            where_clause,
+            text: header(code, &item),
+        })
+    }
+
+    /// Interface of the items in `code`: functions become their signatures, impls and
+    /// traits keep their header and member signatures, and type definitions stay whole
+    /// since their fields are the interface. `None` if `code` holds no items.
+    pub fn extract_interface(&mut self, code: &str) -> Option<String> {
+        let tree = self.parse(code)?;
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+
+        let mut parts = Vec::new();
+        for item in root.named_children(&mut cursor).filter(|child| symbol_kind(child).is_some()) {
+            match item.kind() {
+                "struct_item" | "enum_item" | "union_item" | "type_item" | "macro_definition" => parts.push(self.get_node_text(code, &item)),
+                "impl_item" | "trait_item" => {
+                    let mut lines = vec![format!("{} {{", header(code, &item))];
+                    if let Some(body) = item.child_by_field_name("body") {
+                        let mut body_cursor = body.walk();
+                        let members = body
+                            .named_children(&mut body_cursor)
+                            .filter(|member| symbol_kind(member).is_some() || member.kind() == "associated_type");
+                        for member in members {
+                            lines.push(format!("    {};", header(code, &member)));
+                        }
+                    }
+                    lines.push("}".to_string());
+                    parts.push(lines.join("\n"));
+                }
+                _ => parts.push(format!("{};", header(code, &item))),
+            }
+        }
+        (!parts.is_empty()).then(|| parts.join("\n\n"))
+    }
+
+    /// Attributes directly above an item in source order, skipping interleaved comments
+    fn item_attributes(&self, source: &str, item: &Node) -> Vec<String> {
+        let mut attributes = Vec::new();
+        let mut sibling = item.prev_sibling();
+        while let Some(node) = sibling.filter(|s| matches!(s.kind(), "attribute_item" | "line_comment" | "block_comment")) {
+            if node.kind() == "attribute_item" {
+                attributes.push(self.get_node_text(source, &node));
+            }
+            sibling = node.prev_sibling();
+        }
+        attributes.reverse();
+        attributes
+    }
+
+    /// Collect `///` and `/** */` comments directly above an item, skipping interleaved attributes
+    fn doc_comment(&self, source: &str, item: &Node) -> Option<String> {
+        let mut lines = Vec::new();
+        let mut sibling = item.prev_sibling();
+        while let Some(node) = sibling {
+            match node.kind() {
+                "attribute_item" => {}
+                "line_comment" => {
+                    let text = self.get_node_text(source, &node);
+                    let Some(doc) = text.strip_prefix("///").filter(|doc| !doc.starts_with('/')) else { break };
+                    lines.push(doc.strip_prefix(' ').unwrap_or(doc).trim_end().to_string());
+                }
+                "block_comment" => {
+                    let text = self.get_node_text(source, &node);
+                    let Some(doc) = text.strip_prefix("/**").filter(|doc| !doc.starts_with('*') && *doc != "/") else { break };
+                    let doc = doc.strip_suffix("*/").unwrap_or(doc);
+                    let block: Vec<String> = doc
+                        .lines()
+                        .map(|line| {
+                            let line = line.trim();
+                            let line = line.strip_prefix('*').unwrap_or(line);
+                            line.strip_prefix(' ').unwrap_or(line).trim_end().to_string()
+                        })
+                        .skip_while(|line| line.is_empty())
+                        .collect();
+                    // Lines are gathered bottom-up
+                    let end = block.iter().rposition(|line| !line.is_empty()).map_or(0, |i| i + 1);
+                    lines.extend(block[..end].iter().rev().cloned());
+                }
+                _ => break,
+            }
+            sibling = node.prev_sibling();
+        }
+
+        if lines.is_empty() {
+            return None;
+        }
+        lines.reverse();
+        Some(lines.join("\n"))
+    }
+
+    /// Find a macro invocation starting on `line` (e.g. an item generated by `macro_rules!`).
+    /// Returns the (line, byte column) of the macro name, where expansion can be requested.
+    pub fn macro_invocation_at(&mut self, source_code: &str, line: usize) -> Option<(usize, usize)> {
+        let tree = self.parse(source_code)?;
+        let root = tree.root_node();
+
+        // Start at the first non-whitespace character of the line
+        let text = source_code.lines().nth(line)?;
+        let column = text.len() - text.trim_start().len();
+        let target_point = Point::new(line, column);
+
+        let mut node = root.descendant_for_point_range(target_point, target_point)?;
+        loop {
+            if node.kind() == "macro_invocation" {
+                let name = node.child_by_field_name("macro").unwrap_or(node);
+                let position = name.start_position();
+                return Some((position.row, position.column));
+            }
+            // Stop at item boundaries: a macro call inside a function body is not the item itself
+            if matches!(node.kind(), "function_item" | "impl_item" | "trait_item" | "mod_item" | "source_file") {
+                return None;
+            }
+            node = node.parent()?;
+        }
+    }
+
+    /// Render a macro expansion as a slice block, keeping the invocation for reference
+    pub fn format_expansion(&self, invocation: &str, name: &str, expansion: &str) -> String {
+        format!("// {}\n// expanded from {}!:\n{}", invocation.trim(), name, expansion)
+    }
+
+    /// Scan source code for definitions, including those in inline `mod` blocks and the
+    /// methods, associated consts and types of `impl` and `trait` blocks, under qualified
+    /// names like `module::Type::method`
     pub fn get_defined_symbols(&mut self, source_code: &str) -> Vec<SymbolInfo> {
         let mut symbols = Vec::new();
-        let tree = match self.parser.parse(source_code, None) {
+        let tree = match self.parse(source_code) {
             Some(t) => t,
             None => return symbols,
         };
+        let matched = self.rules.symbols_in(tree.root_node(), source_code);
+        self.collect_symbols(source_code, tree.root_node(), &matched, &mut Vec::new(), &mut symbols);
+        symbols
+    }
+
+    /// Symbols among the children of `node`, descending into modules, impls and traits.
+    /// `matched` holds the nodes the symbol rules capture.
+    fn collect_symbols(
+        &self,
+        source_code: &str,
+        node: Node,
+        matched: &HashSet<usize>,
+        modules: &mut Vec<String>,
+        symbols: &mut Vec<SymbolInfo>,
+    ) {
+        let mut cursor = node.walk();
+
+        for child in node.children(&mut cursor) {
+            let kind = child.kind();
+            if matched.contains(&child.id()) {
+                symbols.push(self.symbol_info(source_code, &child, modules));
+            }
+
+            // Members are qualified by the implementing type or the trait
+            let owner = match kind {
+                "impl_item" => child.child_by_field_name("type").map(|ty| {
+                    let text = self.get_node_text(source_code, &ty);
+                    let base = text.split('<').next().unwrap_or_default().trim();
+                    base.rsplit("::").next().unwrap_or(base).to_string()
+                }),
+                "trait_item" => child.child_by_field_name("name").map(|name| self.get_node_text(source_code, &name)),
+                _ => None,
+            };
+            if let Some(owner) = owner
+                && let Some(body) = child.child_by_field_name("body")
+            {
+                modules.push(owner);
+                let mut body_cursor = body.walk();
+                for member in body.children(&mut body_cursor) {
+                    if matched.contains(&member.id()) {
+                        symbols.push(self.symbol_info(source_code, &member, modules));
+                    }
+                }
+                modules.pop();
+            }
+
+            if kind == "mod_item"
+                && let Some(name) = child.child_by_field_name("name")
+                && let Some(body) = child.child_by_field_name("body")
+            {
+                modules.push(self.get_node_text(source_code, &name));
+                self.collect_symbols(source_code, body, matched, modules, symbols);
+                modules.pop();
+            }
+        }
+    }
+
+    /// Describe a definition, qualifying its name with the enclosing modules, type or trait
+    fn symbol_info(&self, source_code: &str, node: &Node, path: &[String]) -> SymbolInfo {
+        let name_node = node.child_by_field_name("name");
+        let name = name_node
+            .map(|n| self.get_node_text(source_code, &n))
+            .unwrap_or_else(|| "unknown".to_string());
+        let (start, end) = (node.start_position(), node.end_position());
+        let name_start = name_node.map_or(start, |n| n.start_position());
+
+        SymbolInfo {
+            name: path.iter().cloned().chain([name]).collect::<Vec<_>>().join("::"),
+            kind: node.kind().to_string(),
+            code: self.get_node_text(source_code, node),
+            line: start.row,
+            column: start.column,
+            end_line: end.row,
+            end_column: end.column,
+            byte_range: node.byte_range(),
+            name_position: (name_start.row, name_start.column),
+            docs: self.doc_comment(source_code, node),
+            attributes: self.item_attributes(source_code, node),
+        }
+    }
+
+    /// Test functions anywhere in a file, including inside `mod tests { ... }`
+    pub fn test_functions(&mut self, source_code: &str) -> Vec<TestFunction> {
+        let Some(tree) = self.parse(source_code) else {
+            return Vec::new();
+        };
+        let mut tests = Vec::new();
+        self.collect_test_functions(source_code, tree.root_node(), &mut Vec::new(), &mut tests);
+        tests
+    }
 
+    fn collect_test_functions(&self, source: &str, node: Node, modules: &mut Vec<String>, tests: &mut Vec<TestFunction>) {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            match child.kind() {
+                "function_item" => {
+                    let is_test = self
+                        .item_attributes(source, &child)
+                        .iter()
+                        .any(|attribute| is_test_attribute(attribute) && cfg_predicate(attribute).is_none());
+                    if is_test && let Some(name) = child.child_by_field_name("name") {
+                        tests.push(TestFunction {
+                            name: self.get_node_text(source, &name),
+                            modules: modules.clone(),
+                            name_position: (name.start_position().row, name.start_position().column),
+                        });
+                    }
+                }
+                "mod_item" => {
+                    if let Some(name) = child.child_by_field_name("name")
+                        && let Some(body) = child.child_by_field_name("body")
+                    {
+                        modules.push(self.get_node_text(source, &name));
+                        self.collect_test_functions(source, body, modules, tests);
+                        modules.pop();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// `use` declarations at the top level of a file, with the names each brings in
+    /// taken from its flattened `Import`s
+    pub fn use_declarations(&mut self, source_code: &str) -> Vec<UseDeclaration> {
+        let Some(tree) = self.parse(source_code) else {
+            return Vec::new();
+        };
         let root = tree.root_node();
         let mut cursor = root.walk();
 
-        for child in root.children(&mut cursor) {
-            let kind = child.kind();
-            if matches!(kind,
-                "function_item" |
-                "struct_item" |
-                "enum_item" |
-                "trait_item" |
-                "mod_item" |
-                "macro_definition"
-            ) {
-                // Extract name
-                // Usually the name is in a child node of type "identifier" or "name"
-                // Or "type_identifier" for structs
-                let name = child.child_by_field_name("name")
-                    .map(|n| self.get_node_text(source_code, &n))
-                    .unwrap_or_else(|| "unknown".to_string());
-
-                symbols.push(SymbolInfo {
-                    name,
-                    kind: kind.to_string(),
-                    code: self.get_node_text(source_code, &child),
+        root.children(&mut cursor)
+            .filter(|child| child.kind() == "use_declaration")
+            .map(|child| {
+                let imports = self.declaration_imports(source_code, &child);
+                UseDeclaration {
+                    text: self.get_node_text(source_code, &child),
                     line: child.start_position().row,
-                });
+                    names: imports.iter().filter_map(Import::name).map(String::from).collect(),
+                    glob: imports.iter().any(|import| import.glob),
+                }
+            })
+            .collect()
+    }
+
+    /// Every function and method call in `block`, in source order. Positions and spans
+    /// are relative to `block`; macro invocations are not calls.
+    pub fn get_call_sites(&mut self, block: &str) -> Vec<CallSite> {
+        let Some(tree) = self.parse(block) else {
+            return Vec::new();
+        };
+        let mut calls = Vec::new();
+        let mut stack = vec![tree.root_node()];
+        while let Some(node) = stack.pop() {
+            if node.kind() == "call_expression"
+                && let Some(mut function) = node.child_by_field_name("function")
+                && let Some(arguments) = node.child_by_field_name("arguments")
+            {
+                // `parse::<T>(..)` calls `parse`
+                if function.kind() == "generic_function"
+                    && let Some(inner) = function.child_by_field_name("function")
+                {
+                    function = inner;
+                }
+                let (callee, receiver, name) = match function.kind() {
+                    "field_expression" => (
+                        function.child_by_field_name("field"),
+                        function.child_by_field_name("value").map(|value| self.get_node_text(block, &value)),
+                        function.child_by_field_name("field"),
+                    ),
+                    "scoped_identifier" => (Some(function), None, function.child_by_field_name("name")),
+                    "identifier" => (Some(function), None, Some(function)),
+                    // Closures and other computed callees have no name to follow
+                    _ => (None, None, None),
+                };
+                if let (Some(callee), Some(name)) = (callee, name) {
+                    calls.push(CallSite {
+                        callee: path_segments(&self.get_node_text(block, &callee)).join("::"),
+                        receiver,
+                        position: (name.start_position().row, name.start_position().column),
+                        arguments: arguments.byte_range(),
+                    });
+                }
             }
+            let mut cursor = node.walk();
+            let children: Vec<Node> = node.children(&mut cursor).collect();
+            stack.extend(children.into_iter().rev());
         }
+        calls
+    }
 
-        symbols
+    /// Module-level `use` declarations of a file as flat imports, one per path
+    pub fn get_imports(&mut self, source_code: &str) -> Vec<Import> {
+        let Some(tree) = self.parse(source_code) else {
+            return Vec::new();
+        };
+        let root = tree.root_node();
+        let mut cursor = root.walk();
+
+        root.children(&mut cursor)
+            .filter(|child| child.kind() == "use_declaration")
+            .flat_map(|child| self.declaration_imports(source_code, &child))
+            .collect()
+    }
+
+    /// Imports of a single `use_declaration` node
+    fn declaration_imports(&self, source: &str, declaration: &Node) -> Vec<Import> {
+        let mut visibility_cursor = declaration.walk();
+        let template = Import {
+            path: Vec::new(),
+            alias: None,
+            glob: false,
+            visibility: declaration
+                .children(&mut visibility_cursor)
+                .find(|node| node.kind() == "visibility_modifier")
+                .map(|node| self.get_node_text(source, &node)),
+            line: declaration.start_position().row,
+        };
+        let mut imports = Vec::new();
+        if let Some(argument) = declaration.child_by_field_name("argument") {
+            self.collect_imports(source, &argument, &[], &template, &mut imports);
+        }
+        imports
+    }
+
+    /// Imports in a use tree below the path `prefix`
+    fn collect_imports(&self, source: &str, node: &Node, prefix: &[String], template: &Import, imports: &mut Vec<Import>) {
+        let joined = |path: Option<Node>| {
+            let mut full = prefix.to_vec();
+            if let Some(path) = path {
+                full.extend(path_segments(&self.get_node_text(source, &path)));
+            }
+            full
+        };
+        match node.kind() {
+            // `self` in a list imports the list's parent module
+            "self" if !prefix.is_empty() => imports.push(Import { path: prefix.to_vec(), ..template.clone() }),
+            "identifier" | "type_identifier" | "scoped_identifier" | "crate" | "super" | "self" | "metavariable" => {
+                imports.push(Import { path: joined(Some(*node)), ..template.clone() });
+            }
+            "use_as_clause" => {
+                let mut path = joined(node.child_by_field_name("path"));
+                // `use foo::{self as bar}`
+                if path.last().is_some_and(|last| last == "self") && path.len() > 1 {
+                    path.pop();
+                }
+                let alias = node.child_by_field_name("alias").map(|alias| self.get_node_text(source, &alias));
+                imports.push(Import { path, alias, ..template.clone() });
+            }
+            "scoped_use_list" => {
+                let prefix = joined(node.child_by_field_name("path"));
+                if let Some(list) = node.child_by_field_name("list") {
+                    self.collect_imports(source, &list, &prefix, template, imports);
+                }
+            }
+            "use_list" => {
+                let mut cursor = node.walk();
+                for child in node.named_children(&mut cursor) {
+                    self.collect_imports(source, &child, prefix, template, imports);
+                }
+            }
+            "use_wildcard" => {
+                let text = self.get_node_text(source, node);
+                let mut path = prefix.to_vec();
+                path.extend(path_segments(text.trim_end_matches('*')));
+                imports.push(Import { path, glob: true, ..template.clone() });
+            }
+            _ => {}
+        }
+    }
+
+    /// Data flow of the local variable named at the position (`column` in bytes) through
+    /// its enclosing function. Shadowing is not tracked: every binding of the name counts.
+    /// Returns `None` if the position is not on a variable declared in that function.
+    pub fn data_flow_at(&mut self, source_code: &str, line: usize, column: usize) -> Option<DataFlow> {
+        let tree = self.parse(source_code)?;
+        let target_point = Point::new(line, column);
+        let target = tree.root_node().descendant_for_point_range(target_point, target_point)?;
+        if target.kind() != "identifier" {
+            return None;
+        }
+        let variable = self.get_node_text(source_code, &target);
+
+        let mut function = target.parent()?;
+        while !matches!(function.kind(), "function_item" | "closure_expression") {
+            function = function.parent()?;
+        }
+
+        let mut declaration = None;
+        let mut accesses: Vec<VariableAccess> = Vec::new();
+        let mut stack = vec![function];
+        while let Some(node) = stack.pop() {
+            let mut cursor = node.walk();
+            stack.extend(node.children(&mut cursor));
+            if node.kind() != "identifier" || self.get_node_text(source_code, &node) != variable || !is_variable_use(&node) {
+                continue;
+            }
+
+            let occurrence = classify_occurrence(&node);
+            let statement = enclosing_statement(node);
+            let access = VariableAccess {
+                access: if occurrence == Occurrence::Read { Access::Read } else { Access::Write },
+                line: statement.start_position().row,
+                column: statement.start_position().column,
+                statement: self.get_node_text(source_code, &statement),
+            };
+            if occurrence == Occurrence::Declare && declaration.is_none() {
+                declaration = Some(access);
+            } else if !accesses.contains(&access) {
+                accesses.push(access);
+            }
+        }
+
+        let declaration = declaration?;
+        accesses.retain(|access| access != &declaration);
+        accesses.sort_by_key(|access| (access.line, access.column, access.access == Access::Write));
+        Some(DataFlow { variable, declaration, accesses })
     }
 
     fn get_node_text(&self, source: &str, node: &Node) -> String {
@@ -132,15 +826,21 @@ impl Extractor {
         }
     }
 
-    /// Extract constraints for a specific location in the code
+    /// Extract constraints for a specific location in the code (`column` in bytes)
     /// Returns (assignments, conditions)
     /// assignments: variables known to have constant integer values before this point
-    /// conditions: conditions that must be true to reach this point (from surrounding if statements)
-    pub fn extract_constraints(&mut self, source_code: &str, line: usize, column: usize) -> (Vec<Constraint>, Vec<Constraint>) {
+    /// conditions: conditions that must be true to reach this point (surrounding `if` and `while`
+    /// conditions, match arm patterns and guards, and `let ... else` patterns), plus the values
+    /// of boolean flags whose latest binding before it is an immutable `let flag = true/false;`
+    pub fn extract_constraints(&mut self, source_code: &str, line: usize, column: usize) -> (Vec<Constraint>, Vec<Condition>) {
         let mut assignments = Vec::new();
         let mut conditions = Vec::new();
+        // Flag name -> its value at the target, or `None` once rebound or assigned to
+        // something unknown. Blocks are walked inside out and statements last to first,
+        // so the first binding seen for a name is the latest one.
+        let mut flags: BTreeMap<String, Option<Condition>> = BTreeMap::new();
 
-        let tree = match self.parser.parse(source_code, None) {
+        let tree = match self.parse(source_code) {
             Some(t) => t,
             None => return (assignments, conditions),
         };
@@ -162,17 +862,49 @@ impl Extractor {
             // If parent is a block, scan previous siblings
             if parent.kind() == "block" {
                 let mut cursor = parent.walk();
-                for child in parent.children(&mut cursor) {
-                    if child.end_byte() <= curr.start_byte() {
-                        // This child comes before our path
-                        if child.kind() == "let_declaration"
-                            && let Some(constraint) = self.parse_let_assignment(source_code, &child) {
-                                assignments.push(constraint);
-                            }
+                let before: Vec<Node> = parent.children(&mut cursor).filter(|child| child.end_byte() <= curr.start_byte()).collect();
+                for child in before.iter().rev() {
+                    self.record_flag_bindings(source_code, child, &mut flags);
+                }
+                for child in before {
+                    // This child comes before our path
+                    if child.kind() == "let_declaration"
+                        && let Some(constraint) = self.parse_let_assignment(source_code, &child) {
+                            assignments.push(constraint);
+                        }
+                    // Past `let PATTERN = x else { .. };` the pattern matched
+                    if child.kind() == "let_declaration"
+                        && child.child_by_field_name("alternative").is_some()
+                        && let Some(condition) = self.let_pattern_condition(source_code, &child)
+                    {
+                        conditions.push(condition);
                     }
                 }
             }
 
+            // Inside a let-else's `else`, the pattern did not match
+            if parent.kind() == "let_declaration"
+                && parent.child_by_field_name("alternative") == Some(curr)
+                && let Some(condition) = self.let_pattern_condition(source_code, &parent)
+            {
+                conditions.push(Condition::Not(Box::new(condition)));
+            }
+
+            // Loop bodies run while the loop condition holds
+            if parent.kind() == "while_expression"
+                && parent.child_by_field_name("body") == Some(curr)
+                && let Some(condition) = parent.child_by_field_name("condition")
+                && let Some(constraint) = self.parse_condition(source_code, &condition)
+            {
+                conditions.push(constraint);
+            }
+
+            // A match arm runs when its pattern matches and its guard holds, and no
+            // earlier unguarded arm matched
+            if parent.kind() == "match_arm" && parent.child_by_field_name("value") == Some(curr) {
+                conditions.extend(self.match_arm_conditions(source_code, &parent));
+            }
+
             // 2. Check if we are inside an IF block
             if parent.kind() == "if_expression" {
                 // Check if we are in the consequence block
@@ -192,9 +924,97 @@ impl Extractor {
             curr = parent;
         }
 
+        conditions.extend(flags.into_values().flatten());
         (assignments, conditions)
     }
 
+    /// True if the position (`column` in bytes) only executes on some paths through its
+    /// enclosing function: inside an `if` branch, `match` arm or loop body.
+    pub fn is_conditional_at(&mut self, source_code: &str, line: usize, column: usize) -> bool {
+        let Some(tree) = self.parse(source_code) else {
+            return false;
+        };
+        let target_point = Point::new(line, column);
+        let Some(mut curr) = tree.root_node().descendant_for_point_range(target_point, target_point) else {
+            return false;
+        };
+
+        while let Some(parent) = curr.parent() {
+            match parent.kind() {
+                "function_item" | "closure_expression" => return false,
+                "match_arm" | "else_clause" => return true,
+                // The condition itself always runs; only the bodies are conditional
+                "if_expression" | "while_expression" | "for_expression"
+                    if parent.child_by_field_name("body").or_else(|| parent.child_by_field_name("consequence")) == Some(curr) =>
+                {
+                    return true;
+                }
+                _ => {}
+            }
+            curr = parent;
+        }
+
+        false
+    }
+
+    /// True if the position (`column` in bytes) is on a method declared in a trait,
+    /// as opposed to a free function or a method of an impl block
+    pub fn is_trait_method_at(&mut self, source_code: &str, line: usize, column: usize) -> bool {
+        let Some(tree) = self.parse(source_code) else {
+            return false;
+        };
+        let target_point = Point::new(line, column);
+        let Some(mut curr) = tree.root_node().descendant_for_point_range(target_point, target_point) else {
+            return false;
+        };
+
+        while let Some(parent) = curr.parent() {
+            match parent.kind() {
+                "trait_item" => return true,
+                // Inside a body: a nested item, not the trait's own method
+                "impl_item" | "block" | "source_file" => return false,
+                _ => {}
+            }
+            curr = parent;
+        }
+
+        false
+    }
+
+    /// Whether `(line, column)` sits inside a `#[test]` function or a `#[cfg(test)]` item
+    pub fn is_test_code_at(&mut self, source_code: &str, line: usize, column: usize) -> bool {
+        self.attributes_at(source_code, line, column).iter().any(|attribute| is_test_attribute(attribute))
+    }
+
+    /// Attributes on every item enclosing `(line, column)` (`column` in bytes), innermost
+    /// first, followed by the file's inner attributes (`#![...]`)
+    pub fn attributes_at(&mut self, source_code: &str, line: usize, column: usize) -> Vec<String> {
+        let Some(tree) = self.parse(source_code) else {
+            return Vec::new();
+        };
+        let root = tree.root_node();
+        let target_point = Point::new(line, column);
+        let mut attributes = Vec::new();
+
+        let mut curr = root.descendant_for_point_range(target_point, target_point);
+        while let Some(node) = curr {
+            let mut sibling = node.prev_sibling();
+            while let Some(attribute) = sibling.filter(|s| s.kind() == "attribute_item" || s.kind() == "line_comment") {
+                if attribute.kind() == "attribute_item" {
+                    attributes.push(self.get_node_text(source_code, &attribute));
+                }
+                sibling = attribute.prev_sibling();
+            }
+            curr = node.parent();
+        }
+
+        let mut cursor = root.walk();
+        for child in root.children(&mut cursor).filter(|child| child.kind() == "inner_attribute_item") {
+            attributes.push(self.get_node_text(source_code, &child));
+        }
+        attributes
+    }
+
     fn parse_let_assignment(&self, source: &str, node: &Node) -> Option<Constraint> {
         // let pattern = value;
         let pattern = node.child_by_field_name("pattern")?;
@@ -214,20 +1034,213 @@ impl Extractor {
         None
     }
 
-    fn parse_condition(&self, source: &str, node: &Node) -> Option<Constraint> {
-        // Simple binary expression: left op right
-        // heuristic: strip parenthesis if present
-        // tree-sitter often wraps condition in nothing special, but binary_expression is key
+    /// `let flag = true;` as `Flag`, `let flag = false;` as its negation. Mutable
+    /// bindings are skipped, since later assignments aren't tracked.
+    fn parse_let_flag(&self, source: &str, node: &Node) -> Option<Condition> {
+        let mut cursor = node.walk();
+        if node.children(&mut cursor).any(|child| child.kind() == "mutable_specifier") {
+            return None;
+        }
+        let pattern = node.child_by_field_name("pattern")?;
+        let value = node.child_by_field_name("value")?;
+        if pattern.kind() != "identifier" || value.kind() != "boolean_literal" {
+            return None;
+        }
+        let flag = Condition::Flag(self.get_node_text(source, &pattern));
+        match self.get_node_text(source, &value).as_str() {
+            "true" => Some(flag),
+            _ => Some(Condition::Not(Box::new(flag))),
+        }
+    }
 
-        // If condition is just a binary expression
-        if node.kind() == "binary_expression" {
-             return self.parse_binary_expression(source, node);
+    /// Record the value `statement` leaves each flag it binds or assigns with, for names a
+    /// later statement hasn't already decided: the literal for `let flag = true/false;`,
+    /// unknown for any other `let` of the name or assignment to it
+    fn record_flag_bindings(&self, source: &str, statement: &Node, flags: &mut BTreeMap<String, Option<Condition>>) {
+        if statement.kind() == "let_declaration"
+            && let Some(pattern) = statement.child_by_field_name("pattern")
+        {
+            let literal = self.parse_let_flag(source, statement);
+            let mut names = Vec::new();
+            self.collect_identifiers(source, &pattern, &mut names);
+            for name in names {
+                flags.entry(name).or_insert_with(|| literal.clone());
+            }
+        }
+        let mut assigned = Vec::new();
+        self.collect_assigned_names(source, statement, &mut assigned);
+        for name in assigned {
+            flags.entry(name).or_insert(None);
         }
+    }
 
-        // Use recursive search for binary expression if it's wrapped?
-        // e.g. `x < 5` inside `(x < 5)`?
-        // For MVP, just direct binary expression check
-        None
+    /// Every identifier in `node`, e.g. the names a `let` pattern binds
+    fn collect_identifiers(&self, source: &str, node: &Node, names: &mut Vec<String>) {
+        if node.kind() == "identifier" {
+            names.push(self.get_node_text(source, node));
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.collect_identifiers(source, &child, names);
+        }
+    }
+
+    /// Variables assigned to (`x = ..`, `x += ..`) anywhere in `node`
+    fn collect_assigned_names(&self, source: &str, node: &Node, names: &mut Vec<String>) {
+        if matches!(node.kind(), "assignment_expression" | "compound_assignment_expr")
+            && let Some(left) = node.child_by_field_name("left")
+            && left.kind() == "identifier"
+        {
+            names.push(self.get_node_text(source, &left));
+        }
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            self.collect_assigned_names(source, &child, names);
+        }
+    }
+
+    /// What must hold for `arm` to be the one taken: its pattern against the scrutinee,
+    /// its guard, and the negated patterns of the unguarded arms before it
+    fn match_arm_conditions(&self, source: &str, arm: &Node) -> Vec<Condition> {
+        let mut conditions = Vec::new();
+        let Some(match_pattern) = arm.child_by_field_name("pattern") else {
+            return conditions;
+        };
+        let scrutinee = arm.parent().and_then(|block| block.parent()).and_then(|m| m.child_by_field_name("value"));
+
+        if let Some(scrutinee) = scrutinee {
+            if let Some(pattern) = match_pattern.named_child(0)
+                && let Some(condition) = self.pattern_condition(source, &pattern, &scrutinee)
+            {
+                conditions.push(condition);
+            }
+
+            let mut sibling = arm.prev_named_sibling();
+            while let Some(earlier) = sibling {
+                if earlier.kind() == "match_arm"
+                    && let Some(earlier_pattern) = earlier.child_by_field_name("pattern")
+                    && earlier_pattern.child_by_field_name("condition").is_none()
+                    && let Some(pattern) = earlier_pattern.named_child(0)
+                    && let Some(condition) = self.pattern_condition(source, &pattern, &scrutinee)
+                {
+                    conditions.push(Condition::Not(Box::new(condition)));
+                }
+                sibling = earlier.prev_named_sibling();
+            }
+        }
+
+        if let Some(guard) = match_pattern.child_by_field_name("condition")
+            && let Some(condition) = self.parse_condition(source, &guard)
+        {
+            conditions.push(condition);
+        }
+        conditions
+    }
+
+    /// Condition under which a `let` (or `let ... else`) pattern matches its value
+    fn let_pattern_condition(&self, source: &str, node: &Node) -> Option<Condition> {
+        let pattern = node.child_by_field_name("pattern")?;
+        let value = node.child_by_field_name("value")?;
+        self.pattern_condition(source, &pattern, &value)
+    }
+
+    /// Exact condition under which `pattern` matches an integer variable `scrutinee`:
+    /// literals, ranges with literal bounds and `|` alternatives of those. Bindings and
+    /// wildcards say nothing.
+    fn pattern_condition(&self, source: &str, pattern: &Node, scrutinee: &Node) -> Option<Condition> {
+        if scrutinee.kind() != "identifier" {
+            return None;
+        }
+        let var = self.get_node_text(source, scrutinee);
+        let literal = |node: &Node| self.get_node_text(source, node).replace('_', "").parse::<i64>().ok();
+        let compare = |op: &str, val: i64| Condition::Compare(Constraint { var: var.clone(), op: op.to_string(), val });
+
+        match pattern.kind() {
+            "integer_literal" | "negative_literal" => Some(compare("==", literal(pattern)?)),
+            "range_pattern" => {
+                let inclusive = self.get_node_text(source, pattern).contains("..=") || self.get_node_text(source, pattern).contains("...");
+                // A bound that isn't a literal (`0..=LIMIT`) can't be dropped: the condition
+                // gets negated for later arms and let-else, where a weaker one is unsound
+                let bound = |field: &str| match pattern.child_by_field_name(field) {
+                    Some(node) => literal(&node).map(Some),
+                    None => Some(None),
+                };
+                let low = bound("left")?.map(|val| compare(">=", val));
+                let high = bound("right")?.map(|val| compare(if inclusive { "<=" } else { "<" }, val));
+                match (low, high) {
+                    (Some(low), Some(high)) => Some(Condition::And(Box::new(low), Box::new(high))),
+                    (low, high) => low.or(high),
+                }
+            }
+            "or_pattern" => {
+                let mut cursor = pattern.walk();
+                let alternatives: Option<Vec<Condition>> =
+                    pattern.named_children(&mut cursor).map(|part| self.pattern_condition(source, &part, scrutinee)).collect();
+                alternatives?.into_iter().reduce(|left, right| Condition::Or(Box::new(left), Box::new(right)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Parse a condition into comparisons joined by `&&`, `||` and `!`. Parts that can't
+    /// be parsed (calls, non-integer comparisons) weaken the result rather than spoil it:
+    /// an unknown side of `&&` is dropped, while an unknown side of `||` makes the whole
+    /// unknown. Under `!` the roles swap, since `!(a && b)` is `!a || !b`.
+    fn parse_condition(&self, source: &str, node: &Node) -> Option<Condition> {
+        self.parse_polar_condition(source, node, true)
+    }
+
+    /// `parse_condition` for a node under an even (`positive`) or odd number of `!`s
+    fn parse_polar_condition(&self, source: &str, node: &Node, positive: bool) -> Option<Condition> {
+        match node.kind() {
+            "identifier" => Some(Condition::Flag(self.get_node_text(source, node))),
+            "parenthesized_expression" => self.parse_polar_condition(source, &node.named_child(0)?, positive),
+            "unary_expression" => {
+                let operator = node.child(0)?;
+                if self.get_node_text(source, &operator) != "!" {
+                    return None;
+                }
+                let operand = self.parse_polar_condition(source, &node.named_child(0)?, !positive)?;
+                Some(Condition::Not(Box::new(operand)))
+            }
+            "binary_expression" => {
+                let op_node = node.child_by_field_name("operator")?;
+                let (left, right) = (node.child_by_field_name("left")?, node.child_by_field_name("right")?);
+                let op = self.get_node_text(source, &op_node);
+                if op == "&&" || op == "||" {
+                    let (left, right) = (
+                        self.parse_polar_condition(source, &left, positive),
+                        self.parse_polar_condition(source, &right, positive),
+                    );
+                    return match (left, right) {
+                        (Some(left), Some(right)) if op == "&&" => Some(Condition::And(Box::new(left), Box::new(right))),
+                        (Some(left), Some(right)) => Some(Condition::Or(Box::new(left), Box::new(right))),
+                        // Dropping a side weakens a conjunction, or a negated disjunction
+                        (left, right) if (op == "&&") == positive => left.or(right),
+                        _ => None,
+                    };
+                }
+                match op.as_str() {
+                    // `flag == true`, `flag != false` and the like
+                    op @ ("==" | "!=") if left.kind() == "identifier" && right.kind() == "boolean_literal" => {
+                        let flag = Condition::Flag(self.get_node_text(source, &left));
+                        let is_true = (self.get_node_text(source, &right) == "true") == (op == "==");
+                        Some(if is_true { flag } else { Condition::Not(Box::new(flag)) })
+                    }
+                    _ => self.parse_binary_expression(source, node).map(Condition::Compare),
+                }
+            }
+            // `if let 1..=9 = x`: the pattern matched
+            "let_condition" => self.let_pattern_condition(source, node),
+            // `if let Some(v) = w && x > 5`: the comparisons in the chain all hold
+            "let_chain" => {
+                let mut cursor = node.walk();
+                let parts = node.named_children(&mut cursor).map(|part| self.parse_polar_condition(source, &part, positive));
+                let parts: Vec<Condition> = if positive { parts.flatten().collect() } else { parts.collect::<Option<_>>()? };
+                parts.into_iter().reduce(|left, right| Condition::And(Box::new(left), Box::new(right)))
+            }
+            _ => None,
+        }
     }
 
     fn parse_binary_expression(&self, source: &str, node: &Node) -> Option<Constraint> {
@@ -270,6 +1283,152 @@ pub struct Constraint {
     pub val: i64,
 }
 
+/// A branch condition: integer comparisons and boolean flags combined with `&&`, `||` and `!`
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Compare(Constraint),
+    /// A boolean variable is true
+    Flag(String),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+/// False for identifiers naming something other than a value: path segments,
+/// called functions and macros
+fn is_variable_use(node: &Node) -> bool {
+    let Some(parent) = node.parent() else {
+        return false;
+    };
+    match parent.kind() {
+        "scoped_identifier" | "scoped_type_identifier" | "macro_invocation" => false,
+        "call_expression" => parent.child_by_field_name("function") != Some(*node),
+        "function_item" => false,
+        _ => true,
+    }
+}
+
+/// Whether an occurrence of a variable declares, assigns or reads it
+fn classify_occurrence(node: &Node) -> Occurrence {
+    let mut curr = *node;
+    while let Some(parent) = curr.parent() {
+        match parent.kind() {
+            "closure_parameters" => return Occurrence::Declare,
+            "let_declaration" | "parameter" | "for_expression" => {
+                return match parent.child_by_field_name("pattern") == Some(curr) {
+                    true => Occurrence::Declare,
+                    false => Occurrence::Read,
+                };
+            }
+            "assignment_expression" | "compound_assignment_expr" => {
+                return match parent.child_by_field_name("left") == Some(curr) {
+                    true => Occurrence::Write,
+                    false => Occurrence::Read,
+                };
+            }
+            // `v[i] = ..` writes `v` but only reads `i`
+            "index_expression" if parent.child(0) != Some(curr) => return Occurrence::Read,
+            "block" | "expression_statement" | "function_item" | "closure_expression" | "arguments" => {
+                return Occurrence::Read;
+            }
+            _ => {}
+        }
+        curr = parent;
+    }
+    Occurrence::Read
+}
+
+/// The statement (or parameter) containing `node`
+fn enclosing_statement(node: Node) -> Node {
+    let mut curr = node;
+    while let Some(parent) = curr.parent() {
+        if curr.kind() == "parameter" || matches!(parent.kind(), "block" | "function_item" | "closure_expression") {
+            return curr;
+        }
+        curr = parent;
+    }
+    curr
+}
+
+/// Map a tree-sitter item node to its symbol kind; functions inside impls and traits are methods
+fn symbol_kind(node: &Node) -> Option<SymbolKind> {
+    let kind = match node.kind() {
+        "function_item" | "function_signature_item" => {
+            let in_impl = node
+                .parent()
+                .and_then(|list| list.parent())
+                .is_some_and(|owner| matches!(owner.kind(), "impl_item" | "trait_item"));
+            if in_impl { SymbolKind::Method } else { SymbolKind::Function }
+        }
+        "struct_item" => SymbolKind::Struct,
+        "enum_item" => SymbolKind::Enum,
+        "union_item" => SymbolKind::Union,
+        "trait_item" => SymbolKind::Trait,
+        "impl_item" => SymbolKind::Impl,
+        "mod_item" => SymbolKind::Module,
+        "macro_definition" => SymbolKind::Macro,
+        "const_item" => SymbolKind::Const,
+        "static_item" => SymbolKind::Static,
+        "type_item" => SymbolKind::TypeAlias,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+/// An item's text before its body; items without one (unit structs, consts) are kept whole
+fn header(source: &str, node: &Node) -> String {
+    let header_end = node.child_by_field_name("body").map_or(node.end_byte(), |body| body.start_byte());
+    source[node.start_byte()..header_end].trim().trim_end_matches(';').to_string()
+}
+
+/// Edit turning `old` into `new`: the span between their common prefix and suffix
+fn input_edit(old: &str, new: &str) -> InputEdit {
+    let mut prefix = old.bytes().zip(new.bytes()).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old.bytes().rev().zip(new.bytes().rev()).take(max_suffix).take_while(|(a, b)| a == b).count();
+    while !old.is_char_boundary(old.len() - suffix) || !new.is_char_boundary(new.len() - suffix) {
+        suffix -= 1;
+    }
+
+    InputEdit {
+        start_byte: prefix,
+        old_end_byte: old.len() - suffix,
+        new_end_byte: new.len() - suffix,
+        start_position: point_at(old, prefix),
+        old_end_position: point_at(old, old.len() - suffix),
+        new_end_position: point_at(new, new.len() - suffix),
+    }
+}
+
+/// Row and byte column of byte offset `byte` in `text`
+fn point_at(text: &str, byte: usize) -> Point {
+    let before = &text[..byte];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Point::new(before.matches('\n').count(), byte - line_start)
+}
+
+/// Segments of a path written in source, e.g. ["std", "io"] for `std :: io::`
+fn path_segments(text: &str) -> Vec<String> {
+    text.split("::").map(str::trim).filter(|segment| !segment.is_empty()).map(str::to_string).collect()
+}
+
+/// `#[test]`, `#[tokio::test]` and the like, or a `#[cfg(...)]` enabled by `test`
+fn is_test_attribute(text: &str) -> bool {
+    let inner: String = text
+        .trim_start_matches(['#', '!', '['])
+        .trim_end_matches(']')
+        .split_whitespace()
+        .collect();
+    let (path, args) = inner.split_once('(').unwrap_or((&inner, ""));
+    match path {
+        "cfg" => !args.contains("not(") && args.split(|c: char| !c.is_alphanumeric() && c != '_').any(|word| word == "test"),
+        _ => path == "test" || path.ends_with("::test"),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,6 +1461,532 @@ mod tests {
         assert!(assignments.iter().any(|c| c.var == "y" && c.val == 20));
 
         // Expect x > 5
-        assert!(conditions.iter().any(|c| c.var == "x" && c.op == ">" && c.val == 5));
+        assert!(conditions.iter().any(|c| matches!(c, Condition::Compare(c) if c.var == "x" && c.op == ">" && c.val == 5)));
+    }
+
+    #[test]
+    fn test_loop_match_and_let_else_constraints() {
+        let code = r#"
+fn f(x: i64, y: i64) {
+    while y < 10 {
+        a();
+    }
+    match x {
+        0 => b(),
+        n if y > 2 => c(),
+        1..=5 | 9 => d(),
+        _ => e(),
+    }
+    if let 3 = x {
+        g();
+    }
+    let 7 = y else {
+        h();
+        return;
+    };
+    k();
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let conditions = |extractor: &mut Extractor, line, column| format!("{:?}", extractor.extract_constraints(code, line, column).1);
+
+        assert!(conditions(&mut extractor, 3, 8).contains(r#"var: "y", op: "<", val: 10"#));
+        assert!(conditions(&mut extractor, 6, 13).contains(r#"var: "x", op: "==", val: 0"#));
+        // The guarded arm: its guard holds and the `0` arm didn't match
+        let guarded = extractor.extract_constraints(code, 7, 22).1;
+        assert_eq!(guarded.len(), 2);
+        assert!(matches!(&guarded[0], Condition::Not(_)));
+        assert!(matches!(&guarded[1], Condition::Compare(c) if c.var == "y" && c.op == ">" && c.val == 2));
+        // The guarded arm may not have matched, so only `0` is ruled out for the range arm
+        let ranged = extractor.extract_constraints(code, 8, 21).1;
+        assert!(matches!(&ranged[0], Condition::Or(..)));
+        assert_eq!(ranged.len(), 2);
+
+        assert!(conditions(&mut extractor, 12, 8).contains(r#"var: "x", op: "==", val: 3"#));
+        assert!(conditions(&mut extractor, 15, 8).starts_with("[Not(Compare("));
+        assert!(conditions(&mut extractor, 18, 4).contains(r#"var: "y", op: "==", val: 7"#));
+    }
+
+    #[test]
+    fn test_compound_conditions() {
+        let code = r#"
+fn f(x: i64, y: i64) {
+    if x > 5 && (y < 3 || !(x == 7)) {
+        a();
+    }
+    if ready() && x < 0 {
+        b();
+    }
+    if ready() || x < 0 {
+        c();
+    }
+    if !(x > 5 && call()) {
+        d();
+    }
+    if !(x > 5 || call()) {
+        e();
+    }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let (_, conditions) = extractor.extract_constraints(code, 3, 8);
+        assert_eq!(
+            format!("{:?}", conditions),
+            "[And(Compare(Constraint { var: \"x\", op: \">\", val: 5 }), Or(Compare(Constraint { var: \"y\", op: \"<\", val: 3 }), \
+             Not(Compare(Constraint { var: \"x\", op: \"==\", val: 7 }))))]"
+        );
+
+        // The call is unknown: dropped from a conjunction, fatal to a disjunction
+        let (_, conditions) = extractor.extract_constraints(code, 6, 8);
+        assert!(matches!(conditions.as_slice(), [Condition::Compare(c)] if c.var == "x" && c.op == "<"));
+        let (_, conditions) = extractor.extract_constraints(code, 9, 8);
+        assert!(conditions.is_empty());
+
+        // Under `!` it's the other way round: `!(x > 5 && call())` says nothing about `x`,
+        // while `!(x > 5 || call())` still means `x <= 5`
+        let (_, conditions) = extractor.extract_constraints(code, 12, 8);
+        assert!(conditions.is_empty());
+        let (_, conditions) = extractor.extract_constraints(code, 15, 8);
+        assert!(matches!(conditions.as_slice(), [Condition::Not(inner)] if matches!(&**inner, Condition::Compare(c) if c.op == ">")));
+    }
+
+    #[test]
+    fn test_ranges_with_unknown_bounds_are_not_negated() {
+        let code = r#"
+fn f(x: i64) {
+    match x {
+        0..=LIMIT => a(),
+        10.. => b(),
+        _ => c(),
+    }
+    let 0..=LIMIT = x else {
+        h();
+        return;
+    };
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        // `0..=LIMIT` isn't weakened to `x >= 0`, whose negation would rule out `x = 5000`
+        let (_, conditions) = extractor.extract_constraints(code, 5, 13);
+        assert_eq!(conditions.len(), 1);
+        assert!(matches!(&conditions[0], Condition::Not(inner) if matches!(&**inner, Condition::Compare(c) if c.op == ">=" && c.val == 10)));
+        let (_, conditions) = extractor.extract_constraints(code, 8, 8);
+        assert!(conditions.is_empty());
+    }
+
+    #[test]
+    fn test_is_conditional_at() {
+        let code = r#"
+fn f(x: i32) {
+    a();
+    if x > 0 {
+        b();
+    }
+    for _ in 0..x {
+        c();
+    }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        assert!(!extractor.is_conditional_at(code, 2, 4));
+        assert!(!extractor.is_conditional_at(code, 3, 7));
+        assert!(extractor.is_conditional_at(code, 4, 8));
+        assert!(extractor.is_conditional_at(code, 7, 8));
+    }
+
+    #[test]
+    fn test_data_flow_at() {
+        let code = r#"
+fn f(limit: u32) -> u32 {
+    let mut total = 0;
+    for i in 0..limit {
+        total += i;
+    }
+    log(total);
+    total
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let flow = extractor.data_flow_at(code, 6, 8).unwrap();
+        assert_eq!(flow.variable, "total");
+        assert_eq!(flow.declaration.statement, "let mut total = 0;");
+        let accesses: Vec<(usize, Access)> = flow.accesses.iter().map(|a| (a.line, a.access)).collect();
+        assert_eq!(accesses, vec![(4, Access::Write), (6, Access::Read), (7, Access::Read)]);
+
+        let flow = extractor.data_flow_at(code, 3, 17).unwrap();
+        assert_eq!(flow.variable, "limit");
+        assert_eq!(flow.declaration.line, 1);
+
+        // A function name is not a local variable
+        assert!(extractor.data_flow_at(code, 6, 4).is_none());
+    }
+
+    #[test]
+    fn test_use_declarations() {
+        let code = r#"
+use std::collections::{self, HashMap as Map, BTreeSet};
+use crate::graph::NodeId;
+use super::*;
+
+fn f() { use std::fmt; }
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let uses = extractor.use_declarations(code);
+        assert_eq!(uses.len(), 3);
+        assert_eq!(uses[0].names, vec!["collections", "Map", "BTreeSet"]);
+        assert_eq!(uses[1].names, vec!["NodeId"]);
+        assert_eq!(uses[1].text, "use crate::graph::NodeId;");
+        assert!(uses[2].glob);
+    }
+
+    #[test]
+    fn test_extract_signature() {
+        let code = r#"/// Merge two maps.
+pub fn merge<K, V>(a: Map<K, V>, b: Map<K, V>) -> Map<K, V>
+where
+    K: Ord,
+{
+    a.extend(b);
+}"#;
+        let mut extractor = Extractor::new().unwrap();
+        let signature = extractor.extract_signature(code).unwrap();
+        assert_eq!(signature.name.as_deref(), Some("merge"));
+        assert_eq!(signature.generics.as_deref(), Some("<K, V>"));
+        assert_eq!(signature.parameters.as_deref(), Some("(a: Map<K, V>, b: Map<K, V>)"));
+        assert_eq!(signature.return_type.as_deref(), Some("Map<K, V>"));
+        assert_eq!(signature.where_clause.as_deref(), Some("where\n    K: Ord,"));
+        assert!(signature.text.starts_with("pub fn merge<K, V>") && signature.text.ends_with("K: Ord,"));
+    }
+
+    #[test]
+    fn test_extract_interface() {
+        let code = r#"impl<T> Stack<T> {
+    const CAP: usize = 8;
+
+    pub fn push(&mut self, item: T) {
+        self.items.push(item);
+    }
+
+    fn pop(&mut self) -> Option<T> { self.items.pop() }
+}"#;
+        let mut extractor = Extractor::new().unwrap();
+        assert_eq!(
+            extractor.extract_interface(code).unwrap(),
+            "impl<T> Stack<T> {\n    const CAP: usize = 8;\n    pub fn push(&mut self, item: T);\n    fn pop(&mut self) -> Option<T>;\n}"
+        );
+        assert!(extractor.extract_interface("let x = 1;").is_none());
+    }
+
+    #[test]
+    fn test_update_file_reparses_incrementally() {
+        let file = Path::new("/src/lib.rs");
+        let before = "fn alpha() {}\n\nfn omega() {}\n";
+        let after = "fn alpha() {}\n\nfn beta() { alpha() }\n\nfn omega() {}\n";
+
+        let edit = input_edit(before, after);
+        assert_eq!((edit.start_byte, edit.old_end_byte, edit.new_end_byte), (18, 18, 41));
+        assert_eq!(edit.new_end_position, Point::new(4, 3));
+
+        let mut extractor = Extractor::new().unwrap();
+        extractor.update_file(file, before).unwrap();
+        let tree = extractor.update_file(file, after).unwrap();
+        let fresh = Extractor::new().unwrap().parser.parse(after, None).unwrap();
+        assert_eq!(tree.root_node().to_sexp(), fresh.root_node().to_sexp());
+
+        let names: Vec<String> = extractor.get_defined_symbols(after).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["alpha", "beta", "omega"]);
+    }
+
+    #[test]
+    fn test_get_call_sites() {
+        let code = r#"fn run(&self) {
+    let config = Config::load(path);
+    self.config.apply(helper(1), parse::<u32>("2"));
+    let f = |x| x; f(3);
+    println!("{}", render());
+}"#;
+        let mut extractor = Extractor::new().unwrap();
+        let calls = extractor.get_call_sites(code);
+        let callees: Vec<(&str, Option<&str>)> =
+            calls.iter().map(|call| (call.callee.as_str(), call.receiver.as_deref())).collect();
+        assert_eq!(
+            callees,
+            vec![
+                ("Config::load", None),
+                ("apply", Some("self.config")),
+                ("helper", None),
+                ("parse", None),
+                ("f", None),
+            ]
+        );
+        assert_eq!(calls[0].position, (1, 25));
+        assert_eq!(&code[calls[0].arguments.clone()], "(path)");
+    }
+
+    #[test]
+    fn test_get_imports() {
+        let code = r#"
+use std::collections::{self, HashMap as Map, btree_map::{BTreeMap, Entry}};
+pub(crate) use crate::graph::NodeId;
+use super::*;
+use std::io::Write as _;
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let imports = extractor.get_imports(code);
+        let paths: Vec<String> = imports.iter().map(|import| import.path.join("::")).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "std::collections",
+                "std::collections::HashMap",
+                "std::collections::btree_map::BTreeMap",
+                "std::collections::btree_map::Entry",
+                "crate::graph::NodeId",
+                "super",
+                "std::io::Write",
+            ]
+        );
+        assert_eq!(imports[1].name(), Some("Map"));
+        assert_eq!(imports[4].visibility.as_deref(), Some("pub(crate)"));
+        assert_eq!(imports[4].line, 2);
+        assert!(imports[5].glob && imports[5].name().is_none());
+        assert_eq!(imports[6].alias.as_deref(), Some("_"));
+    }
+
+    #[test]
+    fn test_is_trait_method_at() {
+        let code = r#"
+trait Shape {
+    fn area(&self) -> f64;
+    fn scaled(&self) -> f64 { fn helper() {} self.area() }
+}
+impl Shape for Square {
+    fn area(&self) -> f64 { 1.0 }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        assert!(extractor.is_trait_method_at(code, 2, 7));
+        assert!(extractor.is_trait_method_at(code, 3, 7));
+        assert!(!extractor.is_trait_method_at(code, 3, 33));
+        assert!(!extractor.is_trait_method_at(code, 6, 7));
+    }
+
+    #[test]
+    fn test_is_test_code_at() {
+        let code = r#"
+fn area() -> f64 { 1.0 }
+#[cfg(test)]
+mod tests {
+    fn helper() { area(); }
+}
+#[tokio::test]
+/// Async check
+async fn checks_area() { area(); }
+#[cfg(not(test))]
+fn release_only() { area(); }
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        assert!(!extractor.is_test_code_at(code, 1, 19));
+        assert!(extractor.is_test_code_at(code, 4, 18));
+        assert!(extractor.is_test_code_at(code, 8, 25));
+        assert!(!extractor.is_test_code_at(code, 10, 20));
+        assert_eq!(extractor.attributes_at(code, 4, 18), vec!["#[cfg(test)]"]);
+    }
+
+    #[test]
+    fn test_extract_enclosing_function() {
+        let code = r#"
+use crate::helper;
+
+mod inner {
+    fn caller() {
+        let x = helper();
+    }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let function = extractor.extract_enclosing_function(code, 5, 16).unwrap();
+        assert!(function.starts_with("fn caller()"));
+        assert!(function.ends_with('}'));
+        assert!(extractor.extract_enclosing_function(code, 1, 12).is_none());
+    }
+
+    #[test]
+    fn test_defined_symbol_spans() {
+        let code = "use std::fmt;\n\npub fn parse(input: &str) {\n    todo!()\n}\n";
+        let mut extractor = Extractor::new().unwrap();
+        let symbols = extractor.get_defined_symbols(code);
+        assert_eq!(symbols.len(), 1);
+        let parse = &symbols[0];
+        assert_eq!((parse.line, parse.column, parse.end_line, parse.end_column), (2, 0, 4, 1));
+        assert_eq!(parse.name_position, (2, 7));
+        assert_eq!(&code[parse.byte_range.clone()], parse.code);
+    }
+
+    #[test]
+    fn test_defined_symbols_include_impl_members() {
+        let code = r#"
+struct Parser<'a> { input: &'a str }
+
+impl<'a> Parser<'a> {
+    const LIMIT: usize = 8;
+    fn next(&mut self) {}
+}
+
+trait Visit {
+    type Output;
+    fn visit(&self);
+}
+
+impl Visit for crate::ast::Node {
+    type Output = ();
+    fn visit(&self) {}
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let names: Vec<String> = extractor.get_defined_symbols(code).into_iter().map(|s| s.name).collect();
+        assert_eq!(
+            names,
+            vec!["Parser", "Parser::LIMIT", "Parser::next", "Visit", "Visit::Output", "Visit::visit", "Node::Output", "Node::visit"]
+        );
+    }
+
+    #[test]
+    fn test_defined_symbol_docs() {
+        let code = r#"
+/// Parse a header.
+/// Fails on empty input.
+#[inline]
+fn header() {}
+
+/**
+ * Parse a body.
+ *
+ * Trailing data is ignored.
+ */
+fn body() {}
+
+//// Not a doc comment
+fn plain() {}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let docs: Vec<Option<String>> = extractor.get_defined_symbols(code).into_iter().map(|s| s.docs).collect();
+        assert_eq!(
+            docs,
+            vec![
+                Some("Parse a header.\nFails on empty input.".to_string()),
+                Some("Parse a body.\n\nTrailing data is ignored.".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_defined_symbol_attributes() {
+        let code = r#"
+#[derive(Debug, Clone)]
+/// A point.
+#[cfg(feature = "geo")]
+struct Point;
+
+#[cfg(test)]
+mod tests {}
+
+#[tokio::test]
+async fn smoke() {}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let symbols = extractor.get_defined_symbols(code);
+        assert_eq!(symbols[0].attributes, vec!["#[derive(Debug, Clone)]", "#[cfg(feature = \"geo\")]"]);
+        assert_eq!(symbols[0].derives(), vec!["Debug", "Clone"]);
+        assert!(!symbols[1].is_test());
+        assert!(symbols[2].is_test());
+    }
+
+    #[test]
+    fn test_defined_symbols_in_nested_modules() {
+        let code = r#"
+mod net {
+    pub fn connect() {}
+
+    pub mod http {
+        pub struct Client;
+        impl Client {
+            pub fn get(&self) {}
+        }
+    }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let names: Vec<String> = extractor.get_defined_symbols(code).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["net", "net::connect", "net::http", "net::http::Client", "net::http::Client::get"]);
+    }
+
+    #[test]
+    fn test_test_functions() {
+        let code = r#"
+#[test]
+fn top() {}
+
+#[cfg(test)]
+mod tests {
+    fn helper() {}
+
+    #[tokio::test]
+    async fn nested() {
+        helper();
+    }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let tests = extractor.test_functions(code);
+        let found: Vec<(&str, Vec<String>)> = tests.iter().map(|t| (t.name.as_str(), t.modules.clone())).collect();
+        assert_eq!(found, vec![("top", vec![]), ("nested", vec!["tests".to_string()])]);
+        assert_eq!(tests[1].name_position, (9, 13));
+    }
+
+    #[test]
+    fn test_describe_item_at() {
+        let code = r#"
+struct Counter;
+
+impl Counter {
+    /// Bump the counter.
+    /// Saturates at max.
+    #[inline]
+    pub fn bump(&mut self, by: u32) -> u32 {
+        by
+    }
+}
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        let details = extractor.describe_item_at(code, 8, 8).unwrap();
+        assert_eq!(details.name.as_deref(), Some("bump"));
+        assert_eq!(details.kind, SymbolKind::Method);
+        assert_eq!(details.signature, "pub fn bump(&mut self, by: u32) -> u32");
+        assert_eq!(details.docs.as_deref(), Some("Bump the counter.\nSaturates at max."));
+
+        let details = extractor.describe_item_at(code, 1, 0).unwrap();
+        assert_eq!(details.kind, SymbolKind::Struct);
+        assert_eq!(details.signature, "struct Counter");
+    }
+
+    #[test]
+    fn test_macro_invocation_at() {
+        let code = r#"
+make_getter!(value, i32);
+
+fn plain() {
+    println!("hi");
+}
+"#;
+
+        let mut extractor = Extractor::new().unwrap();
+
+        // Line 1 is a top-level macro call; the name starts at column 0
+        assert_eq!(extractor.macro_invocation_at(code, 1), Some((1, 0)));
+
+        // A function item is not a macro invocation, nor is a macro call inside its body
+        assert_eq!(extractor.macro_invocation_at(code, 3), None);
     }
 }
@@ -1,6 +1,8 @@
-use crate::graph::{CodeNode, DependencyGraph, Edge, EdgeType, NodeId};
+use crate::graph::{CodeNode, DependencyGraph, Edge, EdgeType, NodeId, Provenance};
 use crate::extractor::{Extractor, SymbolInfo};
 use crate::llm_client::LlmClient;
+use crate::rules::ExtractionRules;
+use crate::position::{self, PositionEncoding};
 use anyhow::{Result, anyhow};
 use std::path::{Path, PathBuf};
 use std::fs;
@@ -15,6 +17,7 @@ struct LlmAnalysis {
     types: Vec<String>,
 }
 
+#[derive(Clone)]
 pub struct LocatedSymbol {
     pub info: SymbolInfo,
     pub file: PathBuf,
@@ -25,6 +28,8 @@ pub struct FuzzySlicer {
     llm: LlmClient,
     symbol_cache: HashMap<String, Vec<LocatedSymbol>>, // Name -> [Locations]
     workspace_scanned: bool,
+    /// Encoding of node columns, matched to the server's so nodes line up with LSP ones
+    position_encoding: PositionEncoding,
 }
 
 impl FuzzySlicer {
@@ -34,20 +39,45 @@ impl FuzzySlicer {
             llm: LlmClient::new()?,
             symbol_cache: HashMap::new(),
             workspace_scanned: false,
+            position_encoding: PositionEncoding::default(),
         })
     }
 
+    /// Find symbols by `rules` instead of the built-in ones
+    pub fn set_extraction_rules(&mut self, rules: ExtractionRules) {
+        self.extractor.set_rules(rules);
+        // Symbols found under the old rules
+        self.symbol_cache.clear();
+        self.workspace_scanned = false;
+    }
+
+    /// Count node columns in `encoding`, as the language server does
+    pub fn set_position_encoding(&mut self, encoding: PositionEncoding) {
+        self.position_encoding = encoding;
+    }
+
     pub async fn slice(
         &mut self,
         target_file: PathBuf,
         target_line: u32,
         target_col: u32,
+    ) -> Result<DependencyGraph> {
+        let content = fs::read_to_string(&target_file)?;
+        self.slice_source(target_file, &content, target_line, target_col).await
+    }
+
+    /// Slice using `content` as the text of `target_file` (e.g. an unsaved overlay)
+    pub async fn slice_source(
+        &mut self,
+        target_file: PathBuf,
+        content: &str,
+        target_line: u32,
+        target_col: u32,
     ) -> Result<DependencyGraph> {
         let mut graph = DependencyGraph::new();
 
-        // 1. Read and extract target
-        let content = fs::read_to_string(&target_file)?;
-        let target_code = if let Some(code) = self.extractor.extract_block(&content, target_line as usize, 0) {
+        // 1. Extract target
+        let target_code = if let Some(code) = self.extractor.extract_block(content, target_line as usize, 0) {
             code
         } else {
             // Fallback to line if block extraction fails
@@ -65,36 +95,55 @@ impl FuzzySlicer {
             column: target_col,
         };
 
-        graph.add_node(CodeNode {
-            id: target_id.clone(),
-            code: target_code.clone(),
-            node_type: "target".to_string(),
-        });
+        graph.add_node(CodeNode::new(target_id.clone(), target_code.clone(), "target").with_provenance(Provenance::root("slice target")));
 
         // 2. Scan workspace if needed
         if !self.workspace_scanned {
             let root = self.find_workspace_root(&target_file).unwrap_or_else(|| PathBuf::from("."));
-            eprintln!("FuzzySlicer: Scanning workspace at {}", root.display());
-            self.scan_workspace(&root)?;
-            self.workspace_scanned = true;
+            self.prescan(&root)?;
         }
 
-        // 3. Ask LLM for dependencies
-        let analysis = self.analyze_dependencies(&target_code).await?;
+        // 3. Calls the parser can see, matched by exact name only
+        for call in self.extractor.get_call_sites(&target_code) {
+            if self.symbol_cache.contains_key(&call.callee) {
+                self.add_dependency(&mut graph, &target_id, &call.callee, EdgeType::Calls, "call site")?;
+            }
+        }
+
+        // 4. Ask LLM for dependencies; without it the call sites still stand
+        let analysis = match self.analyze_dependencies(&target_code).await {
+            Ok(analysis) => analysis,
+            Err(e) if graph.nodes.len() > 1 => {
+                eprintln!("FuzzySlicer: LLM analysis failed ({}); keeping {} call sites", e, graph.nodes.len() - 1);
+                return Ok(graph);
+            }
+            Err(e) => return Err(e),
+        };
         eprintln!("FuzzySlicer: LLM identified dependencies: {:?}", analysis);
 
-        // 4. Resolve dependencies
+        // 5. Resolve dependencies
         for call_name in analysis.calls {
-            self.add_dependency(&mut graph, &target_id, &call_name, EdgeType::Calls)?;
+            self.add_dependency(&mut graph, &target_id, &call_name, EdgeType::Calls, "LLM guess")?;
         }
 
         for type_name in analysis.types {
-            self.add_dependency(&mut graph, &target_id, &type_name, EdgeType::References)?;
+            self.add_dependency(&mut graph, &target_id, &type_name, EdgeType::References, "LLM guess")?;
         }
 
         Ok(graph)
     }
 
+    /// Index the symbols under `root` now rather than on the first slice
+    pub fn prescan(&mut self, root: &Path) -> Result<()> {
+        if self.workspace_scanned {
+            return Ok(());
+        }
+        eprintln!("FuzzySlicer: Scanning workspace at {}", root.display());
+        self.scan_workspace(root)?;
+        self.workspace_scanned = true;
+        Ok(())
+    }
+
     fn find_workspace_root(&self, start: &Path) -> Option<PathBuf> {
         let mut current = start.to_path_buf();
         if current.is_file() {
@@ -113,7 +162,8 @@ impl FuzzySlicer {
     }
 
     fn scan_workspace(&mut self, root: &Path) -> Result<()> {
-        for entry in WalkDir::new(root).into_iter().filter_map(|e| e.ok()) {
+        // Sorted so the "first match" heuristic picks the same definition every run
+        for entry in WalkDir::new(root).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
             let path = entry.path();
             if path.extension().and_then(|s| s.to_str()) == Some("rs")
                 && let Ok(content) = fs::read_to_string(path) {
@@ -124,6 +174,12 @@ impl FuzzySlicer {
                             file: path.to_path_buf(),
                         };
 
+                        // Qualified symbols also answer to their bare name, e.g. `get` for `http::Client::get`
+                        if let Some((_, member)) = located.info.name.rsplit_once("::") {
+                            self.symbol_cache.entry(member.to_string())
+                                .or_default()
+                                .push(located.clone());
+                        }
                         self.symbol_cache.entry(located.info.name.clone())
                             .or_default()
                             .push(located);
@@ -168,42 +224,41 @@ impl FuzzySlicer {
         graph: &mut DependencyGraph,
         target_id: &NodeId,
         name: &str,
-        edge_type: EdgeType
+        edge_type: EdgeType,
+        found_by: &str,
     ) -> Result<()> {
-        // Look up name in cache
-        if let Some(definitions) = self.symbol_cache.get(name) {
+        // Look up name in cache, falling back to its last segment for paths like
+        // `config::load` or method calls like `self.next`
+        let definitions = self.symbol_cache.get(name).or_else(|| {
+            let last = name.rsplit([':', '.']).next().unwrap_or(name);
+            self.symbol_cache.get(last)
+        });
+        if let Some(definitions) = definitions {
             // Heuristic: take the first match. Ideally we'd disambiguate based on imports/context.
             // But this is "Fuzzy" slicing.
             if let Some(def) = definitions.first() {
+                // At the name, like a definition the server returns, so the two merge
                 let def_id = NodeId {
                     file: def.file.clone(),
-                    line: def.info.line as u32,
-                    column: 0, // We don't have column in SymbolInfo yet, default to 0
+                    line: def.info.name_position.0 as u32,
+                    column: name_column(&def.info, self.position_encoding),
                 };
 
-                // Add node if not exists
-                if !graph.nodes.contains_key(&def_id) {
-                    graph.add_node(CodeNode {
-                        id: def_id.clone(),
-                        code: def.info.code.clone(),
-                        node_type: def.info.kind.clone(),
-                    });
-                }
-
                 // Add edge
                 let edge = match edge_type {
-                    EdgeType::Calls => Edge {
-                        from: target_id.clone(),
-                        to: def_id,
-                        edge_type: EdgeType::Calls,
-                    },
-                    _ => Edge {
-                        from: target_id.clone(),
-                        to: def_id,
-                        edge_type: EdgeType::Defines, // Or References
-                    }
+                    EdgeType::Calls => Edge::new(target_id.clone(), def_id.clone(), EdgeType::Calls),
+                    _ => Edge::new(target_id.clone(), def_id.clone(), EdgeType::Defines), // Or References
                 };
 
+                // Add node if not exists
+                if !graph.nodes.contains_key(&def_id) {
+                    let operation = format!("{} `{}`, looked up by name", found_by, name);
+                    let provenance = Provenance::via(&operation, target_id, edge.edge_type.clone());
+                    let mut node = CodeNode::new(def_id, def.info.code.clone(), &def.info.kind).with_provenance(provenance);
+                    node.docs = def.info.docs.clone();
+                    graph.add_node(node);
+                }
+
                 graph.add_edge(edge);
             }
         }
@@ -211,3 +266,14 @@ impl FuzzySlicer {
         Ok(())
     }
 }
+
+/// Column of the symbol's name in `encoding`, worked out from its code
+fn name_column(info: &SymbolInfo, encoding: PositionEncoding) -> u32 {
+    let (name_line, name_byte_col) = info.name_position;
+    let Some(text) = info.code.lines().nth(name_line - info.line) else {
+        return name_byte_col as u32;
+    };
+    // The code's first line starts at `column`, after indentation only
+    let lead = if name_line == info.line { info.column } else { 0 };
+    lead as u32 + position::from_byte_column(text, name_byte_col - lead, encoding)
+}
@@ -1,28 +1,329 @@
+use anyhow::{Context, Result, anyhow};
+use lsp_types::Range;
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+/// Which way to follow edges during traversal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    /// From a node to what it depends on
+    Forward,
+    /// From a node to what depends on it
+    Backward,
+    Both,
+}
+
+/// Identifies files written by `DependencyGraph::save`
+const GRAPH_FORMAT: &str = "graphslice-graph";
+
+/// Bumped on incompatible changes to the on-disk graph layout
+pub const GRAPH_FORMAT_VERSION: u32 = 1;
+
+/// Ordered by file, then line, then column
+#[derive(Debug, Clone, Hash, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct NodeId {
     pub file: PathBuf,
     pub line: u32,
     pub column: u32,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// Column-0 position on `line` of `/src/lib.rs`, for unit tests
+#[cfg(test)]
+pub(crate) fn test_id(line: u32) -> NodeId {
+    NodeId { file: PathBuf::from("/src/lib.rs"), line, column: 0 }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
 pub enum EdgeType {
     Defines,     // A defines B
     Calls,       // A calls B
     Reads,       // A reads B
     Writes,      // A writes to B
     References,  // Generic reference
+    Implements,  // Impl A implements trait B
+    Contains,    // Module/impl A contains item B
+    Imports,     // A brings B into scope with `use`
+    TypeOf,      // Expression A has type B
+}
+
+impl EdgeType {
+    /// Lowercase name used in exports and rendered context
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeType::Defines => "defines",
+            EdgeType::Calls => "calls",
+            EdgeType::Reads => "reads",
+            EdgeType::Writes => "writes",
+            EdgeType::References => "references",
+            EdgeType::Implements => "implements",
+            EdgeType::Contains => "contains",
+            EdgeType::Imports => "imports",
+            EdgeType::TypeOf => "type_of",
+        }
+    }
+
+    /// Parse the name produced by `as_str`
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "defines" => EdgeType::Defines,
+            "calls" => EdgeType::Calls,
+            "reads" => EdgeType::Reads,
+            "writes" => EdgeType::Writes,
+            "references" => EdgeType::References,
+            "implements" => EdgeType::Implements,
+            "contains" => EdgeType::Contains,
+            "imports" => EdgeType::Imports,
+            "type_of" => EdgeType::TypeOf,
+            _ => return None,
+        })
+    }
+
+    /// True for edges describing code layout rather than a dependency
+    /// (a module containing an item does not depend on it)
+    pub fn is_structural(&self) -> bool {
+        matches!(self, EdgeType::Contains)
+    }
+}
+
+/// Kind of Rust item a node's code belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SymbolKind {
+    Function,
+    Method,
+    Struct,
+    Enum,
+    Union,
+    Trait,
+    Impl,
+    Module,
+    Macro,
+    Const,
+    Static,
+    TypeAlias,
+}
+
+/// Canonical identity of the item a node belongs to, independent of which
+/// position inside it (definition start, name, call site) a request returned
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SymbolId {
+    pub file: PathBuf,
+    pub name: String,
+    pub kind: SymbolKind,
+    /// First line of the item, telling apart same-named items such as two `new` methods
+    pub line: u32,
+}
+
+/// Where a lazily loaded node's code lives: a byte range of its file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeLocator {
+    pub start_byte: usize,
+    pub end_byte: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CodeNode {
     pub id: NodeId,
+    /// Empty for lazy nodes; read code through `text`
     pub code: String,
-    pub node_type: String, // "function", "struct", "variable", etc.
+    pub node_type: String, // "target", "reference", "test", "definition", "call", or an item kind
+    /// Owning crate and module, e.g. "graphslice::slicer"
+    #[serde(default)]
+    pub module_path: Option<String>,
+    /// Name of the enclosing item, e.g. "build_graph"
+    #[serde(default)]
+    pub symbol_name: Option<String>,
+    #[serde(default)]
+    pub symbol_kind: Option<SymbolKind>,
+    /// Item header without its body, e.g. "pub fn new() -> Result<Self>"
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Outer doc comment (`///` or `/** */`) with the markers stripped
+    #[serde(default)]
+    pub docs: Option<String>,
+    /// Structural centrality in 0..=1 (1 = most central), set by `score_importance`
+    #[serde(default)]
+    pub importance: f64,
+    /// Relevance to the slice's targets in 0..=1 (1 = a target), set by `score_relevance`
+    #[serde(default)]
+    pub relevance: f64,
+    /// Set once the enclosing item is known; nodes sharing one are merged on insert
+    #[serde(default)]
+    pub symbol_id: Option<SymbolId>,
+    /// Free-form annotations from downstream tools, e.g. "review" -> "suspicious"
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Other positions merged into this node, e.g. several references inside one function
+    #[serde(default)]
+    pub positions: Vec<NodeId>,
+    /// Set on lazy nodes, whose code is read from `id.file` on demand
+    #[serde(default)]
+    pub locator: Option<CodeLocator>,
+    /// `use` declarations of the node's file that its code relies on
+    #[serde(default)]
+    pub imports: Vec<String>,
+    /// Hex sha256 of `code`; call `rehash` after editing `code`
+    #[serde(default)]
+    pub content_hash: String,
+    /// How the slicer found this node; see `DependencyGraph::explain`
+    #[serde(default)]
+    pub provenance: Option<Provenance>,
+}
+
+/// The step that brought a node into a slice
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// What found the node, e.g. "textDocument/references" or "LLM guess"
+    pub operation: String,
+    /// Node being explored when this one was found; `None` for targets
+    pub parent: Option<NodeId>,
+    /// Edge linking the node to `parent`
+    pub edge_type: Option<EdgeType>,
+}
+
+impl Provenance {
+    /// A node found without exploring another one, like a target
+    pub fn root(operation: &str) -> Self {
+        Self { operation: operation.to_string(), parent: None, edge_type: None }
+    }
+
+    /// A node found by `operation` while exploring `parent`, linked to it by `edge_type`
+    pub fn via(operation: &str, parent: &NodeId, edge_type: EdgeType) -> Self {
+        Self {
+            operation: operation.to_string(),
+            parent: Some(parent.clone()),
+            edge_type: Some(edge_type),
+        }
+    }
+}
+
+impl CodeNode {
+    pub fn new(id: NodeId, code: String, node_type: &str) -> Self {
+        let content_hash = content_hash(&code);
+        Self {
+            id,
+            code,
+            node_type: node_type.to_string(),
+            module_path: None,
+            symbol_name: None,
+            symbol_kind: None,
+            signature: None,
+            docs: None,
+            symbol_id: None,
+            importance: 0.0,
+            relevance: 0.0,
+            tags: BTreeMap::new(),
+            positions: Vec::new(),
+            locator: None,
+            imports: Vec::new(),
+            content_hash,
+            provenance: None,
+        }
+    }
+
+    pub fn with_provenance(mut self, provenance: Provenance) -> Self {
+        self.provenance = Some(provenance);
+        self
+    }
+
+    /// A node that stores only where its code is instead of the code itself.
+    /// `code` is the current text of that range; it is hashed but not kept.
+    pub fn lazy(id: NodeId, locator: CodeLocator, code: &str, node_type: &str) -> Self {
+        let mut node = Self::new(id, String::new(), node_type);
+        node.content_hash = content_hash(code);
+        node.locator = Some(locator);
+        node
+    }
+
+    pub fn is_lazy(&self) -> bool {
+        self.locator.is_some() && self.code.is_empty()
+    }
+
+    /// The node's code, read from disk for lazy nodes
+    pub fn text(&self) -> Cow<'_, str> {
+        let Some(locator) = self.locator.filter(|_| self.code.is_empty()) else {
+            return Cow::Borrowed(&self.code);
+        };
+        match fs::read(&self.id.file) {
+            Ok(bytes) if locator.start_byte <= locator.end_byte && locator.end_byte <= bytes.len() => {
+                Cow::Owned(String::from_utf8_lossy(&bytes[locator.start_byte..locator.end_byte]).into_owned())
+            }
+            _ => Cow::Owned(format!(
+                "// <code unavailable: {} bytes {}..{}>",
+                self.id.file.display(),
+                locator.start_byte,
+                locator.end_byte
+            )),
+        }
+    }
+
+    /// Length of the code in bytes, without reading lazy nodes from disk
+    pub fn code_len(&self) -> usize {
+        match self.locator {
+            Some(locator) if self.code.is_empty() => locator.end_byte.saturating_sub(locator.start_byte),
+            _ => self.code.len(),
+        }
+    }
+
+    /// Read a lazy node's code into `code`, making it a regular node
+    pub fn load_code(&mut self) {
+        if self.is_lazy() {
+            self.code = self.text().into_owned();
+            self.locator = None;
+        }
+    }
+
+    /// Recompute `content_hash` from the node's code
+    pub fn rehash(&mut self) {
+        self.content_hash = content_hash(&self.text());
+    }
+
+    /// Fill in symbol details this node lacks from another view of the same item,
+    /// remembering the other node's positions. Reference lines are accumulated.
+    fn absorb(&mut self, other: CodeNode) {
+        if other.id != self.id && !self.positions.contains(&other.id) {
+            self.positions.push(other.id.clone());
+        }
+        for position in other.positions {
+            if position != self.id && !self.positions.contains(&position) {
+                self.positions.push(position);
+            }
+        }
+        self.positions.sort();
+
+        if self.node_type == "reference" && other.node_type == "reference" && !self.code.contains(&other.code) {
+            self.code = format!("{}\n{}", self.code, other.code);
+            self.rehash();
+        }
+
+        for (key, value) in other.tags {
+            self.tags.entry(key).or_insert(value);
+        }
+        for import in other.imports {
+            if !self.imports.contains(&import) {
+                self.imports.push(import);
+            }
+        }
+        self.module_path = self.module_path.take().or(other.module_path);
+        self.symbol_name = self.symbol_name.take().or(other.symbol_name);
+        self.symbol_kind = self.symbol_kind.or(other.symbol_kind);
+        self.signature = self.signature.take().or(other.signature);
+        self.docs = self.docs.take().or(other.docs);
+        self.symbol_id = self.symbol_id.take().or(other.symbol_id);
+        self.provenance = self.provenance.take().or(other.provenance);
+    }
+}
+
+/// Hex sha256 of `text`
+pub(crate) fn content_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -30,12 +331,263 @@ pub struct Edge {
     pub from: NodeId,
     pub to: NodeId,
     pub edge_type: EdgeType,
+    #[serde(default)]
+    pub metadata: EdgeMetadata,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+impl Edge {
+    pub fn new(from: NodeId, to: NodeId, edge_type: EdgeType) -> Self {
+        Self {
+            from,
+            to,
+            edge_type,
+            metadata: EdgeMetadata::default(),
+        }
+    }
+
+    pub fn with_metadata(mut self, metadata: EdgeMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// Extra detail about where and how an edge occurs in the source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdgeMetadata {
+    /// Ranges inside `from` where the relation occurs (call sites for `Calls`)
+    pub sites: Vec<Range>,
+    /// How many times `from` uses `to`
+    pub occurrences: u32,
+    /// True if every site sits under an `if`, `match` arm or loop body
+    pub conditional: bool,
+    /// Proposed by the fuzzy (LLM) slicer and not confirmed by the language server
+    pub inferred: bool,
+    /// Closes a call cycle: `to` is `from` or already calls back into it (recursion)
+    pub cycle: bool,
+}
+
+impl EdgeMetadata {
+    /// Fold in another occurrence of the same edge. Sites are unioned; the edge
+    /// stays conditional (or inferred) only if both are, and is a cycle if either is.
+    /// With `count`, occurrences add up.
+    fn absorb(&mut self, other: EdgeMetadata, count: bool) {
+        for site in other.sites {
+            if !self.sites.contains(&site) {
+                self.sites.push(site);
+            }
+        }
+        self.occurrences = if count {
+            self.occurrences + other.occurrences
+        } else {
+            self.occurrences.max(other.occurrences)
+        };
+        self.conditional &= other.conditional;
+        self.inferred &= other.inferred;
+        self.cycle |= other.cycle;
+    }
+}
+
+impl Default for EdgeMetadata {
+    fn default() -> Self {
+        Self {
+            sites: Vec::new(),
+            occurrences: 1,
+            conditional: false,
+            inferred: false,
+            cycle: false,
+        }
+    }
+}
+
+/// On-disk layout: nodes are stored as a list because JSON map keys must be strings
+#[derive(Serialize, Deserialize)]
+struct GraphFile {
+    format: String,
+    version: u32,
+    nodes: Vec<CodeNode>,
+    edges: Vec<Edge>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    truncated: Option<Vec<NodeId>>,
+}
+
+/// Small integer standing for a `NodeId` in a graph's `NodeInterner`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NodeHandle(u32);
+
+impl NodeHandle {
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Arena of the node ids a graph's indices refer to, so each index entry is
+/// a `NodeHandle` rather than another copy of the id's path
+#[derive(Debug, Default)]
+struct NodeInterner {
+    /// Shared with `handles`, so each id is stored once
+    ids: Vec<Arc<NodeId>>,
+    handles: HashMap<Arc<NodeId>, NodeHandle>,
+}
+
+impl NodeInterner {
+    fn intern(&mut self, id: &NodeId) -> NodeHandle {
+        if let Some(&handle) = self.handles.get(id) {
+            return handle;
+        }
+        let handle = NodeHandle(self.ids.len() as u32);
+        let id = Arc::new(id.clone());
+        self.ids.push(id.clone());
+        self.handles.insert(id, handle);
+        handle
+    }
+
+    fn get(&self, id: &NodeId) -> Option<NodeHandle> {
+        self.handles.get(id).copied()
+    }
+
+    fn id(&self, handle: NodeHandle) -> &NodeId {
+        &self.ids[handle.index()]
+    }
+
+    fn clear(&mut self) {
+        self.ids.clear();
+        self.handles.clear();
+    }
+}
+
+/// Record edge `index` in the adjacency list of `handle`, growing `lists` as needed
+fn push_adjacent(lists: &mut Vec<Vec<usize>>, handle: NodeHandle, index: usize) {
+    if lists.len() <= handle.index() {
+        lists.resize_with(handle.index() + 1, Vec::new);
+    }
+    lists[handle.index()].push(index);
+}
+
+/// A graph's edges, read and edited like the `Vec` it derefs to. Any mutable access
+/// marks the graph's adjacency index stale, so it is rebuilt before it is relied on.
+#[derive(Default)]
+pub struct EdgeList {
+    items: Vec<Edge>,
+    /// Set on mutable access from outside the graph; cleared by reindexing
+    dirty: bool,
+}
+
+impl Deref for EdgeList {
+    type Target = Vec<Edge>;
+
+    fn deref(&self) -> &Vec<Edge> {
+        &self.items
+    }
+}
+
+impl DerefMut for EdgeList {
+    fn deref_mut(&mut self) -> &mut Vec<Edge> {
+        self.dirty = true;
+        &mut self.items
+    }
+}
+
+impl fmt::Debug for EdgeList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.items.fmt(f)
+    }
+}
+
+impl PartialEq for EdgeList {
+    fn eq(&self, other: &Self) -> bool {
+        self.items == other.items
+    }
+}
+
+impl From<Vec<Edge>> for EdgeList {
+    fn from(items: Vec<Edge>) -> Self {
+        Self { items, dirty: true }
+    }
+}
+
+impl<'a> IntoIterator for &'a EdgeList {
+    type Item = &'a Edge;
+    type IntoIter = std::slice::Iter<'a, Edge>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut EdgeList {
+    type Item = &'a mut Edge;
+    type IntoIter = std::slice::IterMut<'a, Edge>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+/// Edges should be added through `add_edge`, which deduplicates them, and nodes
+/// through `add_node`, which keeps the file index in sync. Editing `edges` directly
+/// is tracked; after editing `nodes` directly, call `reindex`. Likewise, only
+/// `add_node` deduplicates nodes by `SymbolId`.
+#[derive(Debug, Default)]
 pub struct DependencyGraph {
     pub nodes: HashMap<NodeId, CodeNode>,
-    pub edges: Vec<Edge>,
+    pub edges: EdgeList,
+    /// Set when slicing stopped early, e.g. on `SlicerConfig::slice_timeout`: the nodes
+    /// whose neighbours were still to be explored. `None` for a complete slice.
+    pub truncated: Option<Vec<NodeId>>,
+    /// Ids referred to by the indices below
+    interner: NodeInterner,
+    /// Node handle -> indices into `edges` leaving it
+    outgoing: Vec<Vec<usize>>,
+    /// Node handle -> indices into `edges` entering it
+    incoming: Vec<Vec<usize>>,
+    /// Symbol -> the node that represents it
+    symbols: HashMap<SymbolId, NodeHandle>,
+    /// Positions merged into another node -> that node
+    aliases: HashMap<NodeHandle, NodeHandle>,
+    /// File -> nodes in it
+    files: HashMap<PathBuf, HashSet<NodeHandle>>,
+    /// Whether re-adding an existing edge bumps its `occurrences`
+    count_multiplicity: bool,
+}
+
+/// Serialized form of a `DependencyGraph`, without its indices. Nodes are a
+/// sorted list: `NodeId` can't be a JSON map key, and map order varies run to run.
+#[derive(Serialize)]
+struct GraphParts<'a> {
+    nodes: Vec<&'a CodeNode>,
+    edges: Vec<Edge>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncated: Option<&'a Vec<NodeId>>,
+}
+
+/// Owned counterpart of `GraphParts`
+#[derive(Deserialize)]
+struct OwnedGraphParts {
+    nodes: Vec<CodeNode>,
+    edges: Vec<Edge>,
+    #[serde(default)]
+    truncated: Option<Vec<NodeId>>,
+}
+
+impl Serialize for DependencyGraph {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        GraphParts {
+            nodes: self.sorted_nodes(),
+            edges: self.sorted_edges(),
+            truncated: self.truncated.as_ref(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DependencyGraph {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let parts = OwnedGraphParts::deserialize(deserializer)?;
+        let mut graph = Self::from_parts(parts.nodes, parts.edges);
+        graph.truncated = parts.truncated;
+        Ok(graph)
+    }
 }
 
 impl DependencyGraph {
@@ -43,45 +595,983 @@ impl DependencyGraph {
         Self::default()
     }
 
+    /// Insert a node. If another node already stands for the same `SymbolId`,
+    /// the two are merged and `node.id` becomes an alias of the existing one.
     pub fn add_node(&mut self, node: CodeNode) {
+        if let Some(symbol) = &node.symbol_id
+            && let Some(&canonical) = self.symbols.get(symbol)
+            && self.interner.id(canonical) != &node.id
+            && let Some(existing) = self.nodes.get_mut(self.interner.id(canonical))
+        {
+            if node.node_type == "target" {
+                existing.node_type = node.node_type.clone();
+            }
+            let alias = self.interner.intern(&node.id);
+            self.aliases.insert(alias, canonical);
+            existing.absorb(node);
+            return;
+        }
+
+        let handle = self.interner.intern(&node.id);
+        if let Some(symbol) = &node.symbol_id {
+            self.symbols.insert(symbol.clone(), handle);
+        }
+        self.files.entry(node.id.file.clone()).or_default().insert(handle);
         self.nodes.insert(node.id.clone(), node);
     }
 
-    pub fn add_edge(&mut self, edge: Edge) {
-        self.edges.push(edge);
+    /// Nodes located in `file`, in line order. Indexed nodes since removed from
+    /// `nodes` are skipped; nodes inserted into `nodes` directly show up after `reindex`.
+    pub fn nodes_in_file(&self, file: &Path) -> Vec<&CodeNode> {
+        let Some(handles) = self.files.get(file) else {
+            return Vec::new();
+        };
+        let mut nodes: Vec<&CodeNode> =
+            handles.iter().filter_map(|&handle| self.nodes.get(self.interner.id(handle))).collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes
+    }
+
+    /// The node `id` was merged into, or `id` itself
+    pub fn resolve<'a>(&'a self, id: &'a NodeId) -> &'a NodeId {
+        self.alias_of(id).unwrap_or(id)
+    }
+
+    fn alias_of(&self, id: &NodeId) -> Option<&NodeId> {
+        let handle = self.interner.get(id)?;
+        self.aliases.get(&handle).map(|&canonical| self.interner.id(canonical))
+    }
+
+    /// Set an annotation on a node (or the node it was merged into).
+    /// Returns false if there is no such node.
+    pub fn annotate(&mut self, id: &NodeId, key: &str, value: &str) -> bool {
+        let id = self.resolve(id).clone();
+        match self.nodes.get_mut(&id) {
+            Some(node) => {
+                node.tags.insert(key.to_string(), value.to_string());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// True if `id` is a node or was merged into one
+    pub fn contains_node(&self, id: &NodeId) -> bool {
+        self.nodes.contains_key(self.resolve(id))
+    }
+
+    /// Collapse nodes belonging to the same container item (same `SymbolId`) into one
+    /// node, keeping the others' positions in `positions`. Insertion already does this
+    /// for nodes whose symbol is known up front; run it after symbols are filled in
+    /// later or on graphs from older files. Returns how many nodes were merged away.
+    pub fn compact_by_container(&mut self) -> usize {
+        let before = self.nodes.len();
+        let mut nodes: Vec<CodeNode> = std::mem::take(&mut self.nodes).into_values().collect();
+        // Deterministic choice of which position survives
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        let edges = std::mem::take(&mut self.edges.items);
+        self.symbols.clear();
+        self.aliases.clear();
+        self.files.clear();
+        self.interner.clear();
+        self.reindex_edges();
+
+        for node in nodes {
+            self.add_node(node);
+        }
+        for edge in edges {
+            self.add_edge(edge);
+        }
+        before - self.nodes.len()
+    }
+
+    /// Count how often each edge is added: when enabled, re-adding an existing
+    /// (from, to, type) edge adds to its `occurrences` instead of leaving it as is
+    pub fn count_edge_multiplicity(&mut self, enabled: bool) {
+        self.count_multiplicity = enabled;
+    }
+
+    /// Add an edge; endpoints merged into another node are redirected to it,
+    /// and self-loops created by that redirection are dropped. Edges are a set
+    /// keyed by (from, to, type): adding an existing one merges its metadata.
+    pub fn add_edge(&mut self, mut edge: Edge) {
+        let from = self.alias_of(&edge.from).cloned();
+        let to = self.alias_of(&edge.to).cloned();
+        let redirected = from.is_some() || to.is_some();
+        edge.from = from.unwrap_or(edge.from);
+        edge.to = to.unwrap_or(edge.to);
+        if redirected && edge.from == edge.to {
+            return;
+        }
+
+        if !self.index_is_fresh() {
+            self.reindex_edges();
+        }
+
+        let existing = self.interner.get(&edge.from).and_then(|from| self.outgoing.get(from.index())).and_then(|indices| {
+            indices
+                .iter()
+                .copied()
+                .find(|&i| self.edges[i].to == edge.to && self.edges[i].edge_type == edge.edge_type)
+        });
+        if let Some(existing) = existing {
+            let count = self.count_multiplicity;
+            self.edges.items[existing].metadata.absorb(edge.metadata, count);
+            return;
+        }
+
+        let index = self.edges.len();
+        let (from, to) = (self.interner.intern(&edge.from), self.interner.intern(&edge.to));
+        push_adjacent(&mut self.outgoing, from, index);
+        push_adjacent(&mut self.incoming, to, index);
+        self.edges.items.push(edge);
+    }
+
+    /// Rebuild the adjacency index from `edges` and the file index from `nodes`,
+    /// dropping the interned ids of anything no longer in the graph
+    pub fn reindex(&mut self) {
+        self.files.clear();
+        self.rebuild_interner();
+        for id in self.nodes.keys() {
+            let handle = self.interner.intern(id);
+            self.files.entry(id.file.clone()).or_default().insert(handle);
+        }
+        self.reindex_edges();
+    }
+
+    /// Re-intern only the ids the symbol, alias and file indices still refer to; edge
+    /// endpoints are interned again by `reindex_edges`, which must follow
+    fn rebuild_interner(&mut self) {
+        let old = std::mem::take(&mut self.interner);
+        let interner = &mut self.interner;
+        let mut remap = |handle: NodeHandle| interner.intern(old.id(handle));
+
+        for handle in self.symbols.values_mut() {
+            *handle = remap(*handle);
+        }
+        self.aliases = std::mem::take(&mut self.aliases)
+            .into_iter()
+            .map(|(alias, canonical)| (remap(alias), remap(canonical)))
+            .collect();
+        for handles in self.files.values_mut() {
+            *handles = handles.iter().map(|&handle| remap(handle)).collect();
+        }
+    }
+
+    /// Rebuild the adjacency index from `edges`
+    fn reindex_edges(&mut self) {
+        self.outgoing.clear();
+        self.incoming.clear();
+        for (index, edge) in self.edges.iter().enumerate() {
+            let (from, to) = (self.interner.intern(&edge.from), self.interner.intern(&edge.to));
+            push_adjacent(&mut self.outgoing, from, index);
+            push_adjacent(&mut self.incoming, to, index);
+        }
+        self.edges.dirty = false;
+    }
+
+    fn index_is_fresh(&self) -> bool {
+        !self.edges.dirty
+    }
+
+    /// Edges leaving `node`, in insertion order
+    pub fn outgoing_edges<'a>(&'a self, node: &'a NodeId) -> Box<dyn Iterator<Item = &'a Edge> + 'a> {
+        if !self.index_is_fresh() {
+            // Edges were modified directly; fall back to a scan
+            return Box::new(self.edges.iter().filter(move |e| &e.from == node));
+        }
+        match self.interner.get(node).and_then(|handle| self.outgoing.get(handle.index())) {
+            Some(indices) => Box::new(indices.iter().map(move |&i| &self.edges[i])),
+            None => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Edges entering `node`, in insertion order
+    pub fn incoming_edges<'a>(&'a self, node: &'a NodeId) -> Box<dyn Iterator<Item = &'a Edge> + 'a> {
+        if !self.index_is_fresh() {
+            return Box::new(self.edges.iter().filter(move |e| &e.to == node));
+        }
+        match self.interner.get(node).and_then(|handle| self.incoming.get(handle.index())) {
+            Some(indices) => Box::new(indices.iter().map(move |&i| &self.edges[i])),
+            None => Box::new(std::iter::empty()),
+        }
+    }
+
+    /// Remove every node in `file` and every edge touching one. Returns the removed
+    /// edges, whose surviving endpoints are where re-slicing the file should resume.
+    pub fn invalidate_file(&mut self, file: &Path) -> Vec<Edge> {
+        self.nodes.retain(|id, _| id.file != file);
+        self.files.remove(file);
+        self.symbols.retain(|symbol, _| symbol.file != file);
+        let interner = &self.interner;
+        self.aliases
+            .retain(|&alias, &mut canonical| interner.id(alias).file != file && interner.id(canonical).file != file);
+
+        self.remove_edges_where(|edge| edge.from.file == file || edge.to.file == file)
+    }
+
+    /// Remove a node (or the node it was merged into) along with every edge
+    /// touching it. Returns the removed node.
+    pub fn remove_node(&mut self, id: &NodeId) -> Option<CodeNode> {
+        let id = self.resolve(id).clone();
+        let node = self.nodes.remove(&id)?;
+        let handle = self.interner.get(&id);
+
+        if let Some(handle) = handle
+            && let Some(ids) = self.files.get_mut(&id.file)
+            && ids.remove(&handle)
+            && ids.is_empty()
+        {
+            self.files.remove(&id.file);
+        }
+        if let Some(symbol) = &node.symbol_id
+            && self.symbols.get(symbol).copied() == handle
+        {
+            self.symbols.remove(symbol);
+        }
+        if let Some(handle) = handle {
+            self.aliases.retain(|_, canonical| *canonical != handle);
+        }
+
+        self.remove_edges_where(|edge| edge.from == id || edge.to == id);
+        Some(node)
+    }
+
+    /// Remove every edge matching `pred`, keeping the adjacency index in sync.
+    /// Returns the removed edges.
+    pub fn remove_edges_where(&mut self, pred: impl Fn(&Edge) -> bool) -> Vec<Edge> {
+        let (removed, kept): (Vec<Edge>, Vec<Edge>) = std::mem::take(&mut self.edges.items).into_iter().partition(|edge| pred(edge));
+        self.edges.items = kept;
+        // Handles of removed nodes and edge endpoints are freed here too
+        self.rebuild_interner();
+        self.reindex_edges();
+        removed
+    }
+
+    /// True if an edge of `edge_type` already links `from` to `to`
+    pub fn contains_edge(&self, from: &NodeId, to: &NodeId, edge_type: &EdgeType) -> bool {
+        self.outgoing_edges(from).any(|e| &e.to == to && &e.edge_type == edge_type)
+    }
+
+    /// Union `other` into this graph. Nodes present in both keep this graph's
+    /// code and role, with any missing symbol details filled in from `other`; positions
+    /// folded into a node in either graph become aliases of it here.
+    /// Edges are deduplicated by (from, to, type). The result is truncated if either was.
+    pub fn merge(&mut self, other: DependencyGraph) {
+        if let Some(frontier) = other.truncated {
+            self.truncated.get_or_insert_default().extend(frontier);
+        }
+        // Sorted, so which position survives a symbol merge doesn't depend on hash order
+        let mut nodes: Vec<(NodeId, CodeNode)> = other.nodes.into_iter().collect();
+        nodes.sort_by(|a, b| a.0.cmp(&b.0));
+        for (id, node) in nodes {
+            let resolved = self.resolve(&id).clone();
+            match self.nodes.get_mut(&resolved) {
+                Some(existing) => existing.absorb(node),
+                None => self.add_node(node),
+            }
+            let canonical = self.resolve(&id).clone();
+            self.register_folded(&canonical);
+        }
+
+        for edge in other.edges.items {
+            self.add_edge(edge);
+        }
+    }
+
+    /// Index the symbol of node `id` and alias its folded positions to it, as `add_node`
+    /// would have if they had been added separately
+    fn register_folded(&mut self, id: &NodeId) {
+        let Some(node) = self.nodes.get(id) else {
+            return;
+        };
+        let (symbol, positions) = (node.symbol_id.clone(), node.positions.clone());
+        let handle = self.interner.intern(id);
+        if let Some(symbol) = symbol {
+            self.symbols.entry(symbol).or_insert(handle);
+        }
+        for position in positions {
+            if !self.nodes.contains_key(&position) {
+                let alias = self.interner.intern(&position);
+                self.aliases.insert(alias, handle);
+            }
+        }
+    }
+
+    /// New graph with only the nodes matching `keep`, and the edges between them
+    pub fn subgraph(&self, keep: impl Fn(&CodeNode) -> bool) -> DependencyGraph {
+        let mut graph = DependencyGraph::new();
+        for node in self.nodes.values().filter(|node| keep(node)) {
+            graph.add_node(node.clone());
+        }
+        for edge in &self.edges {
+            if graph.nodes.contains_key(&edge.from) && graph.nodes.contains_key(&edge.to) {
+                graph.add_edge(edge.clone());
+            }
+        }
+        graph
+    }
+
+    /// All nodes ordered by (file, line, column), for output that is stable across runs
+    pub fn sorted_nodes(&self) -> Vec<&CodeNode> {
+        let mut nodes: Vec<&CodeNode> = self.nodes.values().collect();
+        nodes.sort_by(|a, b| a.id.cmp(&b.id));
+        nodes
+    }
+
+    /// The node the slice was built around (the first one, for multi-target slices)
+    pub fn target(&self) -> Option<&CodeNode> {
+        self.targets().into_iter().next()
+    }
+
+    /// Every node a slice was built around, in position order
+    pub fn targets(&self) -> Vec<&CodeNode> {
+        self.sorted_nodes().into_iter().filter(|node| node.node_type == "target").collect()
+    }
+
+    /// Why `id` is in the slice: the chain of nodes and edges from where slicing started
+    /// down to it, one step per line, with the operation that found each. `None` if the
+    /// node isn't in the graph.
+    pub fn explain(&self, id: &NodeId) -> Option<String> {
+        let mut chain = vec![self.nodes.get(self.resolve(id))?];
+        while let Some(parent) = chain[chain.len() - 1].provenance.as_ref().and_then(|p| p.parent.as_ref())
+            && let Some(node) = self.nodes.get(self.resolve(parent))
+            && !chain.iter().any(|seen| seen.id == node.id)
+        {
+            chain.push(node);
+        }
+        chain.reverse();
+
+        let mut trail = String::new();
+        for (i, node) in chain.iter().enumerate() {
+            let indent = if i == 0 { String::new() } else { format!("{}↳ ", "  ".repeat(i - 1)) };
+            let name = node.symbol_name.as_deref().map(|name| format!(" `{}`", name)).unwrap_or_default();
+            let how = match &node.provenance {
+                Some(Provenance { operation, edge_type: Some(edge_type), .. }) => {
+                    format!("{}, found by {}", edge_type.as_str(), operation)
+                }
+                Some(Provenance { operation, .. }) => operation.clone(),
+                None => "origin unknown".to_string(),
+            };
+            trail.push_str(&format!(
+                "{}{} {}:{}:{}{} ({})\n",
+                indent,
+                node.node_type,
+                node.id.file.display(),
+                node.id.line + 1,
+                node.id.column + 1,
+                name,
+                how
+            ));
+        }
+        Some(trail)
+    }
+
+    /// Write the graph to `path` as versioned JSON. Nodes and edges are sorted so
+    /// equal graphs produce identical files.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = GraphFile {
+            format: GRAPH_FORMAT.to_string(),
+            version: GRAPH_FORMAT_VERSION,
+            nodes: self.sorted_nodes().into_iter().cloned().collect(),
+            edges: self.sorted_edges(),
+            truncated: self.truncated.clone(),
+        };
+
+        let json = serde_json::to_string_pretty(&file)?;
+        fs::write(path, json).with_context(|| format!("Failed to write graph to {}", path.display()))
+    }
+
+    /// Read a graph written by `save`, rejecting foreign or newer formats
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read graph from {}", path.display()))?;
+
+        // Check the header before committing to the full layout
+        let header: serde_json::Value = serde_json::from_str(&json)?;
+        if header.get("format").and_then(|f| f.as_str()) != Some(GRAPH_FORMAT) {
+            return Err(anyhow!("{} is not a graphslice graph file", path.display()));
+        }
+        let version = header.get("version").and_then(|v| v.as_u64()).unwrap_or(0);
+        if version == 0 || version > GRAPH_FORMAT_VERSION as u64 {
+            return Err(anyhow!(
+                "Unsupported graph format version {} in {} (supported: 1..={})",
+                version,
+                path.display(),
+                GRAPH_FORMAT_VERSION
+            ));
+        }
+
+        let file: GraphFile = serde_json::from_value(header)?;
+        let mut graph = Self::from_parts(file.nodes, file.edges);
+        graph.truncated = file.truncated;
+        Ok(graph)
+    }
+
+    /// Edges ordered by (from, to, type), for output that is stable across runs
+    pub(crate) fn sorted_edges(&self) -> Vec<Edge> {
+        let mut edges = self.edges.clone();
+        edges.sort_by(|a, b| (&a.from, &a.to, a.edge_type.as_str()).cmp(&(&b.from, &b.to, b.edge_type.as_str())));
+        edges
+    }
+
+    /// Rebuild a graph from stored nodes and edges
+    pub(crate) fn from_parts(nodes: Vec<CodeNode>, edges: Vec<Edge>) -> Self {
+        let mut graph = Self::new();
+        for mut node in nodes {
+            // Files written before hashes were recorded
+            if node.content_hash.is_empty() {
+                node.rehash();
+            }
+            graph.add_node(node);
+        }
+        for edge in edges {
+            graph.add_edge(edge);
+        }
+        graph
     }
 
     /// Get all nodes reachable from root via BFS
     /// Returns (node, distance) pairs
     pub fn bfs_from(&self, root: &NodeId) -> Vec<(NodeId, usize)> {
+        self.bfs(std::slice::from_ref(root), Direction::Forward, usize::MAX, |_| true)
+    }
+
+    /// Like `bfs_from`, but only follows edges whose type is in `edge_types`,
+    /// e.g. `&[EdgeType::Calls]` for a call-graph-only view
+    pub fn bfs_from_filtered(&self, root: &NodeId, edge_types: &[EdgeType]) -> Vec<(NodeId, usize)> {
+        self.bfs(std::slice::from_ref(root), Direction::Forward, usize::MAX, |edge| edge_types.contains(&edge.edge_type))
+    }
+
+    /// Get all nodes that (transitively) depend on `root`, walking edges backwards.
+    /// Returns (node, distance) pairs; useful for impact analysis and backward slicing.
+    pub fn bfs_to(&self, root: &NodeId) -> Vec<(NodeId, usize)> {
+        self.bfs(std::slice::from_ref(root), Direction::Backward, usize::MAX, |_| true)
+    }
+
+    /// Nodes reachable from any of `roots`, each with its distance to the nearest root
+    pub fn bfs_from_many(&self, roots: &[NodeId]) -> Vec<(NodeId, usize)> {
+        self.bfs(roots, Direction::Forward, usize::MAX, |_| true)
+    }
+
+    /// Nodes within `k` hops of `node`, with their distance, following edges in `direction`
+    pub fn neighborhood(&self, node: &NodeId, k: usize, direction: Direction) -> Vec<(NodeId, usize)> {
+        self.bfs(std::slice::from_ref(node), direction, k, |_| true)
+    }
+
+    fn bfs(
+        &self,
+        roots: &[NodeId],
+        direction: Direction,
+        max_depth: usize,
+        follow: impl Fn(&Edge) -> bool,
+    ) -> Vec<(NodeId, usize)> {
         let mut visited = HashSet::new();
         let mut queue = VecDeque::new();
         let mut result = Vec::new();
 
-        queue.push_back((root.clone(), 0));
-        visited.insert(root.clone());
+        for root in roots {
+            if visited.insert(root.clone()) {
+                queue.push_back((root.clone(), 0));
+            }
+        }
 
         while let Some((node_id, distance)) = queue.pop_front() {
             result.push((node_id.clone(), distance));
 
-            // Find all edges from this node
-            for edge in &self.edges {
-                if edge.from == node_id && !visited.contains(&edge.to) {
-                    visited.insert(edge.to.clone());
-                    queue.push_back((edge.to.clone(), distance + 1));
+            if distance >= max_depth {
+                continue;
+            }
+
+            let mut neighbors: Vec<&NodeId> = Vec::new();
+            if direction != Direction::Backward {
+                neighbors.extend(self.outgoing_edges(&node_id).filter(|edge| follow(edge)).map(|edge| &edge.to));
+            }
+            if direction != Direction::Forward {
+                neighbors.extend(self.incoming_edges(&node_id).filter(|edge| follow(edge)).map(|edge| &edge.from));
+            }
+
+            for next in neighbors {
+                if !visited.contains(next) {
+                    visited.insert(next.clone());
+                    queue.push_back((next.clone(), distance + 1));
                 }
             }
         }
 
+        // Discovery order follows edge insertion order; report each level by position instead
+        result.sort_by(|(a, a_depth), (b, b_depth)| a_depth.cmp(b_depth).then_with(|| a.cmp(b)));
         result
     }
 
+    /// Get nodes with an edge into `node` (its direct dependents)
+    pub fn get_dependents(&self, node: &NodeId) -> Vec<&CodeNode> {
+        self.incoming_edges(node)
+            .filter_map(|e| self.nodes.get(&e.from))
+            .collect()
+    }
+
     /// Get direct dependencies of a node
     pub fn get_dependencies(&self, node: &NodeId) -> Vec<&CodeNode> {
-        self.edges
-            .iter()
-            .filter(|e| &e.from == node)
+        self.outgoing_edges(node)
             .filter_map(|e| self.nodes.get(&e.to))
             .collect()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_load_round_trip() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), "fn main() { helper(); }".to_string(), "target"));
+        graph.add_node(CodeNode::new(test_id(5), "fn helper() {}".to_string(), "call"));
+        graph.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+        graph.truncated = Some(vec![test_id(5)]);
+
+        let path = std::env::temp_dir().join(format!("graphslice_graph_{}.json", std::process::id()));
+        graph.save(&path).unwrap();
+        let loaded = DependencyGraph::load(&path).unwrap();
+        let first = std::fs::read_to_string(&path).unwrap();
+        loaded.save(&path).unwrap();
+        let second = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(first, second);
+
+        assert_eq!(loaded.nodes.len(), 2);
+        assert_eq!(loaded.edges, graph.edges);
+        assert_eq!(loaded.nodes[&test_id(5)].code, "fn helper() {}");
+        assert_eq!(loaded.truncated, Some(vec![test_id(5)]));
+    }
+
+    #[test]
+    fn test_load_rejects_newer_version() {
+        let path = std::env::temp_dir().join(format!("graphslice_graph_v99_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"format":"graphslice-graph","version":99,"nodes":[],"edges":[]}"#).unwrap();
+        let result = DependencyGraph::load(&path);
+        std::fs::remove_file(&path).ok();
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_serde_round_trip_is_deterministic() {
+        let mut graph = DependencyGraph::new();
+        for line in [9, 1, 5, 3] {
+            graph.add_node(CodeNode::new(test_id(line), format!("fn f{}() {{}}", line), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(5), test_id(9), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(1), test_id(3), EdgeType::References));
+        graph.truncated = Some(vec![test_id(9)]);
+
+        let first = serde_json::to_string(&graph).unwrap();
+        let loaded: DependencyGraph = serde_json::from_str(&first).unwrap();
+        let second = serde_json::to_string(&loaded).unwrap();
+        assert_eq!(first, second);
+
+        assert_eq!(loaded.nodes.len(), 4);
+        assert_eq!(loaded.truncated, Some(vec![test_id(9)]));
+        assert_eq!(loaded.get_dependencies(&test_id(5)).len(), 1);
+        assert_eq!(loaded.nodes_in_file(Path::new("/src/lib.rs")).len(), 4);
+    }
+
+    #[test]
+    fn test_index_survives_direct_edge_mutation() {
+        let mut graph = DependencyGraph::new();
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+
+        let reached: Vec<u32> = graph.bfs_from(&test_id(1)).into_iter().map(|(n, _)| n.line).collect();
+        assert_eq!(reached, vec![1, 2, 3]);
+
+        // Direct mutation makes the index stale; traversal must still be correct
+        graph.edges.retain(|e| e.from != test_id(2));
+        let reached: Vec<u32> = graph.bfs_from(&test_id(1)).into_iter().map(|(n, _)| n.line).collect();
+        assert_eq!(reached, vec![1, 2]);
+
+        graph.reindex();
+        assert_eq!(graph.incoming_edges(&test_id(2)).count(), 1);
+
+        // Same length, different endpoints
+        graph.edges[0].to = test_id(4);
+        assert!(graph.incoming_edges(&test_id(2)).next().is_none());
+        assert_eq!(graph.incoming_edges(&test_id(4)).count(), 1);
+        graph.edges.retain(|e| e.to != test_id(4));
+        graph.edges.push(Edge::new(test_id(3), test_id(1), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(1), EdgeType::Calls));
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.incoming_edges(&test_id(1)).count(), 1);
+        assert_eq!(graph.outgoing_edges(&test_id(3)).count(), 1);
+    }
+
+    #[test]
+    fn test_merge_dedupes_edges() {
+        let mut strict = DependencyGraph::new();
+        strict.add_node(CodeNode::new(test_id(1), "fn a() { b() }".to_string(), "target"));
+        strict.add_node(CodeNode::new(test_id(5), "fn b() {}".to_string(), "call"));
+        strict.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+
+        let mut fuzzy = DependencyGraph::new();
+        let mut target = CodeNode::new(test_id(1), "fn a() { b() }".to_string(), "function_item");
+        target.symbol_name = Some("a".to_string());
+        fuzzy.add_node(target);
+        fuzzy.add_node(CodeNode::new(test_id(9), "struct C;".to_string(), "struct_item"));
+        let inferred = EdgeMetadata { inferred: true, ..EdgeMetadata::default() };
+        fuzzy.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls).with_metadata(inferred.clone()));
+        fuzzy.add_edge(Edge::new(test_id(1), test_id(9), EdgeType::Defines).with_metadata(inferred));
+
+        strict.merge(fuzzy);
+        assert_eq!(strict.nodes.len(), 3);
+        assert_eq!(strict.edges.len(), 2);
+        assert_eq!(strict.nodes[&test_id(1)].node_type, "target");
+        assert_eq!(strict.nodes[&test_id(1)].symbol_name.as_deref(), Some("a"));
+        // Confirmed by the strict slice vs. only guessed by the fuzzy one
+        assert!(!strict.outgoing_edges(&test_id(1)).find(|e| e.to == test_id(5)).unwrap().metadata.inferred);
+        assert!(strict.outgoing_edges(&test_id(1)).find(|e| e.to == test_id(9)).unwrap().metadata.inferred);
+    }
+
+    #[test]
+    fn test_merge_registers_folded_symbols_and_aliases() {
+        let symbol = |line| SymbolId {
+            file: PathBuf::from("/src/lib.rs"),
+            name: "run".to_string(),
+            kind: SymbolKind::Function,
+            line,
+        };
+        let mut strict = DependencyGraph::new();
+        strict.add_node(CodeNode::new(test_id(1), "fn run() {}".to_string(), "target"));
+
+        // The symbol is only known to the fuzzy slice, which also folded line 2 into line 1
+        let mut fuzzy = DependencyGraph::new();
+        for line in [1, 2] {
+            let mut node = CodeNode::new(test_id(line), "fn run() {}".to_string(), "definition");
+            node.symbol_id = Some(symbol(1));
+            fuzzy.add_node(node);
+        }
+        let mut other = CodeNode::new(test_id(10), "fn run() {}".to_string(), "definition");
+        other.symbol_id = Some(symbol(10));
+        fuzzy.add_node(other);
+        strict.merge(fuzzy);
+
+        assert_eq!(strict.resolve(&test_id(2)), &test_id(1));
+        let mut again = CodeNode::new(test_id(3), "fn run() {}".to_string(), "definition");
+        again.symbol_id = Some(symbol(1));
+        strict.add_node(again);
+        assert_eq!(strict.nodes.len(), 2);
+        assert_eq!(strict.nodes[&test_id(1)].positions, vec![test_id(2), test_id(3)]);
+
+        strict.add_edge(Edge::new(test_id(10), test_id(2), EdgeType::Calls));
+        assert!(strict.contains_edge(&test_id(10), &test_id(1), &EdgeType::Calls));
+    }
+
+    #[test]
+    fn test_subgraph_keeps_internal_edges() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), String::new(), "target"));
+        graph.add_node(CodeNode::new(test_id(2), String::new(), "call"));
+        graph.add_node(CodeNode::new(test_id(3), String::new(), "reference"));
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(1), EdgeType::References));
+
+        let calls = graph.subgraph(|node| node.node_type != "reference");
+        assert_eq!(calls.nodes.len(), 2);
+        assert_eq!(calls.edges.len(), 1);
+        assert_eq!(calls.get_dependencies(&test_id(1)).len(), 1);
+    }
+
+    #[test]
+    fn test_bfs_from_filtered() {
+        let mut graph = DependencyGraph::new();
+        for line in 1..=3 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::References));
+        graph.add_edge(Edge::new(test_id(1), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(2), EdgeType::Calls));
+
+        let calls: Vec<(u32, usize)> = graph
+            .bfs_from_filtered(&test_id(1), &[EdgeType::Calls])
+            .into_iter()
+            .map(|(n, d)| (n.line, d))
+            .collect();
+        assert_eq!(calls, vec![(1, 0), (3, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_add_node_dedupes_by_symbol() {
+        let symbol = SymbolId {
+            file: PathBuf::from("/src/lib.rs"),
+            name: "run".to_string(),
+            kind: SymbolKind::Function,
+            line: 1,
+        };
+        let mut graph = DependencyGraph::new();
+        let mut target = CodeNode::new(test_id(2), "fn run() {}".to_string(), "target");
+        target.symbol_id = Some(symbol.clone());
+        graph.add_node(target);
+
+        // The same function, reported at its name position
+        let mut definition = CodeNode::new(test_id(1), "fn run() {}".to_string(), "definition");
+        definition.symbol_id = Some(symbol);
+        definition.docs = Some("Entry point".to_string());
+        graph.add_node(definition);
+        graph.add_node(CodeNode::new(test_id(7), String::new(), "call"));
+
+        graph.add_edge(Edge::new(test_id(2), test_id(1), EdgeType::Defines));
+        graph.add_edge(Edge::new(test_id(1), test_id(7), EdgeType::Calls));
+
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.nodes[&test_id(2)].node_type, "target");
+        assert_eq!(graph.nodes[&test_id(2)].positions, vec![test_id(1)]);
+        assert_eq!(graph.nodes[&test_id(2)].docs.as_deref(), Some("Entry point"));
+        assert!(graph.contains_node(&test_id(1)));
+        assert!(graph.annotate(&test_id(1), "review", "verified"));
+        assert_eq!(graph.nodes[&test_id(2)].tags["review"], "verified");
+        assert!(!graph.annotate(&test_id(3), "review", "verified"));
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].from, test_id(2));
+    }
+
+    #[test]
+    fn test_neighborhood() {
+        // 1 -> 2 -> 3 -> 4, 5 -> 2
+        let mut graph = DependencyGraph::new();
+        for line in 1..=5 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(4), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(5), test_id(2), EdgeType::Calls));
+
+        let lines = |hood: Vec<(NodeId, usize)>| {
+            let mut lines: Vec<u32> = hood.into_iter().map(|(n, _)| n.line).collect();
+            lines.sort();
+            lines
+        };
+        assert_eq!(lines(graph.neighborhood(&test_id(2), 1, Direction::Forward)), vec![2, 3]);
+        assert_eq!(lines(graph.neighborhood(&test_id(2), 1, Direction::Backward)), vec![1, 2, 5]);
+        assert_eq!(lines(graph.neighborhood(&test_id(2), 2, Direction::Both)), vec![1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_invalidate_file() {
+        let other = NodeId { file: PathBuf::from("/src/util.rs"), line: 3, column: 0 };
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), String::new(), "target"));
+        graph.add_node(CodeNode::new(test_id(2), String::new(), "definition"));
+        graph.add_node(CodeNode::new(other.clone(), String::new(), "call"));
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Defines));
+        graph.add_edge(Edge::new(test_id(2), other.clone(), EdgeType::Calls));
+
+        assert_eq!(graph.nodes_in_file(Path::new("/src/util.rs")).len(), 1);
+        let dropped = graph.invalidate_file(Path::new("/src/util.rs"));
+        assert_eq!(dropped.len(), 1);
+        assert!(graph.nodes_in_file(Path::new("/src/util.rs")).is_empty());
+        let lines: Vec<u32> = graph.nodes_in_file(Path::new("/src/lib.rs")).iter().map(|n| n.id.line).collect();
+        assert_eq!(lines, vec![1, 2]);
+        assert_eq!(dropped[0].from, test_id(2));
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.get_dependencies(&test_id(2)).len(), 0);
+        assert_eq!(graph.get_dependencies(&test_id(1)).len(), 1);
+    }
+
+    #[test]
+    fn test_nodes_in_file_after_direct_mutation() {
+        let other = NodeId { file: PathBuf::from("/src/util.rs"), line: 3, column: 0 };
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), String::new(), "target"));
+        graph.add_node(CodeNode::new(test_id(2), String::new(), "call"));
+
+        // Same node count, different nodes
+        graph.nodes.remove(&test_id(2));
+        graph.nodes.insert(other.clone(), CodeNode::new(other.clone(), String::new(), "call"));
+        let lines: Vec<u32> = graph.nodes_in_file(Path::new("/src/lib.rs")).iter().map(|n| n.id.line).collect();
+        assert_eq!(lines, vec![1]);
+
+        graph.reindex();
+        assert_eq!(graph.nodes_in_file(Path::new("/src/util.rs")).len(), 1);
+    }
+
+    #[test]
+    fn test_remove_node_drops_dangling_edges() {
+        let mut graph = DependencyGraph::new();
+        for line in 1..=3 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(1), test_id(3), EdgeType::References));
+
+        assert!(graph.remove_node(&test_id(2)).is_some());
+        assert!(graph.remove_node(&test_id(2)).is_none());
+        assert_eq!(graph.edges.len(), 1);
+        assert!(graph.incoming_edges(&test_id(2)).next().is_none());
+        assert_eq!(graph.nodes_in_file(Path::new("/src/lib.rs")).len(), 2);
+
+        let removed = graph.remove_edges_where(|edge| edge.edge_type == EdgeType::References);
+        assert_eq!(removed.len(), 1);
+        assert!(graph.get_dependencies(&test_id(1)).is_empty());
+    }
+
+    #[test]
+    fn test_indices_share_one_interned_id_per_node() {
+        let mut graph = DependencyGraph::new();
+        for line in 1..=3 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(1), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Reads));
+        assert_eq!(graph.interner.ids.len(), 3);
+
+        // Removing a node frees its handle, and re-adding it interns it again
+        graph.remove_node(&test_id(2));
+        assert_eq!(graph.interner.ids.len(), 2);
+        graph.add_node(CodeNode::new(test_id(2), String::new(), "call"));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Reads));
+        assert_eq!(graph.interner.ids.len(), 3);
+        assert_eq!(graph.incoming_edges(&test_id(3)).count(), 2);
+        assert_eq!(graph.nodes_in_file(Path::new("/src/lib.rs")).len(), 3);
+
+        graph.invalidate_file(Path::new("/src/lib.rs"));
+        assert!(graph.interner.ids.is_empty());
+    }
+
+    #[test]
+    fn test_compact_by_container() {
+        let symbol = SymbolId {
+            file: PathBuf::from("/src/lib.rs"),
+            name: "caller".to_string(),
+            kind: SymbolKind::Function,
+            line: 10,
+        };
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), String::new(), "target"));
+        for line in [12, 15] {
+            graph.add_node(CodeNode::new(test_id(line), format!("    use_target(); // {}", line), "reference"));
+            graph.add_edge(Edge::new(test_id(line), test_id(1), EdgeType::References));
+        }
+        // Symbols learned after insertion
+        for line in [12, 15] {
+            graph.nodes.get_mut(&test_id(line)).unwrap().symbol_id = Some(symbol.clone());
+        }
+
+        assert_eq!(graph.compact_by_container(), 1);
+        let container = &graph.nodes[&test_id(12)];
+        assert_eq!(container.positions, vec![test_id(15)]);
+        assert_eq!(container.code.lines().count(), 2);
+        assert_eq!(graph.incoming_edges(&test_id(1)).count(), 1);
+    }
+
+    #[test]
+    fn test_add_edge_is_idempotent() {
+        let site = |line| Range::new(lsp_types::Position::new(line, 4), lsp_types::Position::new(line, 9));
+        let calls = |line| {
+            Edge::new(test_id(1), test_id(2), EdgeType::Calls)
+                .with_metadata(EdgeMetadata { sites: vec![site(line)], ..EdgeMetadata::default() })
+        };
+
+        let mut graph = DependencyGraph::new();
+        graph.add_edge(calls(3));
+        graph.add_edge(calls(3));
+        graph.add_edge(calls(5));
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::References));
+        assert_eq!(graph.edges.len(), 2);
+        assert_eq!(graph.edges[0].metadata.sites.len(), 2);
+        assert_eq!(graph.edges[0].metadata.occurrences, 1);
+
+        let mut counted = DependencyGraph::new();
+        counted.count_edge_multiplicity(true);
+        counted.add_edge(calls(3));
+        counted.add_edge(calls(5));
+        assert_eq!(counted.edges.len(), 1);
+        assert_eq!(counted.edges[0].metadata.occurrences, 2);
+    }
+
+    #[test]
+    fn test_edge_type_names_round_trip() {
+        for edge_type in [EdgeType::Calls, EdgeType::Implements, EdgeType::Contains, EdgeType::Imports, EdgeType::TypeOf] {
+            assert_eq!(EdgeType::from_name(edge_type.as_str()), Some(edge_type));
+        }
+        assert_eq!(EdgeType::from_name("owns"), None);
+    }
+
+    #[test]
+    fn test_lazy_node_reads_code_on_demand() {
+        let file = std::env::temp_dir().join(format!("graphslice_lazy_{}.rs", std::process::id()));
+        std::fs::write(&file, "use std::fs;\nfn helper() {}\n").unwrap();
+        let id = NodeId { file: file.clone(), line: 1, column: 3 };
+
+        let mut node = CodeNode::lazy(id, CodeLocator { start_byte: 13, end_byte: 27 }, "fn helper() {}", "call");
+        assert!(node.is_lazy());
+        assert_eq!(node.code_len(), 14);
+        assert_eq!(node.text(), "fn helper() {}");
+        assert_eq!(node.content_hash, CodeNode::new(node.id.clone(), "fn helper() {}".to_string(), "call").content_hash);
+
+        node.load_code();
+        std::fs::remove_file(&file).ok();
+        assert!(!node.is_lazy());
+        assert_eq!(node.text(), "fn helper() {}");
+    }
+
+    #[test]
+    fn test_bfs_from_many_keeps_min_distance() {
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "reference"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(4), test_id(3), EdgeType::Calls));
+
+        let distances: HashMap<NodeId, usize> = graph.bfs_from_many(&[test_id(1), test_id(4), test_id(1)]).into_iter().collect();
+        assert_eq!(distances.len(), 4);
+        assert_eq!(distances[&test_id(1)], 0);
+        assert_eq!(distances[&test_id(4)], 0);
+        assert_eq!(distances[&test_id(2)], 1);
+        assert_eq!(distances[&test_id(3)], 1);
+
+        // Each level comes out by position, whatever order the roots and edges were given in
+        let order = graph.bfs_from_many(&[test_id(4), test_id(1)]);
+        assert_eq!(order, vec![(test_id(1), 0), (test_id(4), 0), (test_id(2), 1), (test_id(3), 1)]);
+    }
+
+    #[test]
+    fn test_bfs_to_walks_backwards() {
+        let mut graph = DependencyGraph::new();
+        for line in 1..=4 {
+            graph.add_node(CodeNode::new(test_id(line), String::new(), "call"));
+        }
+        graph.add_edge(Edge::new(test_id(1), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(3), test_id(4), EdgeType::Calls));
+
+        let dependents: Vec<(u32, usize)> = graph.bfs_to(&test_id(4)).into_iter().map(|(n, d)| (n.line, d)).collect();
+        assert_eq!(dependents, vec![(4, 0), (3, 1), (1, 2), (2, 2)]);
+        assert_eq!(graph.get_dependents(&test_id(3)).len(), 2);
+    }
+
+    #[test]
+    fn test_explain_follows_provenance() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), String::new(), "target").with_provenance(Provenance::root("slice target")));
+        let definition = Provenance::via("textDocument/definition", &test_id(1), EdgeType::Defines);
+        graph.add_node(CodeNode::new(test_id(4), String::new(), "definition").with_provenance(definition));
+        let call = Provenance::via("callHierarchy/outgoingCalls", &test_id(4), EdgeType::Calls);
+        graph.add_node(CodeNode::new(test_id(9), String::new(), "call").with_provenance(call));
+
+        let trail = graph.explain(&test_id(9)).unwrap();
+        assert_eq!(
+            trail.lines().collect::<Vec<_>>(),
+            vec![
+                "target /src/lib.rs:2:1 (slice target)",
+                "↳ definition /src/lib.rs:5:1 (defines, found by textDocument/definition)",
+                "  ↳ call /src/lib.rs:10:1 (calls, found by callHierarchy/outgoingCalls)",
+            ]
+        );
+        assert!(graph.explain(&test_id(2)).is_none());
+    }
+}
@@ -1,13 +1,40 @@
 pub mod lsp_client;
+pub mod lsp_pool;
 pub mod graph;
+pub mod export;
+pub mod diff;
+pub mod algo;
+pub mod relevance;
+pub mod stats;
+pub mod cache;
+pub mod bundle;
+pub mod source_cache;
+pub mod query;
 pub mod slicer;
+pub mod engine;
 pub mod compression;
 pub mod extractor;
+pub mod rules;
 pub mod llm_client;
 pub mod fuzzy_slicer;
 pub mod verifier;
+pub mod pass;
+pub mod cfg;
+pub mod position;
 
-pub use lsp_client::LspClient;
-pub use graph::{DependencyGraph, NodeId, EdgeType};
-pub use slicer::Slicer;
-pub use verifier::Verifier;
\ No newline at end of file
+pub use lsp_client::{ExpandedMacro, LspClient, LspClientBuilder, LspFeature, ReferenceOptions, RequestCancelled, ServerExited, TraceLevel, Transport};
+pub use lsp_pool::LspPool;
+pub use graph::{CodeLocator, DependencyGraph, Direction, NodeId, EdgeType, EdgeMetadata, Provenance, SymbolId, SymbolKind};
+pub use diff::GraphDelta;
+pub use algo::{DominatorTree, GraphVisitor};
+pub use stats::GraphStats;
+pub use cache::GraphCache;
+pub use bundle::SliceBundle;
+pub use source_cache::SourceCache;
+pub use slicer::{FuzzyMode, SliceCancelled, SlicePhase, SliceProgress, Slicer, SlicerConfig};
+pub use engine::{Fallback, SliceEngine, SliceFuture};
+pub use verifier::Verifier;
+pub use pass::{Candidate, CfgPass, ReachabilityPass, SlicePass};
+pub use cfg::CfgSet;
+pub use rules::ExtractionRules;
+pub use position::PositionEncoding;
\ No newline at end of file
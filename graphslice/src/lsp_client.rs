@@ -1,55 +1,418 @@
 use anyhow::{Context, Result, anyhow};
 use lsp_types::*;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
 use tokio::process::Command;
-use tokio::sync::{mpsc, oneshot};
+use tokio::sync::{Semaphore, broadcast, mpsc, oneshot, watch};
+use tokio_util::sync::CancellationToken;
 use url::Url;
 
+use crate::position::PositionEncoding;
+
+/// How many times the server may be restarted over the client's lifetime
+const MAX_SERVER_RESTARTS: usize = 3;
+
+/// Buffered diagnostics updates per subscriber before old ones are dropped
+const DIAGNOSTICS_CHANNEL_CAPACITY: usize = 64;
+
+/// How long `wait_until_idle` gives the server to start reporting work
+const PROGRESS_START_GRACE: Duration = Duration::from_secs(1);
+
+/// Default number of requests allowed in flight at once
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 8;
+
+/// JSON-RPC error code for a server request no handler or default answers
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for a server request whose handler failed
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Handler for a request initiated by the server (e.g. `workspace/configuration`).
+/// Receives the request params and returns the `result` to send back.
+pub type RequestHandler = Arc<dyn Fn(&Value) -> Result<Value> + Send + Sync>;
+
+type PendingRequests = Arc<Mutex<HashMap<i64, oneshot::Sender<Result<Value>>>>>;
+
+/// LSP features the Slicer may rely on, probed against the server's capabilities.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LspFeature {
+    References,
+    Definition,
+    CallHierarchy,
+    FoldingRange,
+    SelectionRange,
+    /// rust-analyzer's `experimental/parentModule`
+    ParentModule,
+    Implementation,
+}
+
+/// Verbosity of the LSP traffic trace.
+/// Events go through `tracing` under the `graphslice::lsp` target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceLevel {
+    #[default]
+    Off,
+    /// Method, direction, payload size and latency
+    Summary,
+    /// Summary plus the full JSON payload
+    Full,
+}
+
+impl TraceLevel {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TraceLevel::Summary,
+            2 => TraceLevel::Full,
+            _ => TraceLevel::Off,
+        }
+    }
+}
+
+/// How the client reaches the language server
+#[derive(Debug, Clone)]
+pub enum Transport {
+    /// Spawn the server as a child process and talk over stdio
+    Stdio { command: String, args: Vec<String> },
+    /// Attach to a server (or multiplexer) already listening on a TCP address
+    Tcp(String),
+    /// Attach to a server listening on a Unix domain socket
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Transport::Stdio {
+            command: "rust-analyzer".to_string(),
+            args: Vec::new(),
+        }
+    }
+}
+
+type BoxedReader = Box<dyn AsyncRead + Unpin + Send>;
+type BoxedWriter = Box<dyn AsyncWrite + Unpin + Send>;
+
+/// Controls which references `get_references_with` returns
+#[derive(Debug, Clone, Serialize)]
+pub struct ReferenceOptions {
+    /// Include the symbol's own declaration in the results
+    pub include_declaration: bool,
+    /// Only keep references under one of these paths (empty = keep all)
+    pub path_allowlist: Vec<PathBuf>,
+    /// Cap on the number of references returned
+    pub max_results: Option<usize>,
+}
+
+impl Default for ReferenceOptions {
+    fn default() -> Self {
+        Self {
+            include_declaration: true,
+            path_allowlist: Vec::new(),
+            max_results: None,
+        }
+    }
+}
+
+impl ReferenceOptions {
+    fn allows(&self, location: &Location) -> bool {
+        if self.path_allowlist.is_empty() {
+            return true;
+        }
+
+        let Some(path) = Url::parse(location.uri.as_str()).ok().and_then(|url| url.to_file_path().ok()) else {
+            return false;
+        };
+        self.path_allowlist.iter().any(|allowed| path.starts_with(allowed))
+    }
+
+    /// The allowed `locations`, capped at `max_results`
+    fn apply(&self, locations: Vec<Location>) -> Vec<Location> {
+        let mut locations: Vec<Location> = locations.into_iter().filter(|location| self.allows(location)).collect();
+        if let Some(max) = self.max_results {
+            locations.truncate(max);
+        }
+        locations
+    }
+}
+
+/// rust-analyzer's `rust-analyzer/expandMacro` extension request
+pub enum ExpandMacro {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandMacroParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+/// Recursive expansion of the macro call under the cursor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExpandedMacro {
+    pub name: String,
+    pub expansion: String,
+}
+
+impl lsp_types::request::Request for ExpandMacro {
+    type Params = ExpandMacroParams;
+    type Result = Option<ExpandedMacro>;
+    const METHOD: &'static str = "rust-analyzer/expandMacro";
+}
+
+/// Error delivered to in-flight requests when the server process dies
+#[derive(Debug, Clone)]
+pub struct ServerExited(pub String);
+
+impl fmt::Display for ServerExited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LSP server exited: {}", self.0)
+    }
+}
+
+impl std::error::Error for ServerExited {}
+
+/// Error returned when a request is abandoned through its `CancellationToken`
+#[derive(Debug, Clone)]
+pub struct RequestCancelled(pub String);
+
+impl fmt::Display for RequestCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LSP request cancelled: {}", self.0)
+    }
+}
+
+impl std::error::Error for RequestCancelled {}
+
+/// Keeps a filesystem watcher alive; see `LspClient::watch_workspace`
+#[cfg(feature = "watch")]
+pub struct WorkspaceWatcher {
+    _watcher: notify::RecommendedWatcher,
+}
+
+/// Builder for `LspClient`
+#[derive(Clone, Default)]
+pub struct LspClientBuilder {
+    workspace_folders: Vec<PathBuf>,
+    transport: Transport,
+    initialization_options: Option<Value>,
+    max_in_flight: Option<usize>,
+    trace: TraceLevel,
+}
+
+impl LspClientBuilder {
+    /// Add a workspace folder; the first one added is the root
+    pub fn workspace_folder(mut self, folder: PathBuf) -> Self {
+        self.workspace_folders.push(folder);
+        self
+    }
+
+    pub fn workspace_folders(mut self, folders: Vec<PathBuf>) -> Self {
+        self.workspace_folders.extend(folders);
+        self
+    }
+
+    pub(crate) fn reset_workspace_folders(&mut self) {
+        self.workspace_folders.clear();
+    }
+
+    pub fn transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// `initializationOptions` passed verbatim to the server, e.g. for rust-analyzer:
+    /// `{"procMacro": {"enable": false}, "cargo": {"features": ["foo"]}, "checkOnSave": false}`
+    pub fn initialization_options(mut self, options: Value) -> Self {
+        self.initialization_options = Some(options);
+        self
+    }
+
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    pub fn trace(mut self, level: TraceLevel) -> Self {
+        self.trace = level;
+        self
+    }
+
+    /// Connect to the server and run the initialize handshake
+    pub async fn connect(self) -> Result<LspClient> {
+        if self.workspace_folders.is_empty() {
+            return Err(anyhow!("At least one workspace folder is required"));
+        }
+
+        // Placeholder sender; replaced by the real one in `connect`
+        let (writer_tx, _) = mpsc::unbounded_channel::<String>();
+
+        let client = LspClient {
+            workspace_folders: self.workspace_folders,
+            transport: self.transport,
+            initialization_options: self.initialization_options,
+            writer_tx: Arc::new(Mutex::new(writer_tx)),
+            pending_requests: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(Mutex::new(0)),
+            diagnostics: Arc::new(Mutex::new(HashMap::new())),
+            diagnostics_tx: broadcast::channel(DIAGNOSTICS_CHANNEL_CAPACITY).0,
+            progress: Arc::new(watch::channel(HashSet::new()).0),
+            request_handlers: Arc::new(Mutex::new(HashMap::new())),
+            capabilities: Arc::new(Mutex::new(ServerCapabilities::default())),
+            open_documents: Arc::new(Mutex::new(HashMap::new())),
+            overlays: Arc::new(Mutex::new(HashMap::new())),
+            alive: Arc::new(AtomicBool::new(false)),
+            generation: Arc::new(AtomicUsize::new(0)),
+            closed: Arc::new(AtomicBool::new(false)),
+            restarts: Arc::new(AtomicUsize::new(0)),
+            restart_lock: Arc::new(tokio::sync::Mutex::new(())),
+            cancel: None,
+            in_flight: Arc::new(Semaphore::new(self.max_in_flight.unwrap_or(DEFAULT_MAX_IN_FLIGHT).max(1))),
+            trace: Arc::new(AtomicU8::new(self.trace as u8)),
+        };
+
+        client.connect().await?;
+        client.initialize().await?;
+
+        Ok(client)
+    }
+}
+
 #[derive(Clone)]
 pub struct LspClient {
-    writer_tx: mpsc::UnboundedSender<String>,
-    pending_requests: Arc<Mutex<HashMap<i64, oneshot::Sender<Result<Value>>>>>,
+    /// Workspace folders sent on initialize; the first one is the root
+    workspace_folders: Vec<PathBuf>,
+    transport: Transport,
+    initialization_options: Option<Value>,
+    writer_tx: Arc<Mutex<mpsc::UnboundedSender<String>>>,
+    pending_requests: PendingRequests,
     next_id: Arc<Mutex<i64>>,
     diagnostics: Arc<Mutex<HashMap<Uri, Vec<Diagnostic>>>>,
+    diagnostics_tx: broadcast::Sender<(Uri, Vec<Diagnostic>)>,
+    /// Tokens of the server's `$/progress` work still running, e.g. indexing
+    progress: Arc<watch::Sender<HashSet<String>>>,
+    request_handlers: Arc<Mutex<HashMap<String, RequestHandler>>>,
+    capabilities: Arc<Mutex<ServerCapabilities>>,
+    /// Documents opened via `did_open`, re-sent after a restart
+    open_documents: Arc<Mutex<HashMap<PathBuf, String>>>,
+    /// In-memory documents whose text replaces the file on disk, with their version
+    overlays: Arc<Mutex<HashMap<PathBuf, i32>>>,
+    alive: Arc<AtomicBool>,
+    /// Bumped by every `connect`, so I/O tasks of a replaced connection can't fail the new one
+    generation: Arc<AtomicUsize>,
+    /// Set by `shutdown`; stops crash recovery from bringing the server back
+    closed: Arc<AtomicBool>,
+    restarts: Arc<AtomicUsize>,
+    restart_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Token observed by every request sent through this handle
+    cancel: Option<CancellationToken>,
+    /// Bounds the number of requests multiplexed over the connection
+    in_flight: Arc<Semaphore>,
+    trace: Arc<AtomicU8>,
 }
 
 impl LspClient {
     /// Start rust-analyzer process and initialize
     pub async fn new(workspace_root: PathBuf) -> Result<Self> {
-        let mut child = Command::new("rust-analyzer")
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()
-            .context("Failed to spawn rust-analyzer")?;
+        Self::with_transport(workspace_root, Transport::default()).await
+    }
 
-        let mut stdin = child.stdin.take().context("Failed to open stdin")?;
-        let stdout = child.stdout.take().context("Failed to open stdout")?;
-        let stderr = child.stderr.take().context("Failed to open stderr")?;
+    /// Connect to a server over the given transport and initialize
+    pub async fn with_transport(workspace_root: PathBuf, transport: Transport) -> Result<Self> {
+        Self::with_workspace_folders(vec![workspace_root], transport).await
+    }
 
-        let (writer_tx, mut writer_rx) = mpsc::unbounded_channel::<String>();
-        let pending_requests: Arc<Mutex<HashMap<i64, oneshot::Sender<Result<Value>>>>> = Arc::new(Mutex::new(HashMap::new()));
-        let diagnostics: Arc<Mutex<HashMap<Uri, Vec<Diagnostic>>>> = Arc::new(Mutex::new(HashMap::new()));
+    /// Initialize with several workspace folders (e.g. workspace members plus a sibling repo)
+    /// so references across them resolve. The first folder is used as the root.
+    pub async fn with_workspace_folders(workspace_folders: Vec<PathBuf>, transport: Transport) -> Result<Self> {
+        Self::builder()
+            .workspace_folders(workspace_folders)
+            .transport(transport)
+            .connect()
+            .await
+    }
 
-        // Stderr logger
-        tokio::spawn(async move {
-            let mut reader = BufReader::new(stderr).lines();
-            while let Ok(Some(_line)) = reader.next_line().await {
-                // Keep stderr open but don't spam stdout unless needed
-                // eprintln!("LSP Stderr: {}", _line);
+    /// Configure a client before connecting (transport, folders, initializationOptions, ...)
+    pub fn builder() -> LspClientBuilder {
+        LspClientBuilder::default()
+    }
+
+    /// Workspace folders the server was initialized with; the first is the root
+    pub fn workspace_folders(&self) -> &[PathBuf] {
+        &self.workspace_folders
+    }
+
+    /// Open the transport and spawn the I/O tasks talking to the server
+    async fn connect(&self) -> Result<()> {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let (stdout, mut stdin): (BoxedReader, BoxedWriter) = match &self.transport {
+            Transport::Stdio { command, args } => {
+                let mut child = Command::new(command)
+                    .args(args)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .stderr(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .with_context(|| format!("Failed to spawn {}", command))?;
+
+                let stdin = child.stdin.take().context("Failed to open stdin")?;
+                let stdout = child.stdout.take().context("Failed to open stdout")?;
+                let stderr = child.stderr.take().context("Failed to open stderr")?;
+
+                // Stderr logger: only surfaced when tracing full payloads
+                let trace_clone = self.trace.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stderr).lines();
+                    while let Ok(Some(line)) = reader.next_line().await {
+                        if TraceLevel::from_u8(trace_clone.load(Ordering::Relaxed)) == TraceLevel::Full {
+                            tracing::debug!(target: "graphslice::lsp::stderr", "{}", line);
+                        }
+                    }
+                });
+
+                // Exit watcher: fail everything in flight so callers can restart and replay
+                let lost = self.connection_lost(generation);
+                tokio::spawn(async move {
+                    let status = match child.wait().await {
+                        Ok(status) => status.to_string(),
+                        Err(e) => e.to_string(),
+                    };
+                    lost(&status);
+                });
+
+                (Box::new(stdout), Box::new(stdin))
             }
-        });
+            Transport::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)
+                    .await
+                    .with_context(|| format!("Failed to connect to LSP server at {}", addr))?;
+                let (read_half, write_half) = stream.into_split();
+                (Box::new(read_half), Box::new(write_half))
+            }
+            #[cfg(unix)]
+            Transport::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(path)
+                    .await
+                    .with_context(|| format!("Failed to connect to LSP socket {}", path.display()))?;
+                let (read_half, write_half) = stream.into_split();
+                (Box::new(read_half), Box::new(write_half))
+            }
+        };
+
+        let (writer_tx, mut writer_rx) = mpsc::unbounded_channel::<String>();
 
         // Writer task
         tokio::spawn(async move {
             while let Some(msg) = writer_rx.recv().await {
-                // eprintln!("--> LSP: {}", msg);
                 let content = format!("Content-Length: {}\r\n\r\n{}", msg.len(), msg);
                 if stdin.write_all(content.as_bytes()).await.is_err() {
                     break;
@@ -59,8 +422,16 @@ impl LspClient {
         });
 
         // Reader task
-        let pending_requests_clone = pending_requests.clone();
-        let diagnostics_clone = diagnostics.clone();
+        let pending_requests_clone = self.pending_requests.clone();
+        let diagnostics_clone = self.diagnostics.clone();
+        let diagnostics_tx = self.diagnostics_tx.clone();
+        // Work reported by a previous server process won't end
+        self.progress.send_replace(HashSet::new());
+        let progress = self.progress.clone();
+        let handlers_clone = self.request_handlers.clone();
+        let response_tx = writer_tx.clone();
+        let lost = self.connection_lost(generation);
+        let trace_clone = self.trace.clone();
         tokio::spawn(async move {
             let mut reader = BufReader::new(stdout);
 
@@ -70,7 +441,9 @@ impl LspClient {
                 loop {
                     let mut line = String::new();
                     if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
-                        return; // EOF
+                        // EOF: the server went away (socket closed or process died)
+                        lost("connection closed");
+                        return;
                     }
 
                     if line == "\r\n" {
@@ -86,69 +459,255 @@ impl LspClient {
                 if content_length > 0 {
                     let mut buffer = vec![0; content_length];
                     if reader.read_exact(&mut buffer).await.is_err() {
-                        break;
+                        lost("connection closed");
+                        return;
                     }
 
                     if let Ok(val) = serde_json::from_slice::<Value>(&buffer) {
-                        // eprintln!("<-- LSP: {}", serde_json::to_string(&val).unwrap_or_default());
-                        if let Some(id) = val.get("id").and_then(|i| i.as_i64()) {
-                            // Response
-                            let mut requests = pending_requests_clone.lock().unwrap();
-                            if let Some(tx) = requests.remove(&id) {
-                                if let Some(error) = val.get("error") {
-                                    let _ = tx.send(Err(anyhow!("LSP Error: {}", error)));
-                                } else if let Some(result) = val.get("result") {
-                                    let _ = tx.send(Ok(result.clone()));
-                                } else {
-                                    // Some responses might be null result for success
-                                    let _ = tx.send(Ok(Value::Null));
+                        let method = val.get("method").and_then(|m| m.as_str());
+                        if let Some(method) = method {
+                            // Responses are traced by the requesting side, which knows the latency
+                            let level = TraceLevel::from_u8(trace_clone.load(Ordering::Relaxed));
+                            trace_message(level, "<--", method, &String::from_utf8_lossy(&buffer), None);
+                        }
+                        match (val.get("id"), method) {
+                            (Some(id), Some(method)) => {
+                                // Request from server: it expects a response with the same id
+                                let params = val.get("params").cloned().unwrap_or(Value::Null);
+                                let handler = handlers_clone.lock().unwrap().get(method).cloned();
+                                let response = match answer_server_request(handler.as_ref(), method, &params) {
+                                    Ok(result) => serde_json::json!({
+                                        "jsonrpc": "2.0",
+                                        "id": id,
+                                        "result": result,
+                                    }),
+                                    Err((code, message)) => serde_json::json!({
+                                        "jsonrpc": "2.0",
+                                        "id": id,
+                                        "error": { "code": code, "message": message },
+                                    }),
+                                };
+
+                                if let Ok(msg) = serde_json::to_string(&response) {
+                                    let _ = response_tx.send(msg);
+                                }
+                            }
+                            (Some(id), None) => {
+                                // Response
+                                let Some(id) = id.as_i64() else { continue };
+                                let mut requests = pending_requests_clone.lock().unwrap();
+                                if let Some(tx) = requests.remove(&id) {
+                                    if let Some(error) = val.get("error") {
+                                        let _ = tx.send(Err(anyhow!("LSP Error: {}", error)));
+                                    } else if let Some(result) = val.get("result") {
+                                        let _ = tx.send(Ok(result.clone()));
+                                    } else {
+                                        // Some responses might be null result for success
+                                        let _ = tx.send(Ok(Value::Null));
+                                    }
                                 }
                             }
-                        } else {
-                            // Notification or Request from server
-                            if let Some(method) = val.get("method").and_then(|m| m.as_str())
-                                && method == "textDocument/publishDiagnostics"
+                            (None, Some(method)) => {
+                                // Notification from server
+                                if method == "textDocument/publishDiagnostics"
                                     && let Some(params) = val.get("params")
                                         && let Ok(diag_params) = serde_json::from_value::<PublishDiagnosticsParams>(params.clone()) {
                                             let mut guard = diagnostics_clone.lock().unwrap();
-                                            guard.insert(diag_params.uri, diag_params.diagnostics);
+                                            guard.insert(diag_params.uri.clone(), diag_params.diagnostics.clone());
+                                            // No subscribers is fine
+                                            let _ = diagnostics_tx.send((diag_params.uri, diag_params.diagnostics));
                                         }
+                                if method == "$/progress"
+                                    && let Some(params) = val.get("params")
+                                    && let Some(token) = params.get("token")
+                                {
+                                    match params.pointer("/value/kind").and_then(|kind| kind.as_str()) {
+                                        Some("begin") => progress.send_modify(|active| {
+                                            active.insert(token.to_string());
+                                        }),
+                                        Some("end") => progress.send_modify(|active| {
+                                            active.remove(&token.to_string());
+                                        }),
+                                        _ => {}
+                                    }
+                                }
+                            }
+                            (None, None) => {}
                         }
                     }
                 }
             }
         });
 
-        let client = Self {
-            writer_tx,
-            pending_requests,
-            next_id: Arc::new(Mutex::new(0)),
-            diagnostics,
-        };
+        *self.writer_tx.lock().unwrap() = writer_tx;
+        self.alive.store(true, Ordering::SeqCst);
+
+        Ok(())
+    }
 
-        // Initialize
-        let root_url = Url::from_file_path(&workspace_root).map_err(|_| anyhow!("Invalid path: {}", workspace_root.display()))?;
-        let root_uri = Uri::from_str(root_url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+    /// Marks connection `generation` dead and fails everything in flight, unless a newer
+    /// connection has replaced it in the meantime
+    fn connection_lost(&self, generation: usize) -> impl Fn(&str) + Send + use<> {
+        let (current, alive, pending_requests) = (self.generation.clone(), self.alive.clone(), self.pending_requests.clone());
+        move |status: &str| {
+            if current.load(Ordering::SeqCst) == generation {
+                alive.store(false, Ordering::SeqCst);
+                fail_pending(&pending_requests, status);
+            }
+        }
+    }
+
+    /// Run the initialize handshake and remember what the server can do
+    async fn initialize(&self) -> Result<()> {
+        let mut folders = Vec::new();
+        for folder in &self.workspace_folders {
+            let url = Url::from_file_path(folder).map_err(|_| anyhow!("Invalid path: {}", folder.display()))?;
+            let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+            folders.push(WorkspaceFolder {
+                uri,
+                name: folder.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            });
+        }
 
         #[allow(deprecated)]
         let init_params = InitializeParams {
-            root_uri: Some(root_uri.clone()),
-            workspace_folders: Some(vec![WorkspaceFolder {
-                uri: root_uri,
-                name: workspace_root.file_name().unwrap_or_default().to_string_lossy().to_string(),
-            }]),
-            capabilities: ClientCapabilities::default(),
+            root_uri: folders.first().map(|folder| folder.uri.clone()),
+            workspace_folders: Some(folders),
+            initialization_options: self.initialization_options.clone(),
+            capabilities: ClientCapabilities {
+                // Prefer byte offsets, which match tree-sitter columns directly
+                general: Some(GeneralClientCapabilities {
+                    position_encodings: Some(vec![PositionEncodingKind::UTF8, PositionEncodingKind::UTF16]),
+                    ..Default::default()
+                }),
+                // Lets `wait_until_idle` see when indexing is done
+                window: Some(WindowClientCapabilities {
+                    work_done_progress: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
             ..Default::default()
         };
 
-        // Wait for initialize response
-        client.request("initialize", init_params).await?;
-        client.notify("initialized", serde_json::json!({})).await?;
+        // Sent without replay: a crash during initialize is reported to the caller
+        let response = self.send_raw("initialize", &serde_json::to_value(init_params)?).await?;
+        let init_result: InitializeResult = serde_json::from_value(response)
+            .context("Failed to parse initialize response")?;
+        *self.capabilities.lock().unwrap() = init_result.capabilities;
+        self.notify("initialized", serde_json::json!({})).await?;
 
-        Ok(client)
+        Ok(())
     }
 
-    /// Send LSP request and get response
+    /// Restart a crashed server, re-initialize it and re-open tracked documents
+    async fn recover(&self) -> Result<()> {
+        let _guard = self.restart_lock.lock().await;
+
+        // Another request already brought the server back up
+        if self.alive.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(anyhow!("LSP client was shut down"));
+        }
+
+        let restarts = self.restarts.fetch_add(1, Ordering::SeqCst) + 1;
+        if restarts > MAX_SERVER_RESTARTS {
+            return Err(anyhow!("LSP server crashed too often ({} restarts), giving up", MAX_SERVER_RESTARTS));
+        }
+
+        eprintln!("⚠️  LSP server exited. Restarting (attempt {}/{})...", restarts, MAX_SERVER_RESTARTS);
+        self.connect().await?;
+        self.initialize().await?;
+
+        let documents: Vec<(PathBuf, String)> = self
+            .open_documents
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(path, text)| (path.clone(), text.clone()))
+            .collect();
+        for (path, text) in documents {
+            self.send_did_open(&path, text).await?;
+        }
+
+        // Re-opened documents start over at version 0
+        for version in self.overlays.lock().unwrap().values_mut() {
+            *version = 0;
+        }
+
+        Ok(())
+    }
+
+    /// Send a single request and wait for its response, without retries
+    async fn send_raw(&self, method: &str, params: &Value) -> Result<Value> {
+        let _permit = self
+            .in_flight
+            .acquire()
+            .await
+            .map_err(|_| anyhow!("LSP request limiter closed"))?;
+
+        let id = {
+            let mut guard = self.next_id.lock().unwrap();
+            *guard += 1;
+            *guard
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending_requests.lock().unwrap().insert(id, tx);
+
+        // The exit watcher may already have drained pending requests
+        if !self.alive.load(Ordering::SeqCst) {
+            self.pending_requests.lock().unwrap().remove(&id);
+            return Err(ServerExited("not running".to_string()).into());
+        }
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+
+        let payload = serde_json::to_string(&request)?;
+        let level = self.trace_level();
+        trace_message(level, "-->", method, &payload, None);
+        let started = Instant::now();
+
+        let sent = self.writer_tx.lock().unwrap().send(payload);
+        if sent.is_err() {
+            self.pending_requests.lock().unwrap().remove(&id);
+            return Err(ServerExited("LSP writer closed".to_string()).into());
+        }
+
+        let response = match &self.cancel {
+            None => rx.await.context("LSP client dropped or response failed")?,
+            Some(cancel) => tokio::select! {
+                response = rx => response.context("LSP client dropped or response failed")?,
+                _ = cancel.cancelled() => {
+                    // Drop our side and tell the server to stop working on it
+                    self.pending_requests.lock().unwrap().remove(&id);
+                    self.notify("$/cancelRequest", CancelParams { id: cancel_id(id) }).await?;
+                    Err(RequestCancelled(method.to_string()).into())
+                }
+            },
+        };
+
+        if level != TraceLevel::Off {
+            let payload = match &response {
+                Ok(value) => value.to_string(),
+                Err(e) => e.to_string(),
+            };
+            trace_message(level, "<--", method, &payload, Some(started.elapsed()));
+        }
+
+        response
+    }
+
+    /// Send LSP request and get response.
+    /// Retries on "content modified" and replays the request if the server crashed.
     async fn request<T: serde::Serialize>(
         &self,
         method: &str,
@@ -159,34 +718,20 @@ impl LspClient {
 
         loop {
             attempts += 1;
-            let id = {
-                let mut guard = self.next_id.lock().unwrap();
-                *guard += 1;
-                *guard
-            };
-
-            let (tx, rx) = oneshot::channel();
-            self.pending_requests.lock().unwrap().insert(id, tx);
-
-            let request = serde_json::json!({
-                "jsonrpc": "2.0",
-                "id": id,
-                "method": method,
-                "params": params_value,
-            });
 
-            self.writer_tx.send(serde_json::to_string(&request)?)
-                .map_err(|_| anyhow!("LSP writer closed"))?;
-
-            // eprintln!("Sending request (attempt {}): {}", attempts, method);
-
-            match rx.await.context("LSP client dropped or response failed")? {
+            match self.send_raw(method, &params_value).await {
                 Ok(val) => return Ok(val),
+                Err(e) if e.downcast_ref::<ServerExited>().is_some() => {
+                    self.recover()
+                        .await
+                        .with_context(|| format!("{} failed: {}", method, e))?;
+                    continue;
+                }
                 Err(e) => {
                     let err_str = e.to_string();
                     // Check for "content modified" error (-32801)
                     if attempts < 5 && (err_str.contains("content modified") || err_str.contains("-32801")) {
-                        // eprintln!("LSP 'content modified' error, retrying in {}ms...", 500 * attempts);
+                        tracing::debug!(target: "graphslice::lsp", method, attempts, "content modified, retrying");
                         tokio::time::sleep(std::time::Duration::from_millis(500 * attempts as u64)).await;
                         continue;
                     }
@@ -196,6 +741,124 @@ impl LspClient {
         }
     }
 
+    /// Handle to the same server whose requests are abandoned once `token` is cancelled.
+    /// Cancelled requests send `$/cancelRequest` and fail with `RequestCancelled`.
+    pub fn with_cancellation(&self, token: CancellationToken) -> Self {
+        Self {
+            cancel: Some(token),
+            ..self.clone()
+        }
+    }
+
+    /// Handle to the same server allowing at most `max` concurrent requests.
+    /// Clones of the returned handle share the new limit.
+    pub fn with_max_in_flight(&self, max: usize) -> Self {
+        Self {
+            in_flight: Arc::new(Semaphore::new(max.max(1))),
+            ..self.clone()
+        }
+    }
+
+    /// Change the LSP traffic trace level; applies to all clones of this client
+    pub fn set_trace(&self, level: TraceLevel) {
+        self.trace.store(level as u8, Ordering::Relaxed);
+    }
+
+    fn trace_level(&self) -> TraceLevel {
+        TraceLevel::from_u8(self.trace.load(Ordering::Relaxed))
+    }
+
+    /// Capabilities reported by the server in its initialize response
+    pub fn capabilities(&self) -> ServerCapabilities {
+        self.capabilities.lock().unwrap().clone()
+    }
+
+    /// Encoding of `Position::character` negotiated during initialize
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.capabilities
+            .lock()
+            .unwrap()
+            .position_encoding
+            .as_ref()
+            .map(PositionEncoding::from_kind)
+            .unwrap_or_default()
+    }
+
+    /// Check whether the server advertised support for a feature
+    pub fn supports(&self, feature: LspFeature) -> bool {
+        let caps = self.capabilities.lock().unwrap();
+        match feature {
+            LspFeature::References => one_of_enabled(&caps.references_provider),
+            LspFeature::Definition => one_of_enabled(&caps.definition_provider),
+            LspFeature::CallHierarchy => matches!(
+                caps.call_hierarchy_provider,
+                Some(CallHierarchyServerCapability::Simple(true))
+                    | Some(CallHierarchyServerCapability::Options(_))
+            ),
+            LspFeature::FoldingRange => matches!(
+                caps.folding_range_provider,
+                Some(FoldingRangeProviderCapability::Simple(true))
+                    | Some(FoldingRangeProviderCapability::FoldingProvider(_))
+                    | Some(FoldingRangeProviderCapability::Options(_))
+            ),
+            LspFeature::SelectionRange => matches!(
+                caps.selection_range_provider,
+                Some(SelectionRangeProviderCapability::Simple(true))
+                    | Some(SelectionRangeProviderCapability::Options(_))
+                    | Some(SelectionRangeProviderCapability::RegistrationOptions(_))
+            ),
+            LspFeature::Implementation => matches!(
+                caps.implementation_provider,
+                Some(ImplementationProviderCapability::Simple(true)) | Some(ImplementationProviderCapability::Options(_))
+            ),
+            LspFeature::ParentModule => caps
+                .experimental
+                .as_ref()
+                .and_then(|experimental| experimental.get("parentModule"))
+                .and_then(|enabled| enabled.as_bool())
+                .unwrap_or(false),
+        }
+    }
+
+    /// Ask the server to shut down and exit. The client is unusable afterwards.
+    pub async fn shutdown(&self) -> Result<()> {
+        self.closed.store(true, Ordering::SeqCst);
+        if !self.alive.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.send_raw("shutdown", &Value::Null).await?;
+        self.notify("exit", Value::Null).await
+    }
+
+    /// Register a handler for a server-to-client request method.
+    /// Overrides the built-in default response for that method, if any.
+    pub fn on_request<F>(&self, method: &str, handler: F)
+    where
+        F: Fn(&Value) -> Result<Value> + Send + Sync + 'static,
+    {
+        self.request_handlers
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), Arc::new(handler));
+    }
+
+    /// Send any typed LSP request, including ones graphslice doesn't wrap yet.
+    /// Gets the same retry and crash-replay handling as the built-in wrappers.
+    ///
+    /// ```ignore
+    /// let hover = client.send_request::<lsp_types::request::HoverRequest>(params).await?;
+    /// ```
+    pub async fn send_request<R: lsp_types::request::Request>(&self, params: R::Params) -> Result<R::Result> {
+        let response = self.request(R::METHOD, params).await?;
+        serde_json::from_value(response)
+            .with_context(|| format!("Failed to parse {} response", R::METHOD))
+    }
+
+    /// Send any typed LSP notification
+    pub async fn send_notification<N: lsp_types::notification::Notification>(&self, params: N::Params) -> Result<()> {
+        self.notify(N::METHOD, params).await
+    }
+
     /// Send LSP notification (no response expected)
     pub async fn notify<T: serde::Serialize>(
         &self,
@@ -208,13 +871,24 @@ impl LspClient {
             "params": params,
         });
 
-        self.writer_tx.send(serde_json::to_string(&notification)?)
+        let payload = serde_json::to_string(&notification)?;
+        trace_message(self.trace_level(), "-->", method, &payload, None);
+
+        self.writer_tx.lock().unwrap().send(payload)
             .map_err(|_| anyhow!("LSP writer closed"))?;
         Ok(())
     }
 
     /// Notify server that a file was opened
     pub async fn did_open(&self, file_path: &PathBuf, text: String) -> Result<()> {
+        self.open_documents
+            .lock()
+            .unwrap()
+            .insert(file_path.clone(), text.clone());
+        self.send_did_open(file_path, text).await
+    }
+
+    async fn send_did_open(&self, file_path: &PathBuf, text: String) -> Result<()> {
         let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
         let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
 
@@ -230,12 +904,176 @@ impl LspClient {
         self.notify("textDocument/didOpen", params).await
     }
 
+    /// Serve `text` as the content of `file_path` instead of what is on disk
+    /// (unsaved editor buffers, synthesized code). Calling it again replaces the text.
+    /// A document the server already has open is changed rather than opened again.
+    pub async fn open_overlay(&self, file_path: &PathBuf, text: String) -> Result<()> {
+        let version = self.overlays.lock().unwrap().get(file_path).copied();
+        let already_open = self.open_documents.lock().unwrap().contains_key(file_path);
+        let version = match version {
+            Some(version) => version + 1,
+            // Opened by `did_open`, always at version 0
+            None if already_open => 1,
+            None => {
+                self.overlays.lock().unwrap().insert(file_path.clone(), 0);
+                return self.did_open(file_path, text).await;
+            }
+        };
+
+        self.overlays.lock().unwrap().insert(file_path.clone(), version);
+        self.open_documents
+            .lock()
+            .unwrap()
+            .insert(file_path.clone(), text.clone());
+
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier { uri, version },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text,
+            }],
+        };
+
+        self.notify("textDocument/didChange", params).await
+    }
+
+    /// Drop an overlay; the server falls back to the file on disk
+    pub async fn close_overlay(&self, file_path: &PathBuf) -> Result<()> {
+        if self.overlays.lock().unwrap().remove(file_path).is_none() {
+            return Ok(());
+        }
+        self.open_documents.lock().unwrap().remove(file_path);
+
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+
+        self.notify("textDocument/didClose", params).await
+    }
+
+    /// Text of an overlay document, if `file_path` has one
+    pub fn overlay_text(&self, file_path: &PathBuf) -> Option<String> {
+        if !self.overlays.lock().unwrap().contains_key(file_path) {
+            return None;
+        }
+        self.open_documents.lock().unwrap().get(file_path).cloned()
+    }
+
+    /// Files currently served from an overlay
+    pub fn overlay_files(&self) -> Vec<PathBuf> {
+        self.overlays.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Tell the server a file changed on disk
+    pub async fn notify_file_changed(&self, file_path: &Path) -> Result<()> {
+        self.notify_watched_files(vec![(file_path.to_path_buf(), FileChangeType::CHANGED)]).await
+    }
+
+    /// Tell the server a file was created on disk
+    pub async fn notify_file_created(&self, file_path: &Path) -> Result<()> {
+        self.notify_watched_files(vec![(file_path.to_path_buf(), FileChangeType::CREATED)]).await
+    }
+
+    /// Tell the server a file was deleted from disk
+    pub async fn notify_file_deleted(&self, file_path: &PathBuf) -> Result<()> {
+        self.open_documents.lock().unwrap().remove(file_path);
+        self.notify_watched_files(vec![(file_path.clone(), FileChangeType::DELETED)]).await
+    }
+
+    /// Send a batch of file events via `workspace/didChangeWatchedFiles`
+    pub async fn notify_watched_files(&self, changes: Vec<(PathBuf, FileChangeType)>) -> Result<()> {
+        let mut events = Vec::new();
+        for (file_path, typ) in changes {
+            let url = Url::from_file_path(&file_path).map_err(|_| anyhow!("Invalid file path"))?;
+            let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+            events.push(FileEvent { uri, typ });
+        }
+
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        self.notify("workspace/didChangeWatchedFiles", DidChangeWatchedFilesParams { changes: events }).await
+    }
+
+    /// Watch the workspace folders and forward Rust source and manifest changes
+    /// to the server. Watching stops when the returned handle is dropped.
+    #[cfg(feature = "watch")]
+    pub fn watch_workspace(&self) -> Result<WorkspaceWatcher> {
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel::<(PathBuf, FileChangeType)>();
+
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else { return };
+            let typ = match event.kind {
+                EventKind::Create(_) => FileChangeType::CREATED,
+                EventKind::Modify(_) => FileChangeType::CHANGED,
+                EventKind::Remove(_) => FileChangeType::DELETED,
+                _ => return,
+            };
+            for path in event.paths {
+                let relevant = path.extension().and_then(|e| e.to_str()) == Some("rs")
+                    || path.file_name().and_then(|n| n.to_str()) == Some("Cargo.toml");
+                let in_target = path.components().any(|c| c.as_os_str() == "target");
+                if relevant && !in_target {
+                    let _ = event_tx.send((path, typ));
+                }
+            }
+        })?;
+
+        for folder in &self.workspace_folders {
+            watcher
+                .watch(folder, RecursiveMode::Recursive)
+                .with_context(|| format!("Failed to watch {}", folder.display()))?;
+        }
+
+        // Forward events until the watcher (and with it the sender) is dropped
+        let client = self.clone();
+        tokio::spawn(async move {
+            while let Some(change) = event_rx.recv().await {
+                let mut batch = vec![change];
+                while let Ok(change) = event_rx.try_recv() {
+                    batch.push(change);
+                }
+                for (path, typ) in &batch {
+                    if *typ == FileChangeType::DELETED {
+                        client.open_documents.lock().unwrap().remove(path);
+                    }
+                }
+                if client.notify_watched_files(batch).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(WorkspaceWatcher { _watcher: watcher })
+    }
+
     /// Get all references to symbol at position
     pub async fn get_references(
         &self,
         file_path: &PathBuf,
         line: u32,
         character: u32,
+    ) -> Result<Vec<Location>> {
+        self.get_references_with(file_path, line, character, &ReferenceOptions::default()).await
+    }
+
+    /// Get references to symbol at position, filtered according to `options`
+    pub async fn get_references_with(
+        &self,
+        file_path: &PathBuf,
+        line: u32,
+        character: u32,
+        options: &ReferenceOptions,
     ) -> Result<Vec<Location>> {
         let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
         let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
@@ -248,7 +1086,7 @@ impl LspClient {
             work_done_progress_params: WorkDoneProgressParams::default(),
             partial_result_params: PartialResultParams::default(),
             context: ReferenceContext {
-                include_declaration: true,
+                include_declaration: options.include_declaration,
             },
         };
 
@@ -258,7 +1096,7 @@ impl LspClient {
         let locations: Vec<Location> = serde_json::from_value(response)
             .unwrap_or_default();
 
-        Ok(locations)
+        Ok(options.apply(locations))
     }
 
     /// Get definition of symbol at position
@@ -281,21 +1119,30 @@ impl LspClient {
         };
 
         let response = self.request("textDocument/definition", params).await?;
+        Ok(goto_locations(response))
+    }
 
-        // Definition response can be Location, []Location, or null
-        // rust-analyzer usually returns []Location
-        if response.is_null() {
-            return Ok(Vec::new());
-        }
-
-        if let Ok(location) = serde_json::from_value::<Location>(response.clone()) {
-            return Ok(vec![location]);
-        }
+    /// Implementations of the trait or trait method at position
+    pub async fn get_implementations(
+        &self,
+        file_path: &PathBuf,
+        line: u32,
+        character: u32,
+    ) -> Result<Vec<Location>> {
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
 
-        let locations: Vec<Location> = serde_json::from_value(response)
-            .unwrap_or_default();
+        let params = GotoDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position: Position { line, character },
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
 
-        Ok(locations)
+        let response = self.request("textDocument/implementation", params).await?;
+        Ok(goto_locations(response))
     }
 
     /// Prepare call hierarchy at position
@@ -352,6 +1199,168 @@ impl LspClient {
         Ok(calls)
     }
 
+    /// Get folding ranges (server-computed item boundaries) for a file
+    pub async fn get_folding_ranges(&self, file_path: &PathBuf) -> Result<Vec<FoldingRange>> {
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let params = FoldingRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let response = self.request("textDocument/foldingRange", params).await?;
+
+        if response.is_null() {
+            return Ok(Vec::new());
+        }
+
+        let ranges: Vec<FoldingRange> = serde_json::from_value(response)
+            .unwrap_or_default();
+
+        Ok(ranges)
+    }
+
+    /// Get the selection range chain (innermost first, linked via `parent`) at a position
+    pub async fn get_selection_range(
+        &self,
+        file_path: &PathBuf,
+        line: u32,
+        character: u32,
+    ) -> Result<Option<SelectionRange>> {
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let params = SelectionRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            positions: vec![Position { line, character }],
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let response = self.request("textDocument/selectionRange", params).await?;
+
+        if response.is_null() {
+            return Ok(None);
+        }
+
+        let ranges: Vec<SelectionRange> = serde_json::from_value(response)
+            .unwrap_or_default();
+
+        Ok(ranges.into_iter().next())
+    }
+
+    /// Expand the macro invocation at position (rust-analyzer extension).
+    /// Returns `None` if there is no macro call there.
+    pub async fn expand_macro(
+        &self,
+        file_path: &PathBuf,
+        line: u32,
+        character: u32,
+    ) -> Result<Option<ExpandedMacro>> {
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let params = ExpandMacroParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position { line, character },
+        };
+
+        self.send_request::<ExpandMacro>(params).await
+    }
+
+    /// Locations of the `mod` declarations owning the file at position
+    /// (rust-analyzer's `experimental/parentModule`)
+    pub async fn parent_module(
+        &self,
+        file_path: &PathBuf,
+        line: u32,
+        character: u32,
+    ) -> Result<Vec<Location>> {
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position: Position { line, character },
+        };
+
+        let response = self.request("experimental/parentModule", params).await?;
+
+        if response.is_null() {
+            return Ok(Vec::new());
+        }
+
+        // Either Location[] or LocationLink[] depending on client capabilities
+        if let Ok(locations) = serde_json::from_value::<Vec<Location>>(response.clone()) {
+            return Ok(locations);
+        }
+
+        let links: Vec<LocationLink> = serde_json::from_value(response)
+            .unwrap_or_default();
+
+        Ok(links
+            .into_iter()
+            .map(|link| Location {
+                uri: link.target_uri,
+                range: link.target_selection_range,
+            })
+            .collect())
+    }
+
+    /// Crate graph of the workspace in Graphviz DOT form (rust-analyzer's `rust-analyzer/viewCrateGraph`).
+    /// `full` includes sysroot and dependency crates.
+    pub async fn view_crate_graph(&self, full: bool) -> Result<String> {
+        let response = self.request("rust-analyzer/viewCrateGraph", serde_json::json!({ "full": full })).await?;
+        response
+            .as_str()
+            .map(|dot| dot.to_string())
+            .ok_or_else(|| anyhow!("Invalid viewCrateGraph response"))
+    }
+
+    /// Stream of every `publishDiagnostics` update received from now on
+    pub fn subscribe_diagnostics(&self) -> broadcast::Receiver<(Uri, Vec<Diagnostic>)> {
+        self.diagnostics_tx.subscribe()
+    }
+
+    /// Wait for the next diagnostics published for `file_path` on `updates`.
+    /// Subscribe before triggering analysis (e.g. `did_open`) so the update can't be missed.
+    /// Returns `None` on timeout.
+    pub async fn wait_for_diagnostics(
+        &self,
+        updates: &mut broadcast::Receiver<(Uri, Vec<Diagnostic>)>,
+        file_path: &PathBuf,
+        timeout: Duration,
+    ) -> Result<Option<Vec<Diagnostic>>> {
+        let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
+        let uri = Uri::from_str(url.as_str()).map_err(|e| anyhow!("Failed to create URI: {}", e))?;
+
+        let wait = async {
+            loop {
+                match updates.recv().await {
+                    Ok((updated, diagnostics)) if updated == uri => return Some(diagnostics),
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        };
+
+        Ok(tokio::time::timeout(timeout, wait).await.ok().flatten())
+    }
+
+    /// Wait until the server has no work-done progress running, such as rust-analyzer's
+    /// initial indexing. Work that hasn't started yet gets a short grace period.
+    /// Returns `false` if the server was still busy after `timeout`.
+    pub async fn wait_until_idle(&self, timeout: Duration) -> bool {
+        let mut progress = self.progress.subscribe();
+        let wait = async {
+            let _ = tokio::time::timeout(PROGRESS_START_GRACE, progress.wait_for(|active| !active.is_empty())).await;
+            progress.wait_for(|active| active.is_empty()).await.is_ok()
+        };
+        tokio::time::timeout(timeout, wait).await.unwrap_or(false)
+    }
+
     /// Get diagnostics for a file
     pub fn get_diagnostics(&self, file_path: &PathBuf) -> Result<Vec<Diagnostic>> {
         let url = Url::from_file_path(file_path).map_err(|_| anyhow!("Invalid file path"))?;
@@ -361,3 +1370,291 @@ impl LspClient {
         Ok(guard.get(&uri).cloned().unwrap_or_default())
     }
 }
+
+/// `true` if a `bool | Options` capability is enabled
+fn one_of_enabled<T>(capability: &Option<OneOf<bool, T>>) -> bool {
+    matches!(capability, Some(OneOf::Left(true)) | Some(OneOf::Right(_)))
+}
+
+/// Locations in a definition/implementation response, which can be
+/// Location, []Location, []LocationLink, or null. rust-analyzer usually returns []Location.
+fn goto_locations(response: serde_json::Value) -> Vec<Location> {
+    if response.is_null() {
+        return Vec::new();
+    }
+    if let Ok(location) = serde_json::from_value::<Location>(response.clone()) {
+        return vec![location];
+    }
+    if let Ok(locations) = serde_json::from_value::<Vec<Location>>(response.clone()) {
+        return locations;
+    }
+    serde_json::from_value::<Vec<LocationLink>>(response)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|link| Location { uri: link.target_uri, range: link.target_selection_range })
+        .collect()
+}
+
+/// Emit one traced LSP message. `payload` is the raw JSON text.
+fn trace_message(level: TraceLevel, direction: &str, method: &str, payload: &str, latency: Option<Duration>) {
+    let latency_ms = latency.map(|l| l.as_millis() as u64);
+    match level {
+        TraceLevel::Off => {}
+        TraceLevel::Summary => {
+            tracing::debug!(target: "graphslice::lsp", direction, method, bytes = payload.len(), latency_ms, "lsp message");
+        }
+        TraceLevel::Full => {
+            tracing::debug!(target: "graphslice::lsp", direction, method, bytes = payload.len(), latency_ms, payload, "lsp message");
+        }
+    }
+}
+
+/// Request id as `$/cancelRequest` takes it; ids beyond `i32` go as strings rather than wrap
+fn cancel_id(id: i64) -> NumberOrString {
+    match i32::try_from(id) {
+        Ok(id) => NumberOrString::Number(id),
+        Err(_) => NumberOrString::String(id.to_string()),
+    }
+}
+
+/// Fail every in-flight request after the server died
+fn fail_pending(pending_requests: &PendingRequests, status: &str) {
+    let drained: Vec<_> = pending_requests.lock().unwrap().drain().collect();
+    for (_, tx) in drained {
+        let _ = tx.send(Err(ServerExited(status.to_string()).into()));
+    }
+}
+
+/// Result for a request the server sent, or the JSON-RPC error code and message to
+/// answer it with: `INTERNAL_ERROR` when the registered handler fails, `METHOD_NOT_FOUND`
+/// when there is neither a handler nor a default answer
+fn answer_server_request(handler: Option<&RequestHandler>, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+    match handler {
+        Some(handler) => handler(params).map_err(|e| (INTERNAL_ERROR, e.to_string())),
+        None => default_server_response(method, params).map_err(|e| (METHOD_NOT_FOUND, e.to_string())),
+    }
+}
+
+/// Default answers for requests the server sends to the client.
+/// Servers such as rust-analyzer block until these are answered.
+fn default_server_response(method: &str, params: &Value) -> Result<Value> {
+    match method {
+        "workspace/configuration" => {
+            // One entry per requested item; null means "use your defaults"
+            let count = params
+                .get("items")
+                .and_then(|items| items.as_array())
+                .map(|items| items.len())
+                .unwrap_or(0);
+            Ok(Value::Array(vec![Value::Null; count]))
+        }
+        "window/workDoneProgress/create"
+        | "client/registerCapability"
+        | "client/unregisterCapability"
+        | "workspace/semanticTokens/refresh"
+        | "workspace/inlayHint/refresh"
+        | "workspace/codeLens/refresh"
+        | "workspace/diagnostic/refresh" => Ok(Value::Null),
+        _ => Err(anyhow!("Unhandled server request: {}", method)),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Answers every request with an empty success until `hang_up` fires or its sender drops
+    pub(crate) async fn serve_fake(stream: TcpStream, hang_up: oneshot::Receiver<()>) {
+        let (read_half, mut write_half) = stream.into_split();
+        let mut reader = BufReader::new(read_half);
+        let serve = async move {
+            loop {
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(len) = line.strip_prefix("Content-Length: ") {
+                        content_length = len.trim().parse().unwrap();
+                    }
+                }
+                let mut buffer = vec![0; content_length];
+                reader.read_exact(&mut buffer).await.unwrap();
+                let message: Value = serde_json::from_slice(&buffer).unwrap();
+                let (Some(id), Some(method)) = (message.get("id"), message.get("method")) else {
+                    continue;
+                };
+                let result = match method.as_str() {
+                    Some("initialize") => serde_json::json!({ "capabilities": {} }),
+                    _ => Value::Null,
+                };
+                let response = serde_json::json!({ "jsonrpc": "2.0", "id": id, "result": result }).to_string();
+                let framed = format!("Content-Length: {}\r\n\r\n{}", response.len(), response);
+                if write_half.write_all(framed.as_bytes()).await.is_err() {
+                    return;
+                }
+            }
+        };
+        tokio::select! {
+            _ = serve => {}
+            _ = hang_up => {}
+        }
+    }
+
+    fn location(path: &str, line: u32) -> Location {
+        let position = Position::new(line, 0);
+        Location { uri: Uri::from_str(&format!("file://{}", path)).unwrap(), range: Range::new(position, position) }
+    }
+
+    #[test]
+    fn test_default_server_responses() {
+        let params = serde_json::json!({ "items": [{ "section": "rust-analyzer" }, { "section": "files" }] });
+        assert_eq!(default_server_response("workspace/configuration", &params).unwrap(), serde_json::json!([null, null]));
+        assert_eq!(default_server_response("workspace/configuration", &Value::Null).unwrap(), serde_json::json!([]));
+        assert_eq!(default_server_response("client/registerCapability", &Value::Null).unwrap(), Value::Null);
+        assert!(default_server_response("window/showDocument", &Value::Null).is_err());
+    }
+
+    #[test]
+    fn test_server_request_error_codes() {
+        let failing: RequestHandler = Arc::new(|_| Err(anyhow!("handler failed")));
+        let answer = answer_server_request(Some(&failing), "workspace/configuration", &Value::Null);
+        assert_eq!(answer, Err((INTERNAL_ERROR, "handler failed".to_string())));
+        assert_eq!(answer_server_request(None, "custom/unknown", &Value::Null).unwrap_err().0, METHOD_NOT_FOUND);
+        assert_eq!(answer_server_request(None, "window/workDoneProgress/create", &Value::Null), Ok(Value::Null));
+    }
+
+    #[test]
+    fn test_cancel_id_never_truncates() {
+        assert_eq!(cancel_id(7), NumberOrString::Number(7));
+        let large = i64::from(i32::MAX) + 1;
+        assert_eq!(cancel_id(large), NumberOrString::String(large.to_string()));
+    }
+
+    #[test]
+    fn test_reference_options_filter_and_cap() {
+        let locations = vec![location("/repo/src/a.rs", 1), location("/vendor/b.rs", 2), location("/repo/src/c.rs", 3)];
+
+        assert_eq!(ReferenceOptions::default().apply(locations.clone()).len(), 3);
+
+        let options = ReferenceOptions { path_allowlist: vec![PathBuf::from("/repo/src")], ..ReferenceOptions::default() };
+        let kept: Vec<u32> = options.apply(locations.clone()).iter().map(|l| l.range.start.line).collect();
+        assert_eq!(kept, vec![1, 3]);
+
+        let options = ReferenceOptions { max_results: Some(1), ..options };
+        let kept: Vec<u32> = options.apply(locations).iter().map(|l| l.range.start.line).collect();
+        assert_eq!(kept, vec![1]);
+    }
+
+    #[test]
+    fn test_goto_locations_flattens_every_response_shape() {
+        let single = location("/repo/src/a.rs", 1);
+        assert!(goto_locations(Value::Null).is_empty());
+        assert_eq!(goto_locations(serde_json::to_value(&single).unwrap()), vec![single.clone()]);
+        assert_eq!(goto_locations(serde_json::to_value(vec![&single, &single]).unwrap()).len(), 2);
+
+        // Links resolve to the target's selection range, i.e. the name
+        let link = LocationLink {
+            origin_selection_range: None,
+            target_uri: single.uri.clone(),
+            target_range: Range::new(Position::new(1, 0), Position::new(9, 1)),
+            target_selection_range: Range::new(Position::new(1, 7), Position::new(1, 11)),
+        };
+        let resolved = goto_locations(serde_json::to_value(vec![link]).unwrap());
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0].range.start, Position::new(1, 7));
+    }
+
+    #[tokio::test]
+    async fn test_supports_reads_server_capabilities() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (_keep, never) = oneshot::channel();
+            serve_fake(stream, never).await;
+        });
+        let client = LspClient::builder()
+            .workspace_folder(std::env::temp_dir())
+            .transport(Transport::Tcp(addr))
+            .connect()
+            .await
+            .unwrap();
+        assert!(!client.supports(LspFeature::References));
+
+        *client.capabilities.lock().unwrap() = ServerCapabilities {
+            references_provider: Some(OneOf::Left(true)),
+            definition_provider: Some(OneOf::Left(false)),
+            implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+            experimental: Some(serde_json::json!({ "parentModule": true })),
+            ..ServerCapabilities::default()
+        };
+        assert!(client.supports(LspFeature::References));
+        assert!(!client.supports(LspFeature::Definition));
+        assert!(client.supports(LspFeature::Implementation));
+        assert!(client.supports(LspFeature::ParentModule));
+        assert!(!client.supports(LspFeature::CallHierarchy));
+    }
+
+    #[tokio::test]
+    async fn test_replaced_connection_closing_leaves_new_one_alone() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let (hang_up, first_hang_up) = oneshot::channel();
+        tokio::spawn(async move {
+            let (first, _) = listener.accept().await.unwrap();
+            tokio::spawn(serve_fake(first, first_hang_up));
+            let (second, _) = listener.accept().await.unwrap();
+            let (_keep, never) = oneshot::channel();
+            serve_fake(second, never).await;
+        });
+
+        let client = LspClient::builder()
+            .workspace_folder(std::env::temp_dir())
+            .transport(Transport::Tcp(addr))
+            .connect()
+            .await
+            .unwrap();
+
+        // Reconnect as `recover` does, then let the old connection's reader see EOF
+        client.connect().await.unwrap();
+        hang_up.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert!(client.alive.load(Ordering::SeqCst));
+        client.request("graphslice/ping", Value::Null).await.unwrap();
+        assert_eq!(client.restarts.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_overlay_of_open_document_bumps_its_version() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (_keep, never) = oneshot::channel();
+            serve_fake(stream, never).await;
+        });
+        let client = LspClient::builder()
+            .workspace_folder(std::env::temp_dir())
+            .transport(Transport::Tcp(addr))
+            .connect()
+            .await
+            .unwrap();
+
+        let (opened, fresh) = (PathBuf::from("/src/lib.rs"), PathBuf::from("/src/new.rs"));
+        client.did_open(&opened, "fn a() {}".to_string()).await.unwrap();
+        client.open_overlay(&opened, "fn b() {}".to_string()).await.unwrap();
+        client.open_overlay(&fresh, "fn c() {}".to_string()).await.unwrap();
+        client.open_overlay(&fresh, "fn d() {}".to_string()).await.unwrap();
+
+        let overlays = client.overlays.lock().unwrap().clone();
+        assert_eq!(overlays, HashMap::from([(opened.clone(), 1), (fresh, 1)]));
+        assert_eq!(client.overlay_text(&opened).as_deref(), Some("fn b() {}"));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::lsp_client::{LspClient, LspClientBuilder};
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// Default cap on concurrently running language servers
+pub const DEFAULT_MAX_SERVERS: usize = 4;
+
+struct PooledClient {
+    root: PathBuf,
+    client: LspClient,
+    last_used: Instant,
+}
+
+/// One `LspClient` per workspace root, so a session can slice several unrelated
+/// repositories. Requests are routed by file path; when more than `max_servers`
+/// roots are active the least recently used server is shut down.
+#[derive(Clone)]
+pub struct LspPool {
+    template: LspClientBuilder,
+    max_servers: usize,
+    clients: Arc<Mutex<Vec<PooledClient>>>,
+}
+
+impl LspPool {
+    pub fn new(max_servers: usize) -> Self {
+        Self::with_template(LspClient::builder(), max_servers)
+    }
+
+    /// Pool whose servers are configured like `template` (transport, initializationOptions, ...).
+    /// Workspace folders on the template are ignored; each server gets its own root.
+    pub fn with_template(template: LspClientBuilder, max_servers: usize) -> Self {
+        Self {
+            template,
+            max_servers: max_servers.max(1),
+            clients: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Client responsible for `file`, starting a server for its workspace if needed
+    pub async fn client_for(&self, file: &Path) -> Result<LspClient> {
+        let root = workspace_root_for(file);
+        self.client_for_root(&root).await
+    }
+
+    /// Client for an explicit workspace root
+    pub async fn client_for_root(&self, root: &Path) -> Result<LspClient> {
+        if let Some(pooled) = self.clients.lock().await.iter_mut().find(|pooled| pooled.root == root) {
+            pooled.last_used = Instant::now();
+            return Ok(pooled.client.clone());
+        }
+
+        // Start the server unlocked, so lookups for warm roots don't wait on a cold start
+        let mut builder = self.template.clone();
+        builder.reset_workspace_folders();
+        let client = builder.workspace_folder(root.to_path_buf()).connect().await?;
+
+        let retired = {
+            let mut clients = self.clients.lock().await;
+
+            // Another caller started this root meanwhile; keep theirs
+            if let Some(pooled) = clients.iter_mut().find(|pooled| pooled.root == root) {
+                pooled.last_used = Instant::now();
+                let existing = pooled.client.clone();
+                drop(clients);
+                let _ = client.shutdown().await;
+                return Ok(existing);
+            }
+
+            // Make room by retiring the least recently used server
+            let mut retired = None;
+            if clients.len() >= self.max_servers
+                && let Some(oldest) = clients
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, pooled)| pooled.last_used)
+                    .map(|(index, _)| index)
+            {
+                retired = Some(clients.remove(oldest));
+            }
+
+            clients.push(PooledClient {
+                root: root.to_path_buf(),
+                client: client.clone(),
+                last_used: Instant::now(),
+            });
+            retired
+        };
+
+        if let Some(retired) = retired {
+            eprintln!("LspPool: shutting down server for {}", retired.root.display());
+            let _ = retired.client.shutdown().await;
+        }
+        Ok(client)
+    }
+
+    /// Workspace roots with a running server
+    pub async fn roots(&self) -> Vec<PathBuf> {
+        self.clients.lock().await.iter().map(|pooled| pooled.root.clone()).collect()
+    }
+
+    /// Shut down every server in the pool
+    pub async fn shutdown(&self) -> Result<()> {
+        let mut clients = self.clients.lock().await;
+        for pooled in clients.drain(..) {
+            pooled.client.shutdown().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Workspace root for a file: the outermost Cargo.toml declaring `[workspace]`,
+/// otherwise the nearest Cargo.toml, otherwise the file's directory
+pub fn workspace_root_for(file: &Path) -> PathBuf {
+    let mut nearest = None;
+    let mut workspace = None;
+
+    for dir in file.ancestors().skip(1) {
+        let manifest = dir.join("Cargo.toml");
+        if let Ok(content) = fs::read_to_string(&manifest) {
+            if nearest.is_none() {
+                nearest = Some(dir.to_path_buf());
+            }
+            if content.lines().any(|line| line.trim() == "[workspace]") {
+                workspace = Some(dir.to_path_buf());
+            }
+        }
+    }
+
+    workspace
+        .or(nearest)
+        .or_else(|| file.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsp_client::Transport;
+    use crate::lsp_client::tests::serve_fake;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn test_workspace_root_for() {
+        let dir = std::env::temp_dir().join(format!("graphslice_pool_{}", std::process::id()));
+        let member = dir.join("ws/member/src");
+        let solo = dir.join("solo/src");
+        fs::create_dir_all(&member).unwrap();
+        fs::create_dir_all(&solo).unwrap();
+        fs::write(dir.join("ws/Cargo.toml"), "[workspace]\nmembers = [\"member\"]\n").unwrap();
+        fs::write(dir.join("ws/member/Cargo.toml"), "[package]\nname = \"member\"\n").unwrap();
+        fs::write(dir.join("solo/Cargo.toml"), "[package]\nname = \"solo\"\n").unwrap();
+
+        // A member resolves to its workspace, a standalone crate to itself
+        assert_eq!(workspace_root_for(&member.join("lib.rs")), dir.join("ws"));
+        assert_eq!(workspace_root_for(&solo.join("main.rs")), dir.join("solo"));
+        // Without a manifest, the file's own directory
+        assert_eq!(workspace_root_for(&dir.join("loose/x.rs")), dir.join("loose"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_least_recently_used_server_is_retired() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (_keep, never) = oneshot::channel();
+                    serve_fake(stream, never).await;
+                });
+            }
+        });
+
+        let pool = LspPool::with_template(LspClient::builder().transport(Transport::Tcp(addr)), 2);
+        let root = |name: &str| std::env::temp_dir().join(name);
+        pool.client_for_root(&root("a")).await.unwrap();
+        pool.client_for_root(&root("b")).await.unwrap();
+        // Touch `a`, so `b` is the one to go when `c` needs room
+        pool.client_for_root(&root("a")).await.unwrap();
+        pool.client_for_root(&root("c")).await.unwrap();
+
+        assert_eq!(pool.roots().await, vec![root("a"), root("c")]);
+    }
+}
@@ -1,13 +1,14 @@
 use anyhow::Result;
-use graphslice::{Slicer, compression::HierarchicalContext};
+use graphslice::{CfgPass, CfgSet, FuzzyMode, GraphCache, SliceProgress, Slicer, SlicerConfig, TraceLevel, compression::HierarchicalContext};
 use std::path::PathBuf;
+use tokio::sync::broadcast;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args: Vec<String> = std::env::args().collect();
     
     if args.len() < 4 {
-        eprintln!("Usage: graphslice <workspace> <file> <line>:<col> [--max-tokens N]");
+        eprintln!("Usage: graphslice <workspace> <file> <line>:<col> [--max-tokens N] [--max-depth N] [--expansion-budget N] [--timeout SECS] [--mode auto|strict|fuzzy] [--exclude GLOB]... [--cfg OPTION]... [--cache DIR] [--rules DIR] [--dot FILE] [--html FILE] [--bundle FILE]");
         std::process::exit(1);
     }
 
@@ -15,12 +16,64 @@ async fn main() -> Result<()> {
     let target_file = workspace.join(&args[2]);
     let location = &args[3];
 
-    // Parse optional token limit
-    let max_tokens = if args.len() > 5 && args[4] == "--max-tokens" {
-        args[5].parse().unwrap_or(2000)
-    } else {
-        2000
-    };
+    // Parse optional flags
+    let mut config = SlicerConfig::default();
+    let mut dot_output: Option<PathBuf> = None;
+    let mut html_output: Option<PathBuf> = None;
+    let mut bundle_output: Option<PathBuf> = None;
+    let mut cache_dir: Option<PathBuf> = None;
+    let mut rules_dir: Option<PathBuf> = None;
+    let mut cfg: Option<CfgSet> = None;
+    let mut flags = args[4..].iter();
+    while let Some(flag) = flags.next() {
+        match flag.as_str() {
+            "--max-tokens" => {
+                config.max_tokens = flags.next().and_then(|n| n.parse().ok()).unwrap_or(config.max_tokens);
+            }
+            "--max-depth" => {
+                config.max_depth = flags.next().and_then(|n| n.parse().ok()).unwrap_or(config.max_depth);
+            }
+            "--expansion-budget" => {
+                config.expansion_budget = flags.next().and_then(|n| n.parse().ok());
+            }
+            "--timeout" => {
+                config.slice_timeout = flags.next().and_then(|n| n.parse().ok()).map(std::time::Duration::from_secs_f64);
+            }
+            "--mode" => {
+                config.fuzzy_mode = match flags.next().map(String::as_str) {
+                    Some("strict") => FuzzyMode::Strict,
+                    Some("fuzzy") => FuzzyMode::Fuzzy,
+                    _ => FuzzyMode::Auto,
+                };
+            }
+            "--exclude" => {
+                config.exclude_globs.extend(flags.next().cloned());
+            }
+            "--cfg" => {
+                if let Some(option) = flags.next() {
+                    cfg.get_or_insert_with(CfgSet::new).enable(option);
+                }
+            }
+            "--cache" => {
+                cache_dir = flags.next().map(PathBuf::from);
+            }
+            "--rules" => {
+                rules_dir = flags.next().map(PathBuf::from);
+            }
+            "--dot" => {
+                dot_output = flags.next().map(PathBuf::from);
+            }
+            "--html" => {
+                html_output = flags.next().map(PathBuf::from);
+            }
+            "--bundle" => {
+                bundle_output = flags.next().map(PathBuf::from);
+            }
+            other => eprintln!("Ignoring unknown argument: {}", other),
+        }
+    }
+
+    let max_tokens = config.max_tokens;
 
     let parts: Vec<&str> = location.split(':').collect();
     let line: u32 = parts[0].parse()?;
@@ -33,12 +86,53 @@ async fn main() -> Result<()> {
     println!();
 
     println!("Starting rust-analyzer...");
-    let mut slicer = Slicer::new(workspace).await?;
+    let mut slicer = Slicer::with_config(workspace, config).await?;
+    if let Some(cfg) = cfg {
+        slicer.add_pass(Box::new(CfgPass::new(cfg)?));
+    }
+    if let Some(dir) = rules_dir {
+        slicer.load_extraction_rules(&dir)?;
+    }
+    if let Some(dir) = cache_dir {
+        slicer.set_graph_cache(GraphCache::new(dir)?);
+    }
+
+    // Opt-in LSP traffic trace: GRAPHSLICE_LSP_TRACE=summary|full
+    if let Ok(trace) = std::env::var("GRAPHSLICE_LSP_TRACE") {
+        let level = match trace.as_str() {
+            "full" => TraceLevel::Full,
+            _ => TraceLevel::Summary,
+        };
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_max_level(tracing::Level::DEBUG)
+            .init();
+        slicer.set_lsp_trace(level);
+    }
 
     println!("Building dependency graph...");
-    let graph = slicer.build_graph(target_file.clone(), line, col).await?;
+    let mut progress = slicer.subscribe_progress();
+    tokio::spawn(async move {
+        let mut nodes = 0;
+        loop {
+            match progress.recv().await {
+                Ok(SliceProgress::Phase(phase)) => eprintln!("⏳ {:?} ({} nodes)", phase, nodes),
+                Ok(SliceProgress::NodesDiscovered(count)) => nodes = count,
+                Ok(SliceProgress::DepthReached(depth)) => eprintln!("⏳ Expanding calls at depth {}", depth),
+                Ok(SliceProgress::PendingRequests(_)) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    let graph = slicer.build_graph_cached(target_file.clone(), line, col).await?;
 
     println!("Found {} nodes, {} edges", graph.nodes.len(), graph.edges.len());
+    if let Some(frontier) = &graph.truncated {
+        println!("⚠️  Slice truncated; not expanded:");
+        for id in frontier {
+            println!("  {}:{}:{}", id.file.display(), id.line, id.column);
+        }
+    }
 
     // Build hierarchical context
     println!("Compressing context...");
@@ -66,5 +160,20 @@ async fn main() -> Result<()> {
     std::fs::write("graphslice_context.txt", &output)?;
     println!("✅ Saved to graphslice_context.txt");
 
+    if let Some(path) = dot_output {
+        std::fs::write(&path, graph.to_dot())?;
+        println!("✅ Saved graph to {}", path.display());
+    }
+
+    if let Some(path) = html_output {
+        graph.export_html(&path)?;
+        println!("✅ Saved interactive graph to {}", path.display());
+    }
+
+    if let Some(path) = bundle_output {
+        slicer.export_bundle(&graph, &path)?;
+        println!("✅ Saved slice bundle to {}", path.display());
+    }
+
     Ok(())
 }
\ No newline at end of file
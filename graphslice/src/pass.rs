@@ -0,0 +1,232 @@
+use crate::cfg::{CfgSet, cfg_predicate};
+use crate::extractor::Extractor;
+use crate::graph::NodeId;
+use crate::position::{self, PositionEncoding};
+use crate::verifier::Verifier;
+use anyhow::Result;
+use lsp_types::Range;
+use std::path::Path;
+
+/// A node the slicer is about to add, as seen by `SlicePass`es
+pub struct Candidate<'a> {
+    pub id: &'a NodeId,
+    /// "call", "reference" or "definition"
+    pub node_type: &'a str,
+    /// Node already in the slice that leads to the candidate (the caller, for calls)
+    pub from: &'a NodeId,
+    /// Where the candidate is used: the call sites in `from` for calls, the reference
+    /// itself for references, the target for definitions
+    pub sites: &'a [Range],
+    /// File holding `sites`
+    pub file: &'a Path,
+    /// Text of `file`
+    pub source: &'a str,
+    /// Unit of the columns in `sites`
+    pub encoding: PositionEncoding,
+}
+
+/// A filter run over candidate nodes before they join a slice, e.g. to prune dead code,
+/// tests or private items. Passes run in the order they were added to the `Slicer`.
+pub trait SlicePass: Send {
+    /// Shown when the pass prunes something
+    fn name(&self) -> &str;
+
+    /// Why `candidate` should be left out of the slice, or `None` to keep it
+    fn prune(&mut self, candidate: &Candidate<'_>) -> Option<String>;
+}
+
+/// Prunes calls whose every call site sits under integer or boolean constraints that Z3
+/// proves contradictory, e.g. `let x = 1; if x > 5 { call() }` or `let debug = false; if debug { call() }`
+pub struct ReachabilityPass {
+    extractor: Extractor,
+    verifier: Verifier,
+}
+
+impl ReachabilityPass {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            extractor: Extractor::new()?,
+            verifier: Verifier::new()?,
+        })
+    }
+
+    /// Whether the constraints at a site are satisfiable, along with those constraints
+    fn is_reachable(&mut self, source: &str, line: u32, byte_col: usize) -> (bool, String) {
+        let (assignments, conditions) = self.extractor.extract_constraints(source, line as usize, byte_col);
+        if assignments.is_empty() && conditions.is_empty() {
+            return (true, String::new());
+        }
+
+        let constraints: Vec<(&str, &str, i64)> = assignments.iter().map(|c| (c.var.as_str(), c.op.as_str(), c.val)).collect();
+        let consistent = self.verifier.check_conditions(&constraints, &conditions);
+        (consistent, format!("{:?} + {:?}", assignments, conditions))
+    }
+}
+
+impl SlicePass for ReachabilityPass {
+    fn name(&self) -> &str {
+        "reachability"
+    }
+
+    fn prune(&mut self, candidate: &Candidate<'_>) -> Option<String> {
+        if candidate.node_type != "call" || candidate.sites.is_empty() {
+            return None;
+        }
+
+        let mut explanation = Vec::new();
+        for site in candidate.sites {
+            // LSP columns are in the negotiated encoding; tree-sitter wants bytes
+            let byte_col = position::byte_column_in(candidate.source, site.start.line, site.start.character, candidate.encoding);
+            let (reachable, constraints) = self.is_reachable(candidate.source, site.start.line, byte_col);
+            if reachable {
+                return None;
+            }
+            explanation.push(format!("line {}: {}", site.start.line + 1, constraints));
+        }
+        Some(format!("all call sites unreachable ({})", explanation.join("; ")))
+    }
+}
+
+/// Prunes calls and references from code compiled out under the given cfg options,
+/// e.g. a call under `#[cfg(windows)]` when slicing for Linux
+pub struct CfgPass {
+    extractor: Extractor,
+    cfg: CfgSet,
+}
+
+impl CfgPass {
+    pub fn new(cfg: CfgSet) -> Result<Self> {
+        Ok(Self { extractor: Extractor::new()?, cfg })
+    }
+
+    /// The first `cfg` predicate around a site that doesn't hold, if any
+    fn disabled_by(&mut self, source: &str, line: u32, byte_col: usize) -> Option<String> {
+        self.extractor
+            .attributes_at(source, line as usize, byte_col)
+            .iter()
+            .filter_map(|attribute| cfg_predicate(attribute))
+            .find(|predicate| !self.cfg.eval(predicate))
+            .map(str::to_string)
+    }
+}
+
+impl SlicePass for CfgPass {
+    fn name(&self) -> &str {
+        "cfg"
+    }
+
+    fn prune(&mut self, candidate: &Candidate<'_>) -> Option<String> {
+        // A definition's sites are the target's, which was asked for explicitly
+        if candidate.node_type == "definition" || candidate.sites.is_empty() {
+            return None;
+        }
+
+        let mut predicate = None;
+        for site in candidate.sites {
+            let byte_col = position::byte_column_in(candidate.source, site.start.line, site.start.character, candidate.encoding);
+            predicate = Some(self.disabled_by(candidate.source, site.start.line, byte_col)?);
+        }
+        predicate.map(|predicate| format!("compiled out by #[cfg({})]", predicate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_reachability_prunes_dead_calls() {
+        let source = "fn f() {\n    let x = 1;\n    if x > 5 {\n        g();\n    }\n    h();\n}\n";
+        let caller = NodeId { file: PathBuf::from("/src/lib.rs"), line: 0, column: 3 };
+        let callee = NodeId { file: PathBuf::from("/src/lib.rs"), line: 10, column: 3 };
+        let site = |line, col| Range::new(Position::new(line, col), Position::new(line, col + 1));
+        let (dead, live) = ([site(3, 8)], [site(3, 8), site(5, 4)]);
+        let mut candidate = Candidate {
+            id: &callee,
+            node_type: "call",
+            from: &caller,
+            sites: &dead,
+            file: Path::new("/src/lib.rs"),
+            source,
+            encoding: PositionEncoding::Utf16,
+        };
+
+        let mut pass = ReachabilityPass::new().unwrap();
+        assert!(pass.prune(&candidate).is_some());
+        candidate.sites = &live;
+        assert!(pass.prune(&candidate).is_none());
+    }
+
+    #[test]
+    fn test_reachability_prunes_calls_behind_false_flags() {
+        let source = "fn f() {\n    let verbose = false;\n    if verbose && ready() {\n        g();\n    }\n    if !verbose {\n        h();\n    }\n}\n";
+        let caller = NodeId { file: PathBuf::from("/src/lib.rs"), line: 0, column: 3 };
+        let callee = NodeId { file: PathBuf::from("/src/lib.rs"), line: 10, column: 3 };
+        let site = |line| [Range::new(Position::new(line, 8), Position::new(line, 9))];
+        let (dead, live) = (site(3), site(6));
+        let mut candidate = Candidate {
+            id: &callee,
+            node_type: "call",
+            from: &caller,
+            sites: &dead,
+            file: Path::new("/src/lib.rs"),
+            source,
+            encoding: PositionEncoding::Utf16,
+        };
+
+        let mut pass = ReachabilityPass::new().unwrap();
+        assert!(pass.prune(&candidate).is_some());
+        candidate.sites = &live;
+        assert!(pass.prune(&candidate).is_none());
+    }
+
+    #[test]
+    fn test_reachability_follows_latest_flag_binding() {
+        let source = "fn f(cli: &Cli) {\n    let debug = false;\n    let debug = cli.debug();\n    if debug {\n        g();\n    }\n    let quiet = false;\n    let quiet = true;\n    if quiet {\n        g();\n    }\n    if !quiet {\n        g();\n    }\n}\n";
+        let caller = NodeId { file: PathBuf::from("/src/lib.rs"), line: 0, column: 3 };
+        let callee = NodeId { file: PathBuf::from("/src/lib.rs"), line: 20, column: 3 };
+        let site = |line| [Range::new(Position::new(line, 8), Position::new(line, 9))];
+        let (rebound, shadowed, dead) = (site(4), site(9), site(12));
+        let mut candidate = Candidate {
+            id: &callee,
+            node_type: "call",
+            from: &caller,
+            sites: &rebound,
+            file: Path::new("/src/lib.rs"),
+            source,
+            encoding: PositionEncoding::Utf16,
+        };
+
+        let mut pass = ReachabilityPass::new().unwrap();
+        assert!(pass.prune(&candidate).is_none());
+        candidate.sites = &shadowed;
+        assert!(pass.prune(&candidate).is_none());
+        candidate.sites = &dead;
+        assert!(pass.prune(&candidate).is_some());
+    }
+
+    #[test]
+    fn test_cfg_prunes_compiled_out_sites() {
+        let source = "fn f() {\n    #[cfg(windows)]\n    g();\n    h();\n}\n";
+        let caller = NodeId { file: PathBuf::from("/src/lib.rs"), line: 0, column: 3 };
+        let callee = NodeId { file: PathBuf::from("/src/lib.rs"), line: 10, column: 3 };
+        let site = |line| Range::new(Position::new(line, 4), Position::new(line, 5));
+        let (windows_only, both) = ([site(2)], [site(2), site(3)]);
+        let mut candidate = Candidate {
+            id: &callee,
+            node_type: "call",
+            from: &caller,
+            sites: &windows_only,
+            file: Path::new("/src/lib.rs"),
+            source,
+            encoding: PositionEncoding::Utf16,
+        };
+
+        let mut pass = CfgPass::new(CfgSet::new().with_flag("unix")).unwrap();
+        assert_eq!(pass.prune(&candidate).as_deref(), Some("compiled out by #[cfg(windows)]"));
+        candidate.sites = &both;
+        assert!(pass.prune(&candidate).is_none());
+    }
+}
@@ -0,0 +1,99 @@
+use lsp_types::PositionEncodingKind;
+
+/// Unit in which LSP `Position::character` is measured, as negotiated with the server.
+/// Tree-sitter always works in bytes, so columns must be converted at the boundary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionEncoding {
+    Utf8,
+    /// LSP default when the server does not announce an encoding
+    #[default]
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    pub fn from_kind(kind: &PositionEncodingKind) -> Self {
+        match kind.as_str() {
+            "utf-8" => PositionEncoding::Utf8,
+            "utf-32" => PositionEncoding::Utf32,
+            _ => PositionEncoding::Utf16,
+        }
+    }
+
+    pub fn kind(&self) -> PositionEncodingKind {
+        match self {
+            PositionEncoding::Utf8 => PositionEncodingKind::UTF8,
+            PositionEncoding::Utf16 => PositionEncodingKind::UTF16,
+            PositionEncoding::Utf32 => PositionEncodingKind::UTF32,
+        }
+    }
+
+    fn char_width(&self, c: char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// Convert an LSP column on `line` into a byte offset (tree-sitter column).
+/// Columns past the end of the line clamp to the line length.
+pub fn to_byte_column(line: &str, column: u32, encoding: PositionEncoding) -> usize {
+    let mut units = 0;
+    for (byte, c) in line.char_indices() {
+        if units >= column as usize {
+            return byte;
+        }
+        units += encoding.char_width(c);
+    }
+    line.len()
+}
+
+/// Convert a byte offset on `line` (tree-sitter column) into an LSP column.
+pub fn from_byte_column(line: &str, byte_column: usize, encoding: PositionEncoding) -> u32 {
+    line.char_indices()
+        .take_while(|(byte, _)| *byte < byte_column)
+        .map(|(_, c)| encoding.char_width(c))
+        .sum::<usize>() as u32
+}
+
+/// Byte column for an LSP position, looking up the line in `source`
+pub fn byte_column_in(source: &str, line: u32, column: u32, encoding: PositionEncoding) -> usize {
+    source
+        .lines()
+        .nth(line as usize)
+        .map(|text| to_byte_column(text, column, encoding))
+        .unwrap_or(column as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ascii_columns_are_identical() {
+        let line = "let x = helper(5);";
+        for encoding in [PositionEncoding::Utf8, PositionEncoding::Utf16, PositionEncoding::Utf32] {
+            assert_eq!(to_byte_column(line, 8, encoding), 8);
+            assert_eq!(from_byte_column(line, 8, encoding), 8);
+        }
+    }
+
+    #[test]
+    fn test_non_ascii_round_trip() {
+        // 'é' is 2 bytes / 1 UTF-16 unit, '𝄞' is 4 bytes / 2 UTF-16 units
+        let line = "let é𝄞 = f(1);";
+        let byte = line.find('=').unwrap();
+
+        let utf16 = from_byte_column(line, byte, PositionEncoding::Utf16);
+        assert_eq!(utf16, 8);
+        assert_eq!(to_byte_column(line, utf16, PositionEncoding::Utf16), byte);
+
+        let utf32 = from_byte_column(line, byte, PositionEncoding::Utf32);
+        assert_eq!(utf32, 7);
+        assert_eq!(to_byte_column(line, utf32, PositionEncoding::Utf32), byte);
+
+        assert_eq!(from_byte_column(line, byte, PositionEncoding::Utf8) as usize, byte);
+    }
+}
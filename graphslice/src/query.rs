@@ -0,0 +1,369 @@
+use crate::graph::{DependencyGraph, Direction, NodeId};
+use anyhow::{Result, anyhow, bail};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(usize),
+    Str(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Op(Op),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Glob,
+}
+
+#[derive(Debug)]
+enum Expr {
+    All,
+    Target,
+    Callers(Box<Expr>),
+    Callees(Box<Expr>),
+    Dependents(Box<Expr>),
+    Deps(Box<Expr>),
+    Filter(String, Op, Token),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+impl DependencyGraph {
+    /// Evaluate a query and return the matching nodes in position order, e.g.
+    /// `callers(target) & depth<=2 & file~"src/**"`.
+    ///
+    /// Sets: `all`, `target`, `callers(q)` / `callees(q)` (direct edges) and
+    /// `dependents(q)` / `deps(q)` (transitive, excluding `q` itself).
+    /// Filters: `depth` (hops from the target either way), `file` (glob with `~`),
+    /// `type` (node role), `kind`, `name`, `module`. Combine with `&`, `|`, `!` and parentheses.
+    pub fn query(&self, query: &str) -> Result<Vec<NodeId>> {
+        let mut parser = Parser { tokens: tokenize(query)?, pos: 0 };
+        let expr = parser.expr()?;
+        if let Some(token) = parser.peek() {
+            bail!("Unexpected {:?} in query", token);
+        }
+
+        let target = self.target().map(|n| n.id.clone());
+        let depths: HashMap<NodeId, usize> = match &target {
+            Some(target) => self.neighborhood(target, usize::MAX, Direction::Both).into_iter().collect(),
+            None => HashMap::new(),
+        };
+        let eval = Eval { graph: self, target, depths };
+
+        let mut ids: Vec<NodeId> = eval.eval(&expr)?.into_iter().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// The subgraph of nodes matching `query`
+    pub fn select(&self, query: &str) -> Result<DependencyGraph> {
+        let selected: HashSet<NodeId> = self.query(query)?.into_iter().collect();
+        Ok(self.subgraph(|node| selected.contains(&node.id)))
+    }
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        chars.next();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '&' => Token::And,
+            '|' => Token::Or,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '~' => Token::Op(Op::Glob),
+            '=' => Token::Op(Op::Eq),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => bail!("Unterminated string in query"),
+                    }
+                }
+                Token::Str(text)
+            }
+            c if c.is_ascii_digit() => {
+                let mut digits = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                Token::Number(digits.parse()?)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(d) = chars.next_if(|d| d.is_alphanumeric() || *d == '_' || *d == ':') {
+                    ident.push(d);
+                }
+                Token::Ident(ident)
+            }
+            other => bail!("Unexpected character '{}' in query", other),
+        };
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(anyhow!("Expected {:?} in query, found {:?}", expected, other)),
+        }
+    }
+
+    // expr := term ('|' term)*
+    fn expr(&mut self) -> Result<Expr> {
+        let mut expr = self.term()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.term()?));
+        }
+        Ok(expr)
+    }
+
+    // term := factor ('&' factor)*
+    fn term(&mut self) -> Result<Expr> {
+        let mut expr = self.factor()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    // factor := '!' factor | '(' expr ')' | ident '(' expr ')' | ident op value | ident
+    fn factor(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Not) => Ok(Expr::Not(Box::new(self.factor()?))),
+            Some(Token::Open) => {
+                let expr = self.expr()?;
+                self.expect(Token::Close)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => match self.peek() {
+                Some(Token::Open) => {
+                    self.next();
+                    let inner = Box::new(self.expr()?);
+                    self.expect(Token::Close)?;
+                    match name.as_str() {
+                        "callers" => Ok(Expr::Callers(inner)),
+                        "callees" => Ok(Expr::Callees(inner)),
+                        "dependents" => Ok(Expr::Dependents(inner)),
+                        "deps" => Ok(Expr::Deps(inner)),
+                        _ => bail!("Unknown query function '{}'", name),
+                    }
+                }
+                Some(Token::Op(op)) => {
+                    let op = *op;
+                    self.next();
+                    let value = match self.next() {
+                        Some(value @ (Token::Number(_) | Token::Str(_))) => value,
+                        Some(Token::Ident(word)) => Token::Str(word),
+                        other => bail!("Expected a value after '{}', found {:?}", name, other),
+                    };
+                    Ok(Expr::Filter(name, op, value))
+                }
+                _ => match name.as_str() {
+                    "all" => Ok(Expr::All),
+                    "target" => Ok(Expr::Target),
+                    _ => bail!("Unknown query set '{}'", name),
+                },
+            },
+            other => Err(anyhow!("Unexpected {:?} in query", other)),
+        }
+    }
+}
+
+struct Eval<'a> {
+    graph: &'a DependencyGraph,
+    target: Option<NodeId>,
+    /// Hops from the target, following edges either way
+    depths: HashMap<NodeId, usize>,
+}
+
+impl Eval<'_> {
+    fn eval(&self, expr: &Expr) -> Result<HashSet<NodeId>> {
+        let nodes = &self.graph.nodes;
+        Ok(match expr {
+            Expr::All => nodes.keys().cloned().collect(),
+            Expr::Target => self.target.iter().cloned().collect(),
+            Expr::Callers(inner) => self.step(inner, |id| self.graph.get_dependents(id).into_iter().map(|n| n.id.clone()).collect())?,
+            Expr::Callees(inner) => self.step(inner, |id| self.graph.get_dependencies(id).into_iter().map(|n| n.id.clone()).collect())?,
+            Expr::Dependents(inner) => self.step(inner, |id| self.graph.bfs_to(id).into_iter().skip(1).map(|(n, _)| n).collect())?,
+            Expr::Deps(inner) => self.step(inner, |id| self.graph.bfs_from(id).into_iter().skip(1).map(|(n, _)| n).collect())?,
+            Expr::Filter(field, op, value) => {
+                let mut matched = HashSet::new();
+                for id in nodes.keys() {
+                    if self.matches(id, field, *op, value)? {
+                        matched.insert(id.clone());
+                    }
+                }
+                matched
+            }
+            Expr::And(a, b) => &self.eval(a)? & &self.eval(b)?,
+            Expr::Or(a, b) => &self.eval(a)? | &self.eval(b)?,
+            Expr::Not(inner) => {
+                let excluded = self.eval(inner)?;
+                nodes.keys().filter(|id| !excluded.contains(*id)).cloned().collect()
+            }
+        })
+    }
+
+    /// Apply `step` to every node of `inner` and union the results
+    fn step(&self, inner: &Expr, step: impl Fn(&NodeId) -> Vec<NodeId>) -> Result<HashSet<NodeId>> {
+        let mut result = HashSet::new();
+        for id in self.eval(inner)? {
+            result.extend(step(&id).into_iter().filter(|n| self.graph.nodes.contains_key(n)));
+        }
+        Ok(result)
+    }
+
+    fn matches(&self, id: &NodeId, field: &str, op: Op, value: &Token) -> Result<bool> {
+        let node = &self.graph.nodes[id];
+        if field == "depth" {
+            let Token::Number(limit) = value else {
+                bail!("depth must be compared to a number");
+            };
+            let Some(&depth) = self.depths.get(id) else { return Ok(false) };
+            return Ok(match op {
+                Op::Eq => depth == *limit,
+                Op::Ne => depth != *limit,
+                Op::Lt => depth < *limit,
+                Op::Le => depth <= *limit,
+                Op::Gt => depth > *limit,
+                Op::Ge => depth >= *limit,
+                Op::Glob => bail!("depth cannot be matched with '~'"),
+            });
+        }
+
+        let actual = match field {
+            "file" => Some(id.file.display().to_string()),
+            "type" => Some(node.node_type.clone()),
+            "kind" => node.symbol_kind.map(|kind| format!("{:?}", kind).to_lowercase()),
+            "name" => node.symbol_name.clone(),
+            "module" => node.module_path.clone(),
+            _ => bail!("Unknown query field '{}'", field),
+        };
+        let expected = match value {
+            Token::Str(text) => text.clone(),
+            Token::Number(n) => n.to_string(),
+            _ => unreachable!("parser only produces string and number values"),
+        };
+        let Some(actual) = actual else { return Ok(false) };
+
+        Ok(match op {
+            Op::Eq => actual.eq_ignore_ascii_case(&expected),
+            Op::Ne => !actual.eq_ignore_ascii_case(&expected),
+            Op::Glob if field == "file" => path_matches(&expected, &actual),
+            Op::Glob => glob_match(expected.as_bytes(), actual.as_bytes()),
+            _ => bail!("'{}' only supports =, != and ~", field),
+        })
+    }
+}
+
+/// Relative patterns like `src/**` may match anywhere in an absolute path
+pub(crate) fn path_matches(pattern: &str, path: &str) -> bool {
+    if pattern.starts_with('/') || pattern.starts_with("**") {
+        glob_match(pattern.as_bytes(), path.as_bytes())
+    } else {
+        glob_match(format!("**/{}", pattern).as_bytes(), path.as_bytes())
+    }
+}
+
+/// `*` matches within a path segment, `**` across segments, `?` one character
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match(rest, text) || (0..text.len()).any(|i| text[i] == b'/' && glob_match(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| glob_match(rest, &text[i..])),
+        [b'?', rest @ ..] => !text.is_empty() && text[0] != b'/' && glob_match(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CodeNode, Edge, EdgeType};
+    use std::path::PathBuf;
+
+    fn id(file: &str, line: u32) -> NodeId {
+        NodeId { file: PathBuf::from(file), line, column: 0 }
+    }
+
+    fn sample() -> DependencyGraph {
+        // caller (tests) -> target -> helper (src) -> leaf (vendor)
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(id("/repo/tests/it.rs", 1), String::new(), "reference"));
+        graph.add_node(CodeNode::new(id("/repo/src/lib.rs", 1), String::new(), "target"));
+        graph.add_node(CodeNode::new(id("/repo/src/util.rs", 4), String::new(), "call"));
+        graph.add_node(CodeNode::new(id("/repo/vendor/x.rs", 9), String::new(), "call"));
+        graph.add_edge(Edge::new(id("/repo/tests/it.rs", 1), id("/repo/src/lib.rs", 1), EdgeType::References));
+        graph.add_edge(Edge::new(id("/repo/src/lib.rs", 1), id("/repo/src/util.rs", 4), EdgeType::Calls));
+        graph.add_edge(Edge::new(id("/repo/src/util.rs", 4), id("/repo/vendor/x.rs", 9), EdgeType::Calls));
+        graph
+    }
+
+    #[test]
+    fn test_query() {
+        let graph = sample();
+        assert_eq!(graph.query("callers(target)").unwrap(), vec![id("/repo/tests/it.rs", 1)]);
+        assert_eq!(graph.query("deps(target) & file~\"src/**\"").unwrap(), vec![id("/repo/src/util.rs", 4)]);
+        assert_eq!(graph.query("depth<=1 & !target").unwrap().len(), 2);
+        assert_eq!(graph.query("type=call | callers(target)").unwrap().len(), 3);
+        assert!(graph.query("callers(").is_err());
+        assert!(graph.query("color=red").is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(path_matches("src/**", "/repo/src/a/b.rs"));
+        assert!(path_matches("src/*.rs", "/repo/src/lib.rs"));
+        assert!(!path_matches("src/*.rs", "/repo/src/a/b.rs"));
+        assert!(path_matches("**/lib.rs", "/repo/src/lib.rs"));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::graph::{CodeNode, DependencyGraph, EdgeType, NodeId};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Share of a node's relevance coming from its distance to the targets
+const PROXIMITY_WEIGHT: f64 = 0.6;
+/// Share coming from how often the rest of the slice uses it
+const USAGE_WEIGHT: f64 = 0.2;
+/// Share coming from identifiers it has in common with the targets
+const SIMILARITY_WEIGHT: f64 = 0.2;
+/// Proximity lost per hop away from the target
+const HOP_DECAY: f64 = 0.7;
+
+/// Words too common in Rust code to say anything about similarity
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "false", "fn", "for", "if",
+    "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self", "static", "struct", "super",
+    "trait", "true", "type", "unsafe", "use", "where", "while",
+];
+
+/// How closely an edge ties its ends together: a call or definition binds tighter
+/// than a plain reference, and layout edges hardly at all
+fn edge_strength(edge_type: &EdgeType) -> f64 {
+    match edge_type {
+        EdgeType::Defines | EdgeType::Calls | EdgeType::TypeOf => 1.0,
+        EdgeType::Reads | EdgeType::Writes | EdgeType::Implements => 0.8,
+        EdgeType::References => 0.6,
+        EdgeType::Imports => 0.4,
+        EdgeType::Contains => 0.3,
+    }
+}
+
+/// Identifiers in `code`, for comparing what two pieces of code talk about
+fn identifiers(code: &str) -> HashSet<&str> {
+    code.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| word.len() > 1 && !word.starts_with(|c: char| c.is_ascii_digit()) && !KEYWORDS.contains(word))
+        .collect()
+}
+
+/// Jaccard similarity of two identifier sets
+fn similarity(a: &HashSet<&str>, b: &HashSet<&str>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+impl DependencyGraph {
+    /// Strongest path from any target to each node, ignoring edge direction. A path's
+    /// strength is the product of its edges' strengths, decayed per hop; targets get 1.0.
+    fn proximity(&self, targets: &[NodeId]) -> HashMap<NodeId, f64> {
+        let mut best: HashMap<NodeId, f64> = targets.iter().map(|id| (id.clone(), 1.0)).collect();
+        let mut queue: VecDeque<NodeId> = targets.iter().cloned().collect();
+
+        // Strengths only shrink along a path, so improvements die out
+        while let Some(id) = queue.pop_front() {
+            let strength = best[&id];
+            let neighbors = self
+                .outgoing_edges(&id)
+                .map(|edge| (&edge.to, &edge.edge_type))
+                .chain(self.incoming_edges(&id).map(|edge| (&edge.from, &edge.edge_type)));
+            for (next, edge_type) in neighbors {
+                let candidate = strength * edge_strength(edge_type) * HOP_DECAY;
+                if best.get(next).is_none_or(|&known| candidate > known) {
+                    best.insert(next.clone(), candidate);
+                    queue.push_back(next.clone());
+                }
+            }
+        }
+        best
+    }
+
+    /// Score each node's relevance to the slice's targets into `relevance`: proximity
+    /// (distance and edge types on the way), how often the slice uses it, and how many
+    /// identifiers its code shares with the targets'
+    pub fn score_relevance(&mut self) {
+        let targets: Vec<NodeId> = self.targets().into_iter().map(|node| node.id.clone()).collect();
+        let proximity = self.proximity(&targets);
+
+        let mut usage: HashMap<&NodeId, u32> = HashMap::new();
+        for edge in self.edges.iter().filter(|edge| !edge.edge_type.is_structural()) {
+            *usage.entry(&edge.to).or_default() += edge.metadata.occurrences;
+        }
+        let max_usage = usage.values().copied().max().unwrap_or(0);
+
+        let target_code: String = targets.iter().map(|id| self.nodes[id].text().into_owned()).collect::<Vec<_>>().join("\n");
+        let target_identifiers = identifiers(&target_code);
+
+        let mut scores = HashMap::new();
+        for (id, node) in &self.nodes {
+            if node.node_type == "target" {
+                scores.insert(id.clone(), 1.0);
+                continue;
+            }
+            let used = usage.get(id).copied().unwrap_or(0);
+            let usage_score = if max_usage > 0 {
+                (1.0 + used as f64).ln() / (1.0 + max_usage as f64).ln()
+            } else {
+                0.0
+            };
+            let score = PROXIMITY_WEIGHT * proximity.get(id).copied().unwrap_or(0.0)
+                + USAGE_WEIGHT * usage_score
+                + SIMILARITY_WEIGHT * similarity(&identifiers(&node.text()), &target_identifiers);
+            scores.insert(id.clone(), score);
+        }
+
+        for (id, score) in scores {
+            if let Some(node) = self.nodes.get_mut(&id) {
+                node.relevance = score;
+            }
+        }
+    }
+
+    /// The `n` most relevant nodes by `relevance`, most relevant first, ties going by
+    /// position. Call `score_relevance` first.
+    pub fn top_k(&self, n: usize) -> Vec<&CodeNode> {
+        let mut nodes = self.sorted_nodes();
+        // Stable, so equal scores keep position order
+        nodes.sort_by(|a, b| b.relevance.total_cmp(&a.relevance));
+        nodes.truncate(n);
+        nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Edge, test_id};
+
+    #[test]
+    fn test_top_k_ranks_by_relevance() {
+        // target 1 calls 2, which calls 3; 4 only imports 1
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), "fn parse_config() { load_config(); }".to_string(), "target"));
+        graph.add_node(CodeNode::new(test_id(2), "fn load_config() { read_file(); }".to_string(), "call"));
+        graph.add_node(CodeNode::new(test_id(3), "fn read_file() {}".to_string(), "call"));
+        graph.add_node(CodeNode::new(test_id(4), "use crate::parse_config;".to_string(), "reference"));
+        graph.add_edge(Edge::new(test_id(1), test_id(2), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(2), test_id(3), EdgeType::Calls));
+        graph.add_edge(Edge::new(test_id(4), test_id(1), EdgeType::Imports));
+
+        graph.score_relevance();
+        let ranked: Vec<NodeId> = graph.top_k(3).into_iter().map(|node| node.id.clone()).collect();
+        assert_eq!(ranked, vec![test_id(1), test_id(2), test_id(3)]);
+        assert!(graph.nodes[&test_id(3)].relevance > graph.nodes[&test_id(4)].relevance);
+        assert_eq!(graph.top_k(10).len(), 4);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::graph::content_hash;
+use anyhow::{Context, Result, anyhow};
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use tree_sitter::{Node, Point, Query, QueryCursor, StreamingIterator};
+
+/// Built-in patterns for `ExtractionRules::blocks`
+const DEFAULT_BLOCKS: &str = include_str!("assets/blocks.scm");
+/// Built-in patterns for `ExtractionRules::symbols`
+const DEFAULT_SYMBOLS: &str = include_str!("assets/symbols.scm");
+
+/// Tree-sitter queries deciding what the `Extractor` treats as a block (nodes captured
+/// as `@block`) and as a defined symbol (nodes captured as `@symbol`). Patterns may use
+/// predicates, e.g. `((macro_invocation macro: (identifier) @m) @block (#eq? @m "lazy_static"))`.
+pub struct ExtractionRules {
+    blocks: Query,
+    symbols: Query,
+    /// Query sources, for telling rule sets apart
+    sources: (String, String),
+}
+
+impl Default for ExtractionRules {
+    fn default() -> Self {
+        Self::new(DEFAULT_BLOCKS, DEFAULT_SYMBOLS).expect("built-in extraction rules are valid")
+    }
+}
+
+impl ExtractionRules {
+    /// Compile rules from query sources, replacing the built-in ones
+    pub fn new(blocks: &str, symbols: &str) -> Result<Self> {
+        Ok(Self {
+            blocks: compile(blocks, "block").context("Invalid block rules")?,
+            symbols: compile(symbols, "symbol").context("Invalid symbol rules")?,
+            sources: (blocks.to_string(), symbols.to_string()),
+        })
+    }
+
+    /// The built-in rules extended with the patterns in `dir/blocks.scm` and
+    /// `dir/symbols.scm`, each optional
+    pub fn load(dir: &Path) -> Result<Self> {
+        let extend = |name: &str, defaults: &str| -> Result<String> {
+            let path = dir.join(name);
+            match path.exists() {
+                true => {
+                    let extra = fs::read_to_string(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+                    Ok(format!("{}\n{}", defaults, extra))
+                }
+                false => Ok(defaults.to_string()),
+            }
+        };
+        Self::new(&extend("blocks.scm", DEFAULT_BLOCKS)?, &extend("symbols.scm", DEFAULT_SYMBOLS)?)
+            .with_context(|| format!("Failed to load extraction rules from {}", dir.display()))
+    }
+
+    /// Block and symbol query sources the rules were compiled from
+    pub fn sources(&self) -> (&str, &str) {
+        (&self.sources.0, &self.sources.1)
+    }
+
+    /// Hash of the query sources; equal rules give equal fingerprints
+    pub fn fingerprint(&self) -> String {
+        content_hash(&format!("{}\0{}", self.sources.0, self.sources.1))
+    }
+
+    /// Ids of the block nodes in `root` around `point`
+    pub(crate) fn blocks_at(&self, root: Node, source: &str, point: Point) -> HashSet<usize> {
+        let end = Point::new(point.row, point.column + 1);
+        captured(&self.blocks, "block", root, source, Some(point..end))
+    }
+
+    /// Ids of the symbol nodes in `root`
+    pub(crate) fn symbols_in(&self, root: Node, source: &str) -> HashSet<usize> {
+        captured(&self.symbols, "symbol", root, source, None)
+    }
+}
+
+/// Compile `source`, which must capture `@{capture}`
+fn compile(source: &str, capture: &str) -> Result<Query> {
+    let query = Query::new(&tree_sitter_rust::LANGUAGE.into(), source).map_err(|e| anyhow!("{}", e))?;
+    if query.capture_index_for_name(capture).is_none() {
+        return Err(anyhow!("No pattern captures @{}", capture));
+    }
+    Ok(query)
+}
+
+/// Ids of the nodes captured as `@{capture}`, optionally only in matches intersecting `range`
+fn captured(query: &Query, capture: &str, root: Node, source: &str, range: Option<Range<Point>>) -> HashSet<usize> {
+    let Some(index) = query.capture_index_for_name(capture) else {
+        return HashSet::new();
+    };
+    let mut cursor = QueryCursor::new();
+    if let Some(range) = range {
+        cursor.set_point_range(range);
+    }
+
+    let mut nodes = HashSet::new();
+    let mut matches = cursor.matches(query, root, source.as_bytes());
+    while let Some(found) = matches.next() {
+        nodes.extend(found.captures.iter().filter(|capture| capture.index == index).map(|capture| capture.node.id()));
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractor::Extractor;
+
+    #[test]
+    fn test_custom_rules_extend_blocks_and_symbols() {
+        let code = r#"
+lazy_static! {
+    static ref TABLE: Vec<u8> = build();
+}
+
+const LIMIT: usize = 8;
+"#;
+        let mut extractor = Extractor::new().unwrap();
+        assert!(extractor.get_defined_symbols(code).is_empty());
+
+        let blocks = format!("{}\n((macro_invocation macro: (identifier) @m) @block (#eq? @m \"lazy_static\"))", DEFAULT_BLOCKS);
+        let symbols = format!("{}\n(source_file (const_item) @symbol)", DEFAULT_SYMBOLS);
+        extractor.set_rules(ExtractionRules::new(&blocks, &symbols).unwrap());
+
+        let block = extractor.extract_block(code, 2, 16).unwrap();
+        assert!(block.starts_with("lazy_static!"));
+        let names: Vec<String> = extractor.get_defined_symbols(code).into_iter().map(|s| s.name).collect();
+        assert_eq!(names, vec!["LIMIT"]);
+
+        assert!(ExtractionRules::new("(function_item) @item", DEFAULT_SYMBOLS).is_err());
+        assert_ne!(ExtractionRules::default().fingerprint(), ExtractionRules::new(&blocks, &symbols).unwrap().fingerprint());
+    }
+}
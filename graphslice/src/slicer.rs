@@ -1,247 +1,1895 @@
-use crate::graph::{CodeNode, DependencyGraph, Edge, EdgeType, NodeId};
-use crate::lsp_client::LspClient;
-use crate::extractor::Extractor;
+use crate::graph::{CodeLocator, CodeNode, DependencyGraph, Direction, Edge, EdgeMetadata, EdgeType, NodeId, Provenance, SymbolId};
+use crate::lsp_client::{LspClient, LspFeature, ReferenceOptions, TraceLevel, Transport};
+use crate::lsp_pool::LspPool;
+use crate::extractor::{Access, Extractor, UseDeclaration, VariableAccess};
 use crate::fuzzy_slicer::FuzzySlicer;
-use crate::verifier::Verifier;
+use crate::pass::{Candidate, ReachabilityPass, SlicePass};
+use crate::position;
+use crate::source_cache::SourceCache;
+use crate::cache::GraphCache;
+use crate::diff::GraphDelta;
+use crate::export::format_tags;
+use crate::bundle::SliceBundle;
+use crate::compression::{HierarchicalContext, estimate_tokens};
+use crate::query::path_matches;
+use crate::rules::ExtractionRules;
 use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use url::Url;
-use lsp_types::DiagnosticSeverity;
+use walkdir::WalkDir;
+use lsp_types::{CallHierarchyItem, CallHierarchyOutgoingCall, Diagnostic, DiagnosticSeverity, FoldingRangeKind, Location, Position, Range};
+use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+
+/// Node id for the start of an LSP location
+fn location_id(location: &Location) -> Result<NodeId> {
+    let uri_str = location.uri.as_str();
+    let url = Url::parse(uri_str).map_err(|e| anyhow!("Failed to parse URI: {}", e))?;
+    let file = url.to_file_path().map_err(|_| anyhow!("URI is not a file path: {}", uri_str))?;
+    Ok(NodeId {
+        file,
+        line: location.range.start.line,
+        column: location.range.start.character,
+    })
+}
+
+/// Which slicers build a slice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum FuzzyMode {
+    /// The language server, with the fuzzy slicer filling in items that have errors
+    /// as the `fuzzy_*` settings decide
+    Auto,
+    /// The language server only, whatever the diagnostics say
+    Strict,
+    /// The fuzzy (LLM) slicer only, e.g. when the server can't make sense of the code
+    Fuzzy,
+}
+
+/// Tunables for how a slice is built
+#[derive(Debug, Clone, Serialize)]
+pub struct SlicerConfig {
+    /// Levels of callees expanded below the target's definitions;
+    /// 1 expands direct calls only, 0 disables call expansion
+    pub max_depth: usize,
+    /// `Forward` follows definitions and callees, `Backward` follows references
+    /// to the target, `Both` follows everything
+    pub direction: Direction,
+    /// Keep nodes from test files (under `tests/`, `benches/`, or named `tests.rs`/`*_test.rs`)
+    /// and references from `#[test]`/`#[cfg(test)]` code, flagging the latter as "test" nodes
+    pub include_tests: bool,
+    /// Which slicers to use; `Auto` follows the `fuzzy_*` settings below
+    pub fuzzy_mode: FuzzyMode,
+    /// When the target file has more errors than this, also run the fuzzy slicer on the
+    /// target and definitions whose enclosing item has errors, merging its guesses into
+    /// the strict slice as inferred edges
+    pub fuzzy_error_threshold: usize,
+    /// Errors up to this many lines outside an item still count as the item's
+    pub fuzzy_error_radius: u32,
+    /// Diagnostics at least this severe count as errors
+    pub fuzzy_min_severity: DiagnosticSeverity,
+    /// How long to wait for the server to publish diagnostics for the target file
+    pub diagnostics_timeout: Duration,
+    /// Give up on a single LSP request after this long (`None` = wait indefinitely)
+    pub request_timeout: Option<Duration>,
+    /// Skip nodes in files under any of these paths
+    pub path_excludes: Vec<PathBuf>,
+    /// Skip nodes in files matching any of these globs, e.g. `target/**` or
+    /// `**/generated/**`. As in queries, relative patterns match at any depth.
+    pub exclude_globs: Vec<String>,
+    /// Token budget for the rendered context
+    pub max_tokens: usize,
+    /// Stop expanding calls once the slice's code holds this many tokens (`None` = no limit).
+    /// Callers at each depth are then expanded most central first.
+    pub expansion_budget: Option<usize>,
+    /// Wall-clock budget for `build_graph` (`None` = no limit). A slice that runs out of
+    /// time is returned as far as it got, with `DependencyGraph::truncated` set.
+    pub slice_timeout: Option<Duration>,
+}
+
+impl Default for SlicerConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 3,
+            direction: Direction::Both,
+            include_tests: true,
+            fuzzy_mode: FuzzyMode::Auto,
+            fuzzy_error_threshold: 0,
+            fuzzy_error_radius: 0,
+            fuzzy_min_severity: DiagnosticSeverity::ERROR,
+            diagnostics_timeout: Duration::from_millis(2000),
+            request_timeout: None,
+            path_excludes: Vec::new(),
+            exclude_globs: Vec::new(),
+            max_tokens: 2000,
+            expansion_budget: None,
+            slice_timeout: None,
+        }
+    }
+}
+
+impl SlicerConfig {
+    /// Whether nodes from `file` may join the slice
+    pub fn admits(&self, file: &Path) -> bool {
+        if self.path_excludes.iter().any(|excluded| file.starts_with(excluded)) {
+            return false;
+        }
+        let path = file.to_string_lossy();
+        if self.exclude_globs.iter().any(|glob| path_matches(glob, &path)) {
+            return false;
+        }
+        self.include_tests || !is_test_path(file)
+    }
+
+    fn follows_references(&self) -> bool {
+        self.direction != Direction::Forward
+    }
+
+    fn follows_definitions(&self) -> bool {
+        self.direction != Direction::Backward
+    }
+}
+
+/// Error returned by `Slicer::build_graph_cancellable` once its token is cancelled
+#[derive(Debug)]
+pub struct SliceCancelled {
+    /// Everything sliced before the cancellation was noticed
+    pub partial: DependencyGraph,
+}
+
+impl fmt::Display for SliceCancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Slice cancelled after {} nodes", self.partial.nodes.len())
+    }
+}
+
+impl std::error::Error for SliceCancelled {}
+
+/// Buffered progress events per subscriber before slow ones start lagging
+const PROGRESS_CHANNEL_CAPACITY: usize = 256;
+
+/// Stage of `build_graph`, reported through `SliceProgress::Phase`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlicePhase {
+    /// Opening the target file and waiting for its diagnostics
+    Diagnostics,
+    /// Parts of the slice have errors; filling them in with the fuzzy slicer
+    Fuzzy,
+    /// Looking up references to and definitions of the target
+    Lookup,
+    /// Expanding calls below the definitions
+    Calls,
+    /// Filling in module paths and importance scores
+    Annotating,
+    Done,
+}
+
+/// Event emitted while a slice is being built (see `Slicer::subscribe_progress`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SliceProgress {
+    Phase(SlicePhase),
+    /// The graph now holds this many nodes
+    NodesDiscovered(usize),
+    /// Call expansion has started on this depth (1 = direct callees)
+    DepthReached(usize),
+    /// LSP requests of the current batch still awaiting a response
+    PendingRequests(usize),
+}
+
+/// Heuristic for test-only files
+fn is_test_path(file: &Path) -> bool {
+    let in_test_dir = file
+        .parent()
+        .is_some_and(|dir| dir.components().any(|c| matches!(c.as_os_str().to_str(), Some("tests" | "benches"))));
+    let name = file.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+    in_test_dir || name == "tests" || name.ends_with("_test") || name.ends_with("_tests")
+}
+
+/// 0-based new-side lines of each file a unified diff touches, by the path after `+++ b/`.
+/// A deletion touches the line that now follows it.
+fn changed_lines(patch: &str) -> BTreeMap<PathBuf, BTreeSet<u32>> {
+    let mut changed: BTreeMap<PathBuf, BTreeSet<u32>> = BTreeMap::new();
+    let mut file: Option<PathBuf> = None;
+    // Lines of the current hunk still to come, and the new-side line of the next one
+    let (mut old_left, mut new_left, mut line) = (0u32, 0u32, 0u32);
+
+    for text in patch.lines() {
+        if old_left > 0 || new_left > 0 {
+            let touched = match text.chars().next() {
+                Some('+') => {
+                    new_left = new_left.saturating_sub(1);
+                    line += 1;
+                    Some(line - 1)
+                }
+                Some('-') => {
+                    old_left = old_left.saturating_sub(1);
+                    Some(line)
+                }
+                Some('\\') => None,
+                _ => {
+                    old_left = old_left.saturating_sub(1);
+                    new_left = new_left.saturating_sub(1);
+                    line += 1;
+                    None
+                }
+            };
+            if let (Some(touched), Some(file)) = (touched, &file) {
+                changed.entry(file.clone()).or_default().insert(touched);
+            }
+        } else if let Some(path) = text.strip_prefix("+++ ") {
+            let path = path.split('\t').next().unwrap_or(path).trim();
+            file = (path != "/dev/null").then(|| PathBuf::from(path.strip_prefix("b/").unwrap_or(path)));
+        } else if let Some(header) = text.strip_prefix("@@ ") {
+            // -old_start[,old_count] +new_start[,new_count] @@
+            let range = |range: &str| {
+                let (start, count) = range.split_once(',').unwrap_or((range, "1"));
+                (start.parse::<u32>().unwrap_or(1), count.parse::<u32>().unwrap_or(1))
+            };
+            let mut ranges = header.split_whitespace();
+            let (_, old_count) = ranges.next().and_then(|r| r.strip_prefix('-')).map_or((1, 0), range);
+            let (new_start, new_count) = ranges.next().and_then(|r| r.strip_prefix('+')).map_or((1, 0), range);
+            (old_left, new_left) = (old_count, new_count);
+            line = new_start.saturating_sub(1);
+        }
+    }
+
+    changed
+}
+
+/// 0-based (file, line) of each frame in a panic message and backtrace, crash site
+/// first, as printed by `RUST_BACKTRACE=1` (`at src/main.rs:10:5`) and the panic line
+/// itself (`panicked at src/main.rs:10:5:`). Repeated locations are kept once.
+fn backtrace_locations(backtrace: &str) -> Vec<(PathBuf, u32)> {
+    let mut locations = Vec::new();
+    for text in backtrace.lines() {
+        let text = text.trim();
+        let location = match text.split_once("panicked at ") {
+            // Older toolchains quote the message first: panicked at 'msg', src/main.rs:10:5
+            Some((_, rest)) => rest.rsplit(' ').find(|token| token.contains(".rs:")).unwrap_or(rest),
+            None => match text.strip_prefix("at ") {
+                Some(location) => location,
+                None => continue,
+            },
+        };
+        // path:line:col, possibly followed by a colon
+        let mut parts = location.trim_end_matches([':', ',']).rsplitn(3, ':');
+        let (Some(_col), Some(line), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let Ok(line) = line.parse::<u32>() else {
+            continue;
+        };
+        let location = (PathBuf::from(path.strip_prefix("./").unwrap_or(path)), line.saturating_sub(1));
+        if path.ends_with(".rs") && !locations.contains(&location) {
+            locations.push(location);
+        }
+    }
+    locations
+}
+
+/// 0 for errors up to 3 for hints, so smaller is more severe
+fn severity_rank(severity: DiagnosticSeverity) -> u8 {
+    match severity {
+        DiagnosticSeverity::ERROR => 0,
+        DiagnosticSeverity::WARNING => 1,
+        DiagnosticSeverity::INFORMATION => 2,
+        _ => 3,
+    }
+}
+
+/// Stdout of `git <args>` run in `dir`
+async fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = tokio::process::Command::new("git").arg("-C").arg(dir).args(args).output().await?;
+    if !output.status.success() {
+        return Err(anyhow!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Await `request`, failing if it takes longer than `timeout`
+async fn with_timeout<T>(timeout: Option<Duration>, request: impl Future<Output = Result<T>>) -> Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, request)
+            .await
+            .map_err(|_| anyhow!("LSP request timed out after {:?}", timeout))?,
+        None => request.await,
+    }
+}
+
+/// How long `Slicer::watch` waits for a burst of file events to settle
+#[cfg(feature = "watch")]
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Guard against pathological `parentModule` chains
+const MAX_MODULE_DEPTH: usize = 32;
 
 pub struct Slicer {
     lsp: LspClient,
     extractor: Extractor,
     fuzzy: FuzzySlicer,
-    verifier: Verifier,
-    _workspace_root: PathBuf,
+    /// Filters run over candidate nodes before they are added
+    passes: Vec<Box<dyn SlicePass>>,
+    reference_options: ReferenceOptions,
+    /// Store node code as byte-range locators read on demand
+    lazy_code: bool,
+    config: SlicerConfig,
+    /// Contents of files read while slicing
+    sources: SourceCache,
+    progress_tx: broadcast::Sender<SliceProgress>,
+    /// Token of the cancellable slice in progress, if any
+    cancel: Option<CancellationToken>,
+    /// When set, each slice uses the pooled server owning the target file
+    pool: Option<LspPool>,
+    /// Limit set by `set_max_in_flight`, re-applied to each pooled client
+    max_in_flight: Option<usize>,
+    /// Slices stored by `build_graph_cached`
+    graph_cache: Option<GraphCache>,
+    /// Symbols whose calls were expanded during the current build; each is expanded
+    /// at most once, so recursive call graphs terminate
+    expanded: HashSet<NodeId>,
+    /// Nodes of the current build whose neighbours are still to be explored
+    frontier: Vec<NodeId>,
+    workspace_root: PathBuf,
 }
 
-impl Slicer {
-    pub async fn new(workspace_root: PathBuf) -> Result<Self> {
-        let lsp = LspClient::new(workspace_root.clone()).await?;
-        let extractor = Extractor::new()?;
-        let fuzzy = FuzzySlicer::new()?;
-        let verifier = Verifier::new()?;
-        Ok(Self {
-            lsp,
-            extractor,
-            fuzzy,
-            verifier,
-            _workspace_root: workspace_root,
-        })
+impl Slicer {
+    pub async fn new(workspace_root: PathBuf) -> Result<Self> {
+        Self::with_transport(workspace_root, Transport::default()).await
+    }
+
+    /// Like `new`, with the given tunables instead of the defaults
+    pub async fn with_config(workspace_root: PathBuf, config: SlicerConfig) -> Result<Self> {
+        let mut slicer = Self::new(workspace_root).await?;
+        slicer.set_config(config);
+        Ok(slicer)
+    }
+
+    /// Create a slicer talking to the language server over `transport`,
+    /// e.g. an already-indexed rust-analyzer shared with an editor
+    pub async fn with_transport(workspace_root: PathBuf, transport: Transport) -> Result<Self> {
+        Self::with_workspace_folders(vec![workspace_root], transport).await
+    }
+
+    /// Create a slicer whose language server sees several workspace folders,
+    /// so slices can follow references across them
+    pub async fn with_workspace_folders(workspace_folders: Vec<PathBuf>, transport: Transport) -> Result<Self> {
+        let lsp = LspClient::with_workspace_folders(workspace_folders, transport).await?;
+        Self::with_client(lsp)
+    }
+
+    /// Create a slicer around an already-connected client, e.g. one built with
+    /// `LspClient::builder()` to pass rust-analyzer `initializationOptions`
+    pub fn with_client(lsp: LspClient) -> Result<Self> {
+        let workspace_root = lsp
+            .workspace_folders()
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("At least one workspace folder is required"))?;
+        let extractor = Extractor::new()?;
+        let fuzzy = FuzzySlicer::new()?;
+        let passes: Vec<Box<dyn SlicePass>> = vec![Box::new(ReachabilityPass::new()?)];
+        Ok(Self {
+            lsp,
+            extractor,
+            fuzzy,
+            passes,
+            reference_options: ReferenceOptions::default(),
+            lazy_code: false,
+            config: SlicerConfig::default(),
+            sources: SourceCache::new(),
+            progress_tx: broadcast::channel(PROGRESS_CHANNEL_CAPACITY).0,
+            cancel: None,
+            pool: None,
+            max_in_flight: None,
+            graph_cache: None,
+            expanded: HashSet::new(),
+            frontier: Vec::new(),
+            workspace_root,
+        })
+    }
+
+    /// Create a slicer that can slice across unrelated repositories,
+    /// routing each target to the pool's server for its workspace
+    pub async fn with_pool(pool: LspPool, workspace_root: PathBuf) -> Result<Self> {
+        let lsp = pool.client_for_root(&workspace_root).await?;
+        let mut slicer = Self::with_client(lsp)?;
+        slicer.pool = Some(pool);
+        Ok(slicer)
+    }
+
+    /// Pay the cold-start cost before the first slice, e.g. behind a splash screen: read
+    /// the workspace's Rust files into the source cache and parse them, index their symbols for the
+    /// fuzzy slicer, and wait up to `timeout` for the server to finish indexing.
+    /// A server still busy after `timeout` is not an error; slices just start slower.
+    pub async fn warm_up(&mut self, timeout: Duration) -> Result<()> {
+        let started = Instant::now();
+        eprintln!("🔥 Warming up {}", self.workspace_root.display());
+
+        // The server indexes in its own process meanwhile
+        let mut cached = 0;
+        for path in self.workspace_files() {
+            if let Ok(source) = self.sources.read(&path) {
+                // Parsed now, so later edits reparse incrementally
+                self.extractor.update_file(&path, &source);
+                cached += 1;
+            }
+        }
+        if self.config.fuzzy_mode != FuzzyMode::Strict {
+            self.fuzzy.prescan(&self.workspace_root)?;
+        }
+
+        if self.lsp.wait_until_idle(timeout.saturating_sub(started.elapsed())).await {
+            eprintln!("✅ Warmed up in {:.1?} ({} files cached)", started.elapsed(), cached);
+        } else {
+            eprintln!("⚠️  Server still indexing after {:.1?}; slices may be slow at first", timeout);
+        }
+        Ok(())
+    }
+
+    /// Admitted `.rs` files under the workspace root, skipping `target` and hidden directories
+    fn workspace_files(&self) -> Vec<PathBuf> {
+        let entries = WalkDir::new(&self.workspace_root).sort_by_file_name().into_iter().filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0 || (name != "target" && !name.starts_with('.'))
+        });
+        entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "rs") && self.config.admits(path))
+            .collect()
+    }
+
+    /// Run `pass` over candidate nodes after the passes already added.
+    /// A new slicer starts with a `ReachabilityPass`.
+    pub fn add_pass(&mut self, pass: Box<dyn SlicePass>) {
+        self.passes.push(pass);
+    }
+
+    /// Drop every pass, including the default `ReachabilityPass`
+    pub fn clear_passes(&mut self) {
+        self.passes.clear();
+    }
+
+    /// Whether every pass keeps `candidate`; the first one to prune it says why
+    fn passes_admit(&mut self, candidate: &Candidate<'_>) -> bool {
+        for pass in &mut self.passes {
+            if let Some(reason) = pass.prune(candidate) {
+                eprintln!(
+                    "✂️ Pruned {} at {}:{} [{}]: {}",
+                    candidate.node_type,
+                    candidate.id.file.display(),
+                    candidate.id.line + 1,
+                    pass.name(),
+                    reason
+                );
+                return false;
+            }
+        }
+        true
+    }
+
+    /// True if every call site is nested under a branch or loop in the caller
+    fn is_conditional_call(&mut self, file: &PathBuf, sites: &[Range]) -> bool {
+        let Ok(content) = self.read_source(file) else {
+            return false;
+        };
+        !sites.is_empty()
+            && sites.iter().all(|site| {
+                let byte_col = position::byte_column_in(&content, site.start.line, site.start.character, self.lsp.position_encoding());
+                self.extractor.is_conditional_at(&content, site.start.line as usize, byte_col)
+            })
+    }
+
+    /// Build dependency graph from a target location. With a `slice_timeout`, a slice
+    /// that runs out of time is returned early, marked as `truncated`.
+    pub async fn build_graph(
+        &mut self,
+        target_file: PathBuf,
+        target_line: u32,
+        target_col: u32,
+    ) -> Result<DependencyGraph> {
+        let mut graph = DependencyGraph::new();
+        let Some(budget) = self.config.slice_timeout else {
+            self.build_graph_into(&mut graph, target_file, target_line, target_col).await?;
+            return Ok(graph);
+        };
+
+        // Expiry cancels the slice like `build_graph_cancellable`, in-flight requests included
+        let token = CancellationToken::new();
+        let timer = tokio::spawn({
+            let token = token.clone();
+            async move {
+                tokio::time::sleep(budget).await;
+                token.cancel();
+            }
+        });
+        let lsp = self.lsp.clone();
+        self.cancel = Some(token.clone());
+        let result = self.build_graph_into(&mut graph, target_file, target_line, target_col).await;
+        self.cancel = None;
+        self.lsp = lsp;
+        timer.abort();
+
+        match result {
+            Ok(()) => Ok(graph),
+            Err(_) if token.is_cancelled() => {
+                let frontier: BTreeSet<NodeId> = std::mem::take(&mut self.frontier)
+                    .iter()
+                    .map(|id| graph.resolve(id).clone())
+                    .filter(|id| graph.nodes.contains_key(id) && !self.expanded.contains(id))
+                    .collect();
+                eprintln!(
+                    "⏱️  Slice timed out after {:?} with {} nodes; {} left unexpanded",
+                    budget,
+                    graph.nodes.len(),
+                    frontier.len()
+                );
+                graph.truncated = Some(frontier.into_iter().collect());
+                Ok(graph)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Extend what counts as a block or symbol with the tree-sitter queries in
+    /// `dir/blocks.scm` and `dir/symbols.scm` (see `ExtractionRules::load`)
+    pub fn load_extraction_rules(&mut self, dir: &Path) -> Result<()> {
+        self.extractor.set_rules(ExtractionRules::load(dir)?);
+        self.fuzzy.set_extraction_rules(ExtractionRules::load(dir)?);
+        Ok(())
+    }
+
+    /// Store slices built by `build_graph_cached` in `cache`
+    pub fn set_graph_cache(&mut self, cache: GraphCache) {
+        self.graph_cache = Some(cache);
+    }
+
+    /// Like `build_graph`, but returns the slice stored in the graph cache when the
+    /// target, configuration and contents of every file in the slice are unchanged.
+    /// Slices reaching a file served from an overlay are never cached or reused, as the
+    /// cache hashes files on disk.
+    pub async fn build_graph_cached(
+        &mut self,
+        target_file: PathBuf,
+        target_line: u32,
+        target_col: u32,
+    ) -> Result<DependencyGraph> {
+        let target = NodeId { file: target_file.clone(), line: target_line, column: target_col };
+        let key = self.cache_key();
+
+        if let Some(cache) = &self.graph_cache
+            && let Some(graph) = cache.get(&target, &key)
+            && !self.reads_overlay(&graph)
+        {
+            eprintln!("⚡ Reusing cached slice of {} nodes", graph.nodes.len());
+            self.report(SliceProgress::NodesDiscovered(graph.nodes.len()));
+            self.report(SliceProgress::Phase(SlicePhase::Done));
+            return Ok(graph);
+        }
+
+        let graph = self.build_graph(target_file, target_line, target_col).await?;
+        if graph.truncated.is_none()
+            && !self.reads_overlay(&graph)
+            && let Some(cache) = &self.graph_cache
+            && let Err(e) = cache.put(&target, &key, &graph)
+        {
+            eprintln!("⚠️  Failed to cache slice: {}", e);
+        }
+        Ok(graph)
+    }
+
+    /// True if any of `graph`'s files is currently served from an overlay, so its
+    /// content hashes on disk don't describe what the slice was (or would be) built from
+    fn reads_overlay(&self, graph: &DependencyGraph) -> bool {
+        let overlays: HashSet<PathBuf> = self.lsp.overlay_files().into_iter().collect();
+        !overlays.is_empty() && graph.nodes.keys().any(|id| overlays.contains(&id.file))
+    }
+
+    /// Everything besides the target and file contents that shapes a slice
+    fn cache_key(&self) -> String {
+        format!(
+            "{:?} {:?} lazy={} rules={}",
+            self.config,
+            self.reference_options,
+            self.lazy_code,
+            self.extractor.rules().fingerprint()
+        )
+    }
+
+    /// Like `build_graph`, but abandons the slice once `token` is cancelled, e.g. when
+    /// an editor's cursor moves on. In-flight LSP requests are cancelled too. A cancelled
+    /// slice fails with `SliceCancelled`, which carries the graph built so far.
+    pub async fn build_graph_cancellable(
+        &mut self,
+        target_file: PathBuf,
+        target_line: u32,
+        target_col: u32,
+        token: CancellationToken,
+    ) -> Result<DependencyGraph> {
+        let lsp = self.lsp.clone();
+        self.cancel = Some(token.clone());
+        let mut graph = DependencyGraph::new();
+        let result = self.build_graph_into(&mut graph, target_file, target_line, target_col).await;
+        self.cancel = None;
+        self.lsp = lsp;
+
+        match result {
+            Ok(()) => Ok(graph),
+            Err(_) if token.is_cancelled() => {
+                eprintln!("🛑 Slice cancelled with {} nodes", graph.nodes.len());
+                Err(SliceCancelled { partial: graph }.into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn build_graph_into(
+        &mut self,
+        graph: &mut DependencyGraph,
+        target_file: PathBuf,
+        target_line: u32,
+        target_col: u32,
+    ) -> Result<()> {
+        self.expanded.clear();
+        self.use_pooled_client(&target_file).await?;
+        if let Some(token) = &self.cancel {
+            self.lsp = self.lsp.with_cancellation(token.clone());
+        }
+
+        if self.config.fuzzy_mode == FuzzyMode::Fuzzy {
+            eprintln!("🤖 Fuzzy mode forced. Using Fuzzy (LLM) Slicer.");
+            self.report(SliceProgress::Phase(SlicePhase::Fuzzy));
+            let source = self.read_source(&target_file)?;
+            self.fuzzy.set_position_encoding(self.lsp.position_encoding());
+            let mut inferred = self.fuzzy.slice_source(target_file, &source, target_line, target_col).await?;
+            for edge in &mut inferred.edges {
+                edge.metadata.inferred = true;
+            }
+            graph.merge(inferred);
+            self.report(SliceProgress::NodesDiscovered(graph.nodes.len()));
+            self.check_cancelled()?;
+            self.report(SliceProgress::Phase(SlicePhase::Annotating));
+            self.annotate_modules(graph).await;
+            self.attach_imports(graph);
+            self.annotate_symbols(graph);
+            graph.score_importance();
+            graph.score_relevance();
+            self.report(SliceProgress::Phase(SlicePhase::Done));
+            return Ok(());
+        }
+
+        self.report(SliceProgress::Phase(SlicePhase::Diagnostics));
+        self.open_for_diagnostics(&target_file).await;
+
+        self.check_cancelled()?;
+
+        // Check diagnostics to decide on slicing strategy
+        let errors = self.errors_in(&target_file);
+        let hybrid = self.config.fuzzy_mode == FuzzyMode::Auto && errors.len() > self.config.fuzzy_error_threshold;
+        let broken_target = hybrid && self.block_has_errors(&target_file, target_line, &errors);
+
+        if broken_target {
+            eprintln!("⚠️  Target has errors ({} in file). Using Strict LSP Slicer with Fuzzy (LLM) fill-in.", errors.len());
+        } else if hybrid {
+            eprintln!("⚠️  File has {} errors outside the target. Using Strict LSP Slicer.", errors.len());
+        } else {
+            eprintln!("✅ File is healthy. Using Strict LSP Slicer.");
+        }
+
+        // Strict LSP Slicer Logic
+        let target_id = NodeId {
+            file: target_file.clone(),
+            line: target_line,
+            column: target_col,
+        };
+
+        // Add target node
+        let code = self.read_location(&target_file, target_line)?;
+
+        let target = self.new_node(target_id.clone(), code, "target").with_provenance(Provenance::root("slice target"));
+        graph.add_node(target);
+        self.frontier = vec![target_id.clone()];
+        self.add_data_flow(graph, &target_id)?;
+
+        // Snap the raw position onto the enclosing syntax node before querying
+        let (query_line, query_col) = self.expand_target(&target_file, target_line, target_col).await;
+
+        // References and definitions are independent: issue them concurrently
+        let lsp = self.lsp.clone();
+        let reference_options = &self.reference_options;
+        let config = &self.config;
+        let refs_request = async {
+            if !config.follows_references() {
+                Ok(Vec::new())
+            } else if lsp.supports(LspFeature::References) {
+                let request = lsp.get_references_with(&target_file, query_line, query_col, reference_options);
+                with_timeout(config.request_timeout, request).await
+            } else {
+                eprintln!("⚠️  Server does not support references. Skipping.");
+                Ok(Vec::new())
+            }
+        };
+        let defs_request = async {
+            if !config.follows_definitions() {
+                Ok(Vec::new())
+            } else if lsp.supports(LspFeature::Definition) {
+                with_timeout(config.request_timeout, lsp.get_definition(&target_file, query_line, query_col)).await
+            } else {
+                eprintln!("⚠️  Server does not support go-to-definition. Skipping.");
+                Ok(Vec::new())
+            }
+        };
+        self.report(SliceProgress::Phase(SlicePhase::Lookup));
+        let (refs, defs) = tokio::join!(refs_request, defs_request);
+        let (refs, defs) = if broken_target {
+            // The fuzzy pass below still covers a target the server chokes on
+            let lenient = |result: Result<Vec<Location>>| {
+                result.unwrap_or_else(|e| {
+                    eprintln!("⚠️  LSP lookup failed on broken code: {}", e);
+                    Vec::new()
+                })
+            };
+            (lenient(refs), lenient(defs))
+        } else {
+            (refs?, defs?)
+        };
+
+        for location in refs {
+            if self.config.admits(&location_id(&location)?.file) {
+                self.add_reference(graph, &target_id, &location)?;
+            }
+        }
+
+        let mut def_ids = Vec::new();
+        for location in defs {
+            if !self.config.admits(&location_id(&location)?.file) {
+                continue;
+            }
+            def_ids.extend(self.add_definition(graph, &target_id, &location).await?);
+        }
+        self.report(SliceProgress::NodesDiscovered(graph.nodes.len()));
+        self.check_cancelled()?;
+
+        self.report(SliceProgress::Phase(SlicePhase::Calls));
+        self.expand_calls_transitive(graph, def_ids.clone()).await?;
+
+        let mut broken = Vec::new();
+        if broken_target {
+            broken.push(target_id.clone());
+        }
+        if hybrid {
+            for def_id in def_ids {
+                let def_errors = self.errors_in(&def_id.file);
+                if self.block_has_errors(&def_id.file, def_id.line, &def_errors) {
+                    broken.push(def_id);
+                }
+            }
+        }
+        self.check_cancelled()?;
+        self.merge_fuzzy(graph, &broken).await?;
+
+        self.check_cancelled()?;
+        self.report(SliceProgress::Phase(SlicePhase::Annotating));
+        self.annotate_modules(graph).await;
+        self.attach_imports(graph);
+        if !broken.is_empty() {
+            self.annotate_symbols(graph);
+        }
+        graph.score_importance();
+        graph.score_relevance();
+        self.report(SliceProgress::Phase(SlicePhase::Done));
+
+        Ok(())
+    }
+
+    /// Diagnostics the server has published for `file` that are at least `fuzzy_min_severity`
+    fn errors_in(&self, file: &PathBuf) -> Vec<Diagnostic> {
+        let min = severity_rank(self.config.fuzzy_min_severity);
+        let diagnostics = self.lsp.get_diagnostics(file).unwrap_or_default();
+        diagnostics
+            .into_iter()
+            .filter(|d| d.severity.is_some_and(|severity| severity_rank(severity) <= min))
+            .collect()
+    }
+
+    /// Whether any of `errors` falls inside (or within `fuzzy_error_radius` lines of)
+    /// the item enclosing `line` of `file`
+    fn block_has_errors(&mut self, file: &PathBuf, line: u32, errors: &[Diagnostic]) -> bool {
+        if errors.is_empty() {
+            return false;
+        }
+        let Ok(source) = self.read_source(file) else {
+            return true;
+        };
+        let line_of = |byte: usize| source[..byte].matches('\n').count() as u32;
+        let (first, last) = self
+            .extractor
+            .extract_block(&source, line as usize, 0)
+            .and_then(|block| locate_code(&source, line, &block))
+            .map(|locator| (line_of(locator.start_byte), line_of(locator.end_byte)))
+            .unwrap_or((line, line));
+        let radius = self.config.fuzzy_error_radius;
+        errors
+            .iter()
+            .any(|d| d.range.start.line <= last.saturating_add(radius) && d.range.end.line.saturating_add(radius) >= first)
+    }
+
+    /// Run the fuzzy slicer from each of `seeds` and merge its results into `graph`.
+    /// Its edges are marked inferred unless the strict slice already has them.
+    async fn merge_fuzzy(&mut self, graph: &mut DependencyGraph, seeds: &[NodeId]) -> Result<()> {
+        if seeds.is_empty() {
+            return Ok(());
+        }
+        self.report(SliceProgress::Phase(SlicePhase::Fuzzy));
+        self.fuzzy.set_position_encoding(self.lsp.position_encoding());
+        for seed in seeds {
+            let source = self.read_source(&seed.file)?;
+            let mut inferred = match self.fuzzy.slice_source(seed.file.clone(), &source, seed.line, seed.column).await {
+                Ok(inferred) => inferred,
+                Err(e) => {
+                    eprintln!("⚠️  Fuzzy slicing of {}:{} failed: {}", seed.file.display(), seed.line + 1, e);
+                    continue;
+                }
+            };
+            // The seed keeps its role from the strict slice
+            let role = graph.nodes.get(graph.resolve(seed)).map(|node| node.node_type.clone());
+            if let (Some(node), Some(role)) = (inferred.nodes.get_mut(seed), role) {
+                node.node_type = role;
+            }
+            for edge in &mut inferred.edges {
+                edge.metadata.inferred = true;
+            }
+            let (before_nodes, before_edges) = (graph.nodes.len(), graph.edges.len());
+            graph.merge(inferred);
+            eprintln!(
+                "🤖 Inferred {} nodes and {} edges around {}:{}",
+                graph.nodes.len() - before_nodes,
+                graph.edges.len() - before_edges,
+                seed.file.display(),
+                seed.line + 1
+            );
+            self.report(SliceProgress::NodesDiscovered(graph.nodes.len()));
+        }
+        Ok(())
+    }
+
+    /// Notify LSP that we opened the file (to ensure we get diagnostics) and wait,
+    /// bounded, for them. Overlays are already open with their in-memory text.
+    async fn open_for_diagnostics(&self, file: &PathBuf) {
+        if self.lsp.overlay_text(file).is_some() {
+            return;
+        }
+        let mut diagnostics_updates = self.lsp.subscribe_diagnostics();
+        if let Ok(full_text) = self.sources.read(file) {
+            let _ = self.lsp.did_open(file, full_text.to_string()).await;
+        }
+        let _ = self
+            .lsp
+            .wait_for_diagnostics(&mut diagnostics_updates, file, self.config.diagnostics_timeout)
+            .await;
+    }
+
+    /// Slice the context needed to explain or fix one diagnostic of `file`
+    /// (`diagnostic_index` into the server's diagnostics for it). The diagnostic's range
+    /// and its related-information locations seed a multi-target slice, which always
+    /// uses the strict slicer since the file is expected to have errors. The node at
+    /// the diagnostic is tagged with its message.
+    pub async fn slice_diagnostic(&mut self, file: PathBuf, diagnostic_index: usize) -> Result<DependencyGraph> {
+        self.use_pooled_client(&file).await?;
+        self.open_for_diagnostics(&file).await;
+
+        let diagnostics = self.lsp.get_diagnostics(&file)?;
+        let diagnostic = diagnostics.get(diagnostic_index).ok_or_else(|| {
+            anyhow!("{} has no diagnostic #{} ({} published)", file.display(), diagnostic_index, diagnostics.len())
+        })?;
+        eprintln!("🩺 Slicing diagnostic: {}", diagnostic.message);
+
+        let primary = (file.clone(), diagnostic.range.start.line, diagnostic.range.start.character);
+        let mut seeds = vec![primary.clone()];
+        for related in diagnostic.related_information.iter().flatten() {
+            let id = location_id(&related.location)?;
+            let seed = (id.file, id.line, id.column);
+            if !seeds.contains(&seed) {
+                seeds.push(seed);
+            }
+        }
+
+        let mode = std::mem::replace(&mut self.config.fuzzy_mode, FuzzyMode::Strict);
+        let graph = self.build_graph_multi(&seeds).await;
+        self.config.fuzzy_mode = mode;
+        let mut graph = graph?;
+
+        let primary_id = NodeId { file: primary.0, line: primary.1, column: primary.2 };
+        graph.annotate(&primary_id, "diagnostic", &diagnostic.message);
+        Ok(graph)
+    }
+
+    /// Data-flow slice of the local variable at the position, without the language
+    /// server: see `add_data_flow`. Fails if the position is not on a local variable.
+    pub fn slice_variable(&mut self, file: PathBuf, line: u32, col: u32) -> Result<DependencyGraph> {
+        let mut graph = DependencyGraph::new();
+        let target_id = NodeId { file, line, column: col };
+        let code = self.read_location(&target_id.file, line)?;
+        let target = self.new_node(target_id.clone(), code, "target").with_provenance(Provenance::root("slice target"));
+        graph.add_node(target);
+
+        if !self.add_data_flow(&mut graph, &target_id)? {
+            return Err(anyhow!("No local variable at {}:{}:{}", target_id.file.display(), line, col));
+        }
+        Ok(graph)
+    }
+
+    /// If `target_id` names a local variable, add a "variable" node for its declaration and
+    /// a "data_flow" node per statement of the function touching it: statements assigning
+    /// it get a `Writes` edge to the variable, statements using it a `Reads` edge.
+    /// Returns whether the target was a variable.
+    fn add_data_flow(&mut self, graph: &mut DependencyGraph, target_id: &NodeId) -> Result<bool> {
+        let source = self.read_source(&target_id.file)?;
+        let encoding = self.lsp.position_encoding();
+        let byte_col = position::byte_column_in(&source, target_id.line, target_id.column, encoding);
+        let Some(flow) = self.extractor.data_flow_at(&source, target_id.line as usize, byte_col) else {
+            return Ok(false);
+        };
+
+        let statement_id = |access: &VariableAccess| {
+            let line_text = source.lines().nth(access.line).unwrap_or_default();
+            NodeId {
+                file: target_id.file.clone(),
+                line: access.line as u32,
+                column: position::from_byte_column(line_text, access.column, encoding),
+            }
+        };
+
+        let variable_id = statement_id(&flow.declaration);
+        if !graph.contains_node(&variable_id) {
+            let node = self
+                .new_node(variable_id.clone(), flow.declaration.statement.clone(), "variable")
+                .with_provenance(Provenance { parent: Some(target_id.clone()), ..Provenance::root("data flow") });
+            graph.add_node(node);
+        }
+        let variable_id = graph.resolve(&variable_id).clone();
+
+        for access in &flow.accesses {
+            let id = statement_id(access);
+            let edge_type = match access.access {
+                Access::Read => EdgeType::Reads,
+                Access::Write => EdgeType::Writes,
+            };
+            if !graph.contains_node(&id) {
+                let provenance = Provenance::via("data flow", &variable_id, edge_type.clone());
+                let node = self.new_node(id.clone(), access.statement.clone(), "data_flow").with_provenance(provenance);
+                graph.add_node(node);
+            }
+            graph.add_edge(Edge::new(id, variable_id.clone(), edge_type));
+        }
+        // Link the target to the variable like the statement containing it
+        if graph.resolve(target_id) != &variable_id {
+            let containing = flow
+                .accesses
+                .iter()
+                .rev()
+                .find(|access| (access.line, access.column) <= (target_id.line as usize, byte_col));
+            let edge_type = match containing.map(|access| access.access) {
+                Some(Access::Read) => EdgeType::Reads,
+                Some(Access::Write) => EdgeType::Writes,
+                None => EdgeType::Defines,
+            };
+            graph.add_edge(Edge::new(target_id.clone(), variable_id.clone(), edge_type));
+        }
+
+        eprintln!("🔀 Traced `{}` through {} statements", flow.variable, flow.accesses.len());
+        Ok(true)
+    }
+
+    /// Add a node for a reference to the target, with a `References` edge into it.
+    /// References from test code become "test" nodes, or are dropped unless `include_tests` is set.
+    fn add_reference(&mut self, graph: &mut DependencyGraph, target_id: &NodeId, location: &Location) -> Result<()> {
+        let ref_id = location_id(location)?;
+
+        let source = self.read_source(&ref_id.file)?;
+        let byte_col = position::byte_column_in(&source, ref_id.line, ref_id.column, self.lsp.position_encoding());
+        let in_test = is_test_path(&ref_id.file) || self.extractor.is_test_code_at(&source, ref_id.line as usize, byte_col);
+        if in_test && !self.config.include_tests {
+            return Ok(());
+        }
+        let candidate = Candidate {
+            id: &ref_id,
+            node_type: "reference",
+            from: target_id,
+            sites: std::slice::from_ref(&location.range),
+            file: &ref_id.file,
+            source: &source,
+            encoding: self.lsp.position_encoding(),
+        };
+        if !self.passes_admit(&candidate) {
+            return Ok(());
+        }
+
+        // Add reference node with the function it sits in; references in the same
+        // function share its symbol and merge into one node on insert
+        let ref_code = match self.extractor.extract_enclosing_function(&source, ref_id.line as usize, byte_col) {
+            Some(function) => function,
+            None => self.read_location(&ref_id.file, ref_id.line)?,
+        };
+        let node_type = if in_test { "test" } else { "reference" };
+        let provenance = Provenance::via("textDocument/references", target_id, EdgeType::References);
+        let node = self.new_node(ref_id.clone(), ref_code, node_type).with_provenance(provenance);
+        graph.add_node(node);
+
+        // Add edge: reference -> target
+        let metadata = EdgeMetadata { sites: vec![location.range], ..EdgeMetadata::default() };
+        graph.add_edge(Edge::new(ref_id, target_id.clone(), EdgeType::References).with_metadata(metadata));
+        Ok(())
+    }
+
+    /// Add a node for a definition of the target, with a `Defines` edge to it.
+    /// Returns `None` if a pass pruned it.
+    async fn add_definition(&mut self, graph: &mut DependencyGraph, target_id: &NodeId, location: &Location) -> Result<Option<NodeId>> {
+        let def_id = location_id(location)?;
+
+        let source = self.read_source(&target_id.file)?;
+        let target_range = Range::new(
+            Position::new(target_id.line, target_id.column),
+            Position::new(target_id.line, target_id.column),
+        );
+        let candidate = Candidate {
+            id: &def_id,
+            node_type: "definition",
+            from: target_id,
+            sites: std::slice::from_ref(&target_range),
+            file: &target_id.file,
+            source: &source,
+            encoding: self.lsp.position_encoding(),
+        };
+        if !self.passes_admit(&candidate) {
+            return Ok(None);
+        }
+
+        // Add definition node
+        let def_code = self.read_implementation(&def_id.file, def_id.line).await?;
+        let provenance = Provenance::via("textDocument/definition", target_id, EdgeType::Defines);
+        let node = self.new_node(def_id.clone(), def_code, "definition").with_provenance(provenance);
+        graph.add_node(node);
+
+        // Add edge: target -> definition
+        graph.add_edge(Edge::new(target_id.clone(), def_id.clone(), EdgeType::Defines));
+        Ok(Some(def_id))
+    }
+
+    /// Expand callees breadth-first from `roots`, down to `max_depth` levels,
+    /// expanding each function at most once and stopping once `expansion_budget` is spent
+    async fn expand_calls_transitive(&mut self, graph: &mut DependencyGraph, roots: Vec<NodeId>) -> Result<()> {
+        let mut frontier = roots;
+        let mut spent: usize = match self.config.expansion_budget {
+            Some(_) => graph.nodes.values().map(|node| estimate_tokens(&node.text())).sum(),
+            None => 0,
+        };
+        for depth in 1..=self.config.max_depth {
+            self.report(SliceProgress::DepthReached(depth));
+            if self.config.expansion_budget.is_some() {
+                let importance = graph.pagerank();
+                let score = |id: &NodeId| importance.get(graph.resolve(id)).copied().unwrap_or(0.0);
+                frontier.sort_by(|a, b| score(b).total_cmp(&score(a)).then_with(|| a.cmp(b)));
+            }
+            self.frontier = frontier.clone();
+            let mut next = Vec::new();
+            for caller in frontier {
+                self.check_cancelled()?;
+                if let Some(budget) = self.config.expansion_budget
+                    && spent > budget
+                {
+                    eprintln!("💰 Slice reached its budget of {} tokens at depth {}; not expanding further", budget, depth);
+                    self.frontier.clear();
+                    return Ok(());
+                }
+                if self.expanded.insert(graph.resolve(&caller).clone()) {
+                    let added = self.expand_calls(graph, &caller).await?;
+                    if self.config.expansion_budget.is_some() {
+                        spent += added
+                            .iter()
+                            .filter_map(|id| graph.nodes.get(graph.resolve(id)))
+                            .map(|node| estimate_tokens(&node.text()))
+                            .sum::<usize>();
+                    }
+                    if depth < self.config.max_depth {
+                        self.frontier.extend(added.iter().cloned());
+                    }
+                    next.extend(added);
+                    self.report(SliceProgress::NodesDiscovered(graph.nodes.len()));
+                }
+            }
+            if next.is_empty() {
+                break;
+            }
+            frontier = next;
+        }
+        self.frontier.clear();
+        Ok(())
+    }
+
+    /// Add the functions `def_id` calls, pruning calls whose every site is unreachable.
+    /// Returns the callees that were new to the graph.
+    async fn expand_calls(&mut self, graph: &mut DependencyGraph, def_id: &NodeId) -> Result<Vec<NodeId>> {
+        let mut added = Vec::new();
+        if !self.lsp.supports(LspFeature::CallHierarchy) {
+            return Ok(added);
+        }
+        let def_path = &def_id.file;
+        let timeout = self.config.request_timeout;
+        let hierarchy_items =
+            with_timeout(timeout, self.lsp.prepare_call_hierarchy(def_path, def_id.line, def_id.column)).await?;
+        for outgoing in with_timeout(timeout, self.outgoing_calls(hierarchy_items)).await? {
+            for call in outgoing {
+                let call_item = call.to;
+                let uri_str = call_item.uri.as_str();
+                // Skip if uri parsing fails or not a file
+                if let Ok(url) = Url::parse(uri_str)
+                    && let Ok(call_path) = url.to_file_path()
+                    && self.config.admits(&call_path) {
+                        let call_line = call_item.range.start.line;
+                        let call_col = call_item.range.start.character;
+
+                        let call_id = NodeId {
+                            file: call_path.clone(),
+                            line: call_line,
+                            column: call_col,
+                        };
+
+                        // Avoid cycles or duplicates if already added
+                        if !graph.contains_node(&call_id) {
+                            // An unreadable caller can't be checked; let the passes see it empty
+                            let source = self.read_source(def_path).unwrap_or_else(|_| Arc::from(""));
+                            let candidate = Candidate {
+                                id: &call_id,
+                                node_type: "call",
+                                from: def_id,
+                                sites: &call.from_ranges,
+                                file: def_path,
+                                source: &source,
+                                encoding: self.lsp.position_encoding(),
+                            };
+                            if !self.passes_admit(&candidate) {
+                                continue;
+                            }
+
+                            let call_code = self.read_implementation(&call_path, call_line).await?;
+                            let provenance = Provenance::via("callHierarchy/outgoingCalls", def_id, EdgeType::Calls);
+                            let node = self.new_node(call_id.clone(), call_code, "call").with_provenance(provenance);
+                            graph.add_node(node);
+                            let call_id = graph.resolve(&call_id).clone();
+                            added.push(call_id.clone());
+                            added.extend(self.add_implementations(graph, &call_id, call_item.selection_range.start).await?);
+                        }
+
+                        let call_id = graph.resolve(&call_id).clone();
+                        let metadata = EdgeMetadata {
+                            sites: call.from_ranges.clone(),
+                            occurrences: call.from_ranges.len().max(1) as u32,
+                            conditional: self.is_conditional_call(def_path, &call.from_ranges),
+                            cycle: graph.closes_call_cycle(def_id, &call_id),
+                            ..EdgeMetadata::default()
+                        };
+                        if metadata.cycle {
+                            eprintln!("🔁 Recursive call from {}:{} to {}:{}", def_path.display(), def_id.line + 1, call_path.display(), call_line + 1);
+                        }
+                        // A call to a trait method may dispatch to any of its implementations
+                        let implementations: Vec<NodeId> = graph
+                            .incoming_edges(&call_id)
+                            .filter(|edge| edge.edge_type == EdgeType::Implements)
+                            .map(|edge| edge.from.clone())
+                            .collect();
+                        for implementation in implementations {
+                            let metadata = EdgeMetadata { conditional: true, ..metadata.clone() };
+                            graph.add_edge(Edge::new(def_id.clone(), implementation, EdgeType::Calls).with_metadata(metadata));
+                        }
+                        graph.add_edge(Edge::new(def_id.clone(), call_id, EdgeType::Calls).with_metadata(metadata));
+                    }
+            }
+        }
+        Ok(added)
+    }
+
+    /// If `method_id` is a trait method, add its implementations with `Implements` edges
+    /// into it. `name_at` is the position of the method's name. Returns the
+    /// implementations that were new to the graph.
+    async fn add_implementations(&mut self, graph: &mut DependencyGraph, method_id: &NodeId, name_at: Position) -> Result<Vec<NodeId>> {
+        let mut added = Vec::new();
+        if !self.lsp.supports(LspFeature::Implementation) {
+            return Ok(added);
+        }
+        let Ok(source) = self.read_source(&method_id.file) else {
+            return Ok(added);
+        };
+        let byte_col = position::byte_column_in(&source, name_at.line, name_at.character, self.lsp.position_encoding());
+        if !self.extractor.is_trait_method_at(&source, name_at.line as usize, byte_col) {
+            return Ok(added);
+        }
+
+        let request = self.lsp.get_implementations(&method_id.file, name_at.line, name_at.character);
+        for location in with_timeout(self.config.request_timeout, request).await? {
+            let impl_id = location_id(&location)?;
+            if !self.config.admits(&impl_id.file) || graph.resolve(&impl_id) == method_id {
+                continue;
+            }
+            if !graph.contains_node(&impl_id) {
+                let code = self.read_implementation(&impl_id.file, impl_id.line).await?;
+                let provenance = Provenance::via("textDocument/implementation", method_id, EdgeType::Implements);
+                let node = self.new_node(impl_id.clone(), code, "implementation").with_provenance(provenance);
+                graph.add_node(node);
+                added.push(graph.resolve(&impl_id).clone());
+            }
+            graph.add_edge(Edge::new(impl_id, method_id.clone(), EdgeType::Implements));
+        }
+        if !added.is_empty() {
+            eprintln!("🧩 Found {} implementations of trait method at {}:{}", added.len(), method_id.file.display(), method_id.line + 1);
+        }
+        Ok(added)
+    }
+
+    /// Slice the target as of two git revisions (e.g. `"main"` and `"HEAD"`) and report
+    /// how the slice changed: nodes and edges gained or lost, and nodes whose code differs.
+    /// Rust files that differ between the revisions, or between `HEAD` and what the slicer
+    /// currently sees (uncommitted edits, untracked files, overlays), are overlaid with
+    /// their contents from `git show`, leaving the working tree alone. The position is
+    /// used as-is in both.
+    pub async fn diff_revisions(
+        &mut self,
+        target_file: PathBuf,
+        target_line: u32,
+        target_col: u32,
+        old_rev: &str,
+        new_rev: &str,
+    ) -> Result<GraphDelta> {
+        let dir = target_file.parent().ok_or_else(|| anyhow!("{} has no parent directory", target_file.display()))?;
+        let repo = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"]).await?.trim());
+        let mut changed = BTreeSet::new();
+        for args in [
+            &["diff", "--name-only", old_rev, new_rev, "--", "*.rs"][..],
+            &["diff", "--name-only", "HEAD", "--", "*.rs"],
+            &["ls-files", "--others", "--exclude-standard", "--", "*.rs"],
+        ] {
+            changed.extend(git(&repo, args).await?.lines().map(|path| repo.join(path)));
+        }
+        changed.extend(
+            self.lsp
+                .overlay_files()
+                .into_iter()
+                .filter(|file| file.starts_with(&repo) && file.extension().is_some_and(|ext| ext == "rs")),
+        );
+        let changed: Vec<PathBuf> = changed.into_iter().collect();
+
+        let old = self.build_graph_at(&repo, old_rev, &changed, &target_file, target_line, target_col).await?;
+        let new = self.build_graph_at(&repo, new_rev, &changed, &target_file, target_line, target_col).await?;
+        let delta = old.diff(&new);
+        eprintln!(
+            "🔁 {}..{}: +{} -{} ~{} nodes, +{} -{} edges",
+            old_rev,
+            new_rev,
+            delta.added_nodes.len(),
+            delta.removed_nodes.len(),
+            delta.changed_nodes.len(),
+            delta.added_edges.len(),
+            delta.removed_edges.len()
+        );
+        Ok(delta)
+    }
+
+    /// `build_graph` with each of `files` overlaid by its contents at `rev`
+    /// (empty if it doesn't exist there). Every overlay is undone on the way out,
+    /// including after a failure, and overlays that were already open are restored.
+    async fn build_graph_at(
+        &mut self,
+        repo: &Path,
+        rev: &str,
+        files: &[PathBuf],
+        target_file: &Path,
+        target_line: u32,
+        target_col: u32,
+    ) -> Result<DependencyGraph> {
+        let mut opened: Vec<(&PathBuf, Option<String>)> = Vec::new();
+        let mut result = Ok(DependencyGraph::new());
+        for file in files {
+            let relative = file.strip_prefix(repo).unwrap_or(file);
+            let text = git(repo, &["show", &format!("{}:{}", rev, relative.display())]).await.unwrap_or_default();
+            // Recorded first: a failed open may still have registered the overlay
+            opened.push((file, self.lsp.overlay_text(file)));
+            if let Err(e) = self.open_overlay(file, text).await {
+                result = Err(e);
+                break;
+            }
+        }
+
+        if result.is_ok() {
+            result = self.build_graph(target_file.to_path_buf(), target_line, target_col).await;
+        }
+
+        let mut restored = Ok(());
+        for (file, previous) in opened {
+            let outcome = match previous {
+                Some(text) => self.open_overlay(file, text).await,
+                None => self.close_overlay(file).await,
+            };
+            if let Err(e) = outcome
+                && restored.is_ok()
+            {
+                restored = Err(e);
+            }
+        }
+        let graph = result?;
+        restored?;
+        Ok(graph)
     }
 
-    /// Check if a location is reachable based on static constraints
-    fn is_reachable(&mut self, file: &PathBuf, line: u32, col: u32) -> bool {
-        // Read file content (inefficient to re-read, but simple for MVP)
-        // In production we should cache this
-        let content = match fs::read_to_string(file) {
-            Ok(c) => c,
-            Err(_) => return true, // Assume reachable if we can't read
-        };
+    /// Slice several seed locations (e.g. every function a change touches) and merge
+    /// the slices into one deduplicated graph. Every node gets a `seeds` tag listing
+    /// the `file:line:col` seeds whose slice reached it.
+    pub async fn build_graph_multi(&mut self, seeds: &[(PathBuf, u32, u32)]) -> Result<DependencyGraph> {
+        let mut graph = DependencyGraph::new();
+        for (file, line, col) in seeds {
+            eprintln!("🌱 Slicing seed {}:{}:{}", file.display(), line, col);
+            let seed_graph = self.build_graph(file.clone(), *line, *col).await?;
+            let seed = format!("{}:{}:{}", file.display(), line, col);
+            let reached: Vec<NodeId> = seed_graph.nodes.keys().cloned().collect();
+            let targets: Vec<NodeId> = seed_graph.targets().into_iter().map(|node| node.id.clone()).collect();
+            graph.merge(seed_graph);
+
+            for id in &reached {
+                let id = graph.resolve(id).clone();
+                if let Some(node) = graph.nodes.get_mut(&id) {
+                    add_seed(&mut node.tags, &seed);
+                }
+            }
+            // A seed may already be in the graph as another seed's callee
+            for id in &targets {
+                let id = graph.resolve(id).clone();
+                if let Some(node) = graph.nodes.get_mut(&id) {
+                    node.node_type = "target".to_string();
+                }
+            }
+        }
 
-        let (assignments, conditions) = self.extractor.extract_constraints(&content, line as usize, col as usize);
+        graph.score_importance();
+        graph.score_relevance();
+        Ok(graph)
+    }
 
-        if assignments.is_empty() && conditions.is_empty() {
-            return true;
+    /// Slice everything a unified diff (e.g. a PR patch) touches. Each changed line is
+    /// mapped to its enclosing item, and the items seed one `build_graph_multi` slice.
+    /// Patch paths are relative to the workspace root; changes outside any item
+    /// (imports, deleted files) seed nothing.
+    pub async fn slice_diff(&mut self, patch: &str) -> Result<DependencyGraph> {
+        let mut seeds = BTreeSet::new();
+        for (path, lines) in changed_lines(patch) {
+            let file = self.workspace_root.join(path);
+            let source = match self.read_source(&file) {
+                Ok(source) => source,
+                Err(e) => {
+                    eprintln!("⚠️  Skipping {}: {}", file.display(), e);
+                    continue;
+                }
+            };
+            for line in lines {
+                seeds.extend(self.item_seed(&file, &source, line));
+            }
+        }
+
+        if seeds.is_empty() {
+            return Err(anyhow!("Patch touches no items in the workspace"));
+        }
+        eprintln!("🩹 Patch touches {} items", seeds.len());
+        let seeds: Vec<(PathBuf, u32, u32)> = seeds.into_iter().collect();
+        self.build_graph_multi(&seeds).await
+    }
+
+    /// Slice the crash path of a panic: every frame of a pasted backtrace (and the panic
+    /// location) that falls in the workspace seeds its enclosing function, and the seeds
+    /// make one `build_graph_multi` slice. Frames in the standard library or
+    /// dependencies are skipped. Needs `RUST_BACKTRACE=1` output for more than the crash site.
+    pub async fn slice_backtrace(&mut self, backtrace: &str) -> Result<DependencyGraph> {
+        let mut seeds = Vec::new();
+        for (path, line) in backtrace_locations(backtrace) {
+            let file = if path.is_absolute() { path } else { self.workspace_root.join(path) };
+            if !file.starts_with(&self.workspace_root) || !self.config.admits(&file) {
+                continue;
+            }
+            let Ok(source) = self.read_source(&file) else {
+                continue;
+            };
+            if let Some(seed) = self.item_seed(&file, &source, line)
+                && !seeds.contains(&seed)
+            {
+                seeds.push(seed);
+            }
+        }
+
+        if seeds.is_empty() {
+            return Err(anyhow!("Backtrace has no frames in the workspace"));
         }
+        eprintln!("💥 Crash path runs through {} functions", seeds.len());
+        self.build_graph_multi(&seeds).await
+    }
 
-        // Convert to verifier format
-        let mut constraints = Vec::new();
-        for c in &assignments {
-            constraints.push((c.var.as_str(), c.op.as_str(), c.val));
+    /// Slice a test for failure triage: find the test named like the harness prints it
+    /// (`module::tests::test_name`, or just `test_name`), slice forward through
+    /// everything it calls, and tag every node containing assertions with their lines
+    /// under "assertions". A name matching several tests seeds all of them.
+    pub async fn slice_test(&mut self, test_path: &str) -> Result<DependencyGraph> {
+        let mut segments: Vec<&str> = test_path.split("::").filter(|s| !s.is_empty()).collect();
+        let name = segments.pop().ok_or_else(|| anyhow!("Empty test name"))?;
+
+        let mut seeds = Vec::new();
+        for file in self.workspace_files() {
+            let Ok(source) = self.read_source(&file) else {
+                continue;
+            };
+            if !source.contains(name) {
+                continue;
+            }
+            for test in self.extractor.test_functions(&source) {
+                let mut modules = file_modules(&file);
+                modules.extend(test.modules);
+                if test.name != name || !modules_match(&segments, &modules) {
+                    continue;
+                }
+                let (line, byte_col) = test.name_position;
+                let text = source.lines().nth(line).unwrap_or_default();
+                let col = position::from_byte_column(text, byte_col, self.lsp.position_encoding());
+                seeds.push((file.clone(), line as u32, col));
+            }
         }
-        for c in &conditions {
-            constraints.push((c.var.as_str(), c.op.as_str(), c.val));
+
+        match seeds.len() {
+            0 => return Err(anyhow!("No test named {} in the workspace", test_path)),
+            1 => eprintln!("🧪 Slicing test {}", test_path),
+            n => eprintln!("⚠️  {} tests match {}; slicing all of them", n, test_path),
         }
 
-        let consistent = self.verifier.check_consistency(&constraints);
-        if !consistent {
-            eprintln!("✂️ Pruned unreachable code at {}:{}:{} (Constraints: {:?} + {:?})",
-                file.display(), line, col, assignments, conditions);
+        let direction = std::mem::replace(&mut self.config.direction, Direction::Forward);
+        let graph = self.build_graph_multi(&seeds).await;
+        self.config.direction = direction;
+        let mut graph = graph?;
+
+        self.flag_assertions(&mut graph);
+        Ok(graph)
+    }
+
+    /// Tag nodes whose code asserts something with the 1-based lines of the
+    /// assertions, under "assertions"
+    fn flag_assertions(&self, graph: &mut DependencyGraph) {
+        for node in graph.nodes.values_mut() {
+            let code = node.text().into_owned();
+            let Ok(source) = self.read_source(&node.id.file) else {
+                continue;
+            };
+            let Some(locator) = locate_code(&source, node.id.line, &code) else {
+                continue;
+            };
+            let first_line = source[..locator.start_byte].matches('\n').count();
+            let lines: Vec<String> = code
+                .lines()
+                .enumerate()
+                .filter(|(_, text)| is_assertion(text))
+                .map(|(offset, _)| (first_line + offset + 1).to_string())
+                .collect();
+            if !lines.is_empty() {
+                node.tags.insert("assertions".to_string(), lines.join(", "));
+            }
         }
-        consistent
     }
 
-    /// Build dependency graph from a target location
-    pub async fn build_graph(
+    /// Seed position for the item enclosing 0-based `line`: the item's name, where the
+    /// server can resolve it
+    fn item_seed(&mut self, file: &Path, source: &str, line: u32) -> Option<(PathBuf, u32, u32)> {
+        let source_lines: Vec<&str> = source.lines().collect();
+        let indent = source_lines
+            .get(line as usize)
+            .map_or(0, |text| text.len() - text.trim_start().len());
+        let item = self.extractor.describe_item_at(source, line as usize, indent)?;
+        let (name_line, byte_col) = item.name_position.unwrap_or((item.line, 0));
+        let text = source_lines.get(name_line).copied().unwrap_or_default();
+        let col = position::from_byte_column(text, byte_col, self.lsp.position_encoding());
+        Some((file.to_path_buf(), name_line as u32, col))
+    }
+
+    /// Bring `graph` up to date after `file` changed: drop everything from that file
+    /// and re-slice only around it, instead of rebuilding the whole graph.
+    /// Falls back to a full rebuild when the target itself lives in `file`.
+    pub async fn refresh_file(&mut self, graph: &mut DependencyGraph, file: &Path) -> Result<()> {
+        let target_id = graph
+            .target()
+            .map(|node| node.id.clone())
+            .ok_or_else(|| anyhow!("Graph has no target node"))?;
+
+        if target_id.file == file {
+            *graph = self.build_graph(target_id.file, target_id.line, target_id.column).await?;
+            return Ok(());
+        }
+
+        let file = file.to_path_buf();
+        self.sources.invalidate(&file);
+        if self.lsp.overlay_text(&file).is_none() {
+            self.lsp.notify_file_changed(&file).await?;
+        }
+        // Reparse from the file's previous tree; a deleted file is just forgotten
+        match self.read_source(&file) {
+            Ok(source) => {
+                self.extractor.update_file(&file, &source);
+            }
+            Err(_) => self.extractor.forget_file(&file),
+        }
+
+        self.expanded.clear();
+        let dropped = graph.invalidate_file(&file);
+        eprintln!("♻️  Refreshing {} ({} edges invalidated)", file.display(), dropped.len());
+
+        // Surviving callers of removed code: re-expand their calls into the new version
+        let mut callers: Vec<NodeId> = dropped
+            .iter()
+            .filter(|edge| edge.edge_type == EdgeType::Calls && graph.contains_node(&edge.from))
+            .map(|edge| edge.from.clone())
+            .collect();
+        callers.sort();
+        callers.dedup();
+
+        // The target's references and definitions may now sit anywhere in the file
+        let (query_line, query_col) = self.expand_target(&target_id.file, target_id.line, target_id.column).await;
+        let timeout = self.config.request_timeout;
+        if self.config.follows_references() && self.lsp.supports(LspFeature::References) {
+            let request = self.lsp.get_references_with(&target_id.file, query_line, query_col, &self.reference_options);
+            let refs = with_timeout(timeout, request).await?;
+            for location in refs {
+                if location_id(&location)?.file == file && self.config.admits(&file) {
+                    self.add_reference(graph, &target_id, &location)?;
+                }
+            }
+        }
+        if self.config.follows_definitions() && self.lsp.supports(LspFeature::Definition) {
+            let request = self.lsp.get_definition(&target_id.file, query_line, query_col);
+            for location in with_timeout(timeout, request).await? {
+                if location_id(&location)?.file == file && self.config.admits(&file) {
+                    callers.extend(self.add_definition(graph, &target_id, &location).await?);
+                }
+            }
+        }
+
+        self.expand_calls_transitive(graph, callers).await?;
+
+        self.annotate_modules(graph).await;
+        self.attach_imports(graph);
+        graph.score_importance();
+        graph.score_relevance();
+        Ok(())
+    }
+
+    /// Slice the target, then keep the slice current as its files change on disk.
+    /// Each burst of edits is applied with `refresh_file`, after which `on_update`
+    /// gets the updated graph and its context. Returns once `on_update` breaks.
+    #[cfg(feature = "watch")]
+    pub async fn watch(
         &mut self,
         target_file: PathBuf,
         target_line: u32,
         target_col: u32,
-    ) -> Result<DependencyGraph> {
-        // Notify LSP that we opened the file (to ensure we get diagnostics)
-        if let Ok(full_text) = fs::read_to_string(&target_file) {
-            let _ = self.lsp.did_open(&target_file, full_text).await;
+        mut on_update: impl FnMut(&DependencyGraph, &str) -> std::ops::ControlFlow<()>,
+    ) -> Result<()> {
+        use anyhow::Context;
+        use notify::{RecursiveMode, Watcher};
+        use tokio::sync::mpsc;
+
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel::<PathBuf>();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else { return };
+            if event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove() {
+                for path in event.paths {
+                    let _ = event_tx.send(path);
+                }
+            }
+        })?;
+
+        let mut graph = self.build_graph(target_file, target_line, target_col).await?;
+        let mut watched_dirs = HashSet::new();
+        loop {
+            if on_update(&graph, &self.extract_context(&graph, self.config.max_depth)).is_break() {
+                return Ok(());
+            }
+
+            // Watch directories rather than files: editors often save by replacing the file
+            let files: HashSet<PathBuf> = graph.nodes.keys().map(|id| id.file.clone()).collect();
+            for dir in files.iter().filter_map(|file| file.parent()) {
+                if watched_dirs.insert(dir.to_path_buf()) {
+                    watcher
+                        .watch(dir, RecursiveMode::NonRecursive)
+                        .with_context(|| format!("Failed to watch {}", dir.display()))?;
+                }
+            }
+
+            // Wait for a change in the slice, then let the rest of the burst arrive
+            let mut changed = BTreeSet::new();
+            while changed.is_empty() {
+                let path = event_rx.recv().await.ok_or_else(|| anyhow!("File watcher stopped"))?;
+                if files.contains(&path) {
+                    changed.insert(path);
+                }
+            }
+            tokio::time::sleep(WATCH_DEBOUNCE).await;
+            while let Ok(path) = event_rx.try_recv() {
+                if files.contains(&path) {
+                    changed.insert(path);
+                }
+            }
+
+            for file in &changed {
+                eprintln!("👀 {} changed", file.display());
+                if let Err(e) = self.refresh_file(&mut graph, file).await {
+                    eprintln!("⚠️  Failed to refresh {}: {}", file.display(), e);
+                }
+            }
+        }
+    }
+
+    /// Annotate every node with its owning crate and module path
+    async fn annotate_modules(&self, graph: &mut DependencyGraph) {
+        let mut module_paths: HashMap<PathBuf, Option<String>> = HashMap::new();
+        let files: HashSet<PathBuf> = graph.nodes.keys().map(|id| id.file.clone()).collect();
+
+        for file in files {
+            let module_path = self.module_path(&file).await;
+            module_paths.insert(file, module_path);
         }
 
-        // Give LSP a moment to process diagnostics
-        tokio::time::sleep(std::time::Duration::from_millis(2000)).await;
+        for node in graph.nodes.values_mut() {
+            node.module_path = module_paths.get(&node.id.file).cloned().flatten();
+        }
+    }
 
-        // Check diagnostics to decide on slicing strategy
-        let diagnostics = self.lsp.get_diagnostics(&target_file).unwrap_or_default();
-        let error_count = diagnostics
-            .iter()
-            .filter(|d| d.severity == Some(DiagnosticSeverity::ERROR))
-            .count();
+    /// Attach to each node the `use` declarations of its file that its code mentions,
+    /// so rendered context still resolves when pasted elsewhere. Glob imports are
+    /// always attached since their names are unknown.
+    fn attach_imports(&mut self, graph: &mut DependencyGraph) {
+        let mut uses: HashMap<PathBuf, Vec<UseDeclaration>> = HashMap::new();
 
-        if error_count > 0 {
-            eprintln!("⚠️  File has {} errors. Switching to Fuzzy (LLM) Slicer.", error_count);
-            return self.fuzzy.slice(target_file, target_line, target_col).await;
+        for node in graph.nodes.values_mut() {
+            let declarations = uses.entry(node.id.file.clone()).or_insert_with(|| {
+                self.read_source(&node.id.file)
+                    .map(|source| self.extractor.use_declarations(&source))
+                    .unwrap_or_default()
+            });
+            let imports = {
+                let code = node.text();
+                let used = identifiers(&code);
+                declarations
+                    .iter()
+                    .filter(|declaration| declaration.glob || declaration.names.iter().any(|name| used.contains(name.as_str())))
+                    .map(|declaration| declaration.text.clone())
+                    .collect()
+            };
+            node.imports = imports;
         }
+    }
 
-        eprintln!("✅ File is healthy. Using Strict LSP Slicer.");
+    /// Fill in name, kind, signature and docs of the item enclosing each node,
+    /// and merge nodes that turn out to be the same item
+    fn annotate_symbols(&mut self, graph: &mut DependencyGraph) {
+        for node in graph.nodes.values_mut() {
+            if let Ok(source) = self.read_source(&node.id.file) {
+                self.describe_node(node, &source);
+            }
+        }
 
-        // Strict LSP Slicer Logic
-        let mut graph = DependencyGraph::new();
+        graph.compact_by_container();
+    }
 
-        let target_id = NodeId {
-            file: target_file.clone(),
-            line: target_line,
-            column: target_col,
+    /// Create a node with its symbol details filled in, so the graph can dedupe it on insert
+    fn new_node(&mut self, id: NodeId, code: String, node_type: &str) -> CodeNode {
+        let source = self.read_source(&id.file).ok();
+        // Overlay text isn't on disk, so only locate code in files read from disk
+        let locator = source
+            .as_deref()
+            .filter(|_| self.lazy_code && self.lsp.overlay_text(&id.file).is_none())
+            .and_then(|source| locate_code(source, id.line, &code));
+        let mut node = match locator {
+            Some(locator) => CodeNode::lazy(id, locator, &code, node_type),
+            None => CodeNode::new(id, code, node_type),
         };
+        if let Some(source) = &source {
+            self.describe_node(&mut node, source);
+        }
+        node
+    }
 
-        // Add target node
-        let code = self.read_location(&target_file, target_line)?;
-
-        graph.add_node(CodeNode {
-            id: target_id.clone(),
-            code,
-            node_type: "target".to_string(),
+    fn describe_node(&mut self, node: &mut CodeNode, source: &str) {
+        let byte_col = position::byte_column_in(source, node.id.line, node.id.column, self.lsp.position_encoding());
+        let Some(details) = self.extractor.describe_item_at(source, node.id.line as usize, byte_col) else {
+            return;
+        };
+        node.symbol_id = details.name.clone().map(|name| SymbolId {
+            file: node.id.file.clone(),
+            name,
+            kind: details.kind,
+            line: details.line as u32,
         });
+        node.symbol_name = details.name;
+        node.symbol_kind = Some(details.kind);
+        node.signature = Some(details.signature);
+        node.docs = details.docs;
+    }
 
-        // Get all references to this location
-        let refs = self
-            .lsp
-            .get_references(&target_file, target_line, target_col)
-            .await?;
+    /// Crate-qualified module path of a file, e.g. "graphslice::lsp_client".
+    /// Follows `experimental/parentModule` up to the crate root.
+    pub async fn module_path(&self, file: &Path) -> Option<String> {
+        let crate_name = crate_name_for(file)?;
+        let mut segments = Vec::new();
 
-        for location in refs {
-            let uri_str = location.uri.as_str();
-            let url = Url::parse(uri_str).map_err(|e| anyhow!("Failed to parse URI: {}", e))?;
-            let ref_path = url.to_file_path().map_err(|_| anyhow!("URI is not a file path: {}", uri_str))?;
+        if self.lsp.supports(LspFeature::ParentModule) {
+            let mut current = file.to_path_buf();
+            for _ in 0..MAX_MODULE_DEPTH {
+                let Ok(parents) = self.lsp.parent_module(&current, 0, 0).await else {
+                    break;
+                };
 
-            let ref_line = location.range.start.line;
-            let ref_col = location.range.start.character;
+                // The crate root has no parent module (or points at Cargo.toml)
+                let parent = parents.into_iter().find_map(|location| {
+                    let path = Url::parse(location.uri.as_str()).ok()?.to_file_path().ok()?;
+                    let is_rust = path.extension().and_then(|e| e.to_str()) == Some("rs");
+                    (is_rust && path != current).then_some((path, location.range.start.line))
+                });
+                let Some((parent_file, mod_line)) = parent else {
+                    break;
+                };
+                let source = self.read_source(&parent_file).ok();
+                let Some(name) = source.and_then(|text| mod_name_at(&text, mod_line)) else {
+                    break;
+                };
 
-            let ref_id = NodeId {
-                file: ref_path.clone(),
-                line: ref_line,
-                column: ref_col,
-            };
+                segments.push(name);
+                current = parent_file;
+            }
+        }
 
-            // Add reference node
-            let ref_code = self.read_location(&ref_path, ref_line)?;
-            graph.add_node(CodeNode {
-                id: ref_id.clone(),
-                code: ref_code,
-                node_type: "reference".to_string(),
-            });
+        segments.push(crate_name);
+        segments.reverse();
+        Some(segments.join("::"))
+    }
 
-            // Add edge: reference -> target
-            graph.add_edge(Edge {
-                from: ref_id,
-                to: target_id.clone(),
-                edge_type: EdgeType::References,
-            });
+    /// Expand a raw (line, col) to the start of the innermost non-empty selection range,
+    /// so positions on whitespace or punctuation still resolve to a symbol.
+    /// Falls back to the raw position if the server can't help.
+    async fn expand_target(&self, file: &PathBuf, line: u32, col: u32) -> (u32, u32) {
+        if !self.lsp.supports(LspFeature::SelectionRange) {
+            return (line, col);
         }
 
-        // Get definition
-        let defs = self
-            .lsp
-            .get_definition(&target_file, target_line, target_col)
-            .await?;
+        let Ok(Some(selection)) = self.lsp.get_selection_range(file, line, col).await else {
+            return (line, col);
+        };
 
-        for location in defs {
-            let uri_str = location.uri.as_str();
-            let url = Url::parse(uri_str).map_err(|e| anyhow!("Failed to parse URI: {}", e))?;
-            let def_path = url.to_file_path().map_err(|_| anyhow!("URI is not a file path: {}", uri_str))?;
+        let mut current = Some(&selection);
+        while let Some(range) = current {
+            if range.range.start != range.range.end {
+                return (range.range.start.line, range.range.start.character);
+            }
+            current = range.parent.as_deref();
+        }
 
-            let def_line = location.range.start.line;
-            let def_col = location.range.start.character;
+        (line, col)
+    }
 
-            let def_id = NodeId {
-                file: def_path.clone(),
-                line: def_line,
-                column: def_col,
-            };
+    /// Fetch outgoing calls for several hierarchy items concurrently.
+    /// Results are returned in the same order as `items`.
+    async fn outgoing_calls(
+        &self,
+        items: Vec<CallHierarchyItem>,
+    ) -> Result<Vec<Vec<CallHierarchyOutgoingCall>>> {
+        let mut requests = JoinSet::new();
+        for (index, item) in items.into_iter().enumerate() {
+            let lsp = self.lsp.clone();
+            requests.spawn(async move { (index, lsp.get_outgoing_calls(item).await) });
+        }
+        self.report(SliceProgress::PendingRequests(requests.len()));
 
-            // Add definition node
-            let def_code = self.read_implementation(&def_path, def_line)?;
-            graph.add_node(CodeNode {
-                id: def_id.clone(),
-                code: def_code,
-                node_type: "definition".to_string(),
-            });
+        let mut results = Vec::new();
+        while let Some(joined) = requests.join_next().await {
+            self.report(SliceProgress::PendingRequests(requests.len()));
+            let (index, calls) = joined.map_err(|e| anyhow!("Outgoing calls task failed: {}", e))?;
+            results.push((index, calls?));
+        }
+        results.sort_by_key(|(index, _)| *index);
 
-            // Add edge: target -> definition
-            graph.add_edge(Edge {
-                from: target_id.clone(),
-                to: def_id.clone(),
-                edge_type: EdgeType::Defines,
-            });
+        Ok(results.into_iter().map(|(_, calls)| calls).collect())
+    }
 
-            // Expand outgoing calls from definition
-            let hierarchy_items = self.lsp.prepare_call_hierarchy(&def_path, def_line, def_col).await?;
-            for item in hierarchy_items {
-                let outgoing = self.lsp.get_outgoing_calls(item).await?;
-                for call in outgoing {
-                    let call_item = call.to;
-                    let uri_str = call_item.uri.as_str();
-                    // Skip if uri parsing fails or not a file
-                    if let Ok(url) = Url::parse(uri_str)
-                        && let Ok(call_path) = url.to_file_path() {
-                            let call_line = call_item.range.start.line;
-                            let call_col = call_item.range.start.character;
-
-                            let call_id = NodeId {
-                                file: call_path.clone(),
-                                line: call_line,
-                                column: call_col,
-                            };
+    /// Stream of progress events from slices started from now on, for progress
+    /// bars in CLIs and editors. Events are dropped while nobody is subscribed.
+    pub fn subscribe_progress(&self) -> broadcast::Receiver<SliceProgress> {
+        self.progress_tx.subscribe()
+    }
 
-                            // Avoid cycles or duplicates if already added
-                            if !graph.nodes.contains_key(&call_id) {
-                                // Phase 3: Prune unreachable calls
-                                // Check all call sites in the caller function
-                                let mut any_site_reachable = false;
-                                for range in &call.from_ranges {
-                                    if self.is_reachable(&def_path, range.start.line, range.start.character) {
-                                        any_site_reachable = true;
-                                        break;
-                                    }
-                                }
-
-                                if !any_site_reachable {
-                                    eprintln!("✂️ Pruned call to {} (all sites unreachable)", call_item.name);
-                                    continue;
-                                }
-
-                                let call_code = self.read_implementation(&call_path, call_line)?;
-                                graph.add_node(CodeNode {
-                                    id: call_id.clone(),
-                                    code: call_code,
-                                    node_type: "call".to_string(),
-                                });
-                            }
+    fn check_cancelled(&self) -> Result<()> {
+        match &self.cancel {
+            Some(token) if token.is_cancelled() => Err(anyhow!("Slice cancelled")),
+            _ => Ok(()),
+        }
+    }
 
-                            graph.add_edge(Edge {
-                                from: def_id.clone(),
-                                to: call_id,
-                                edge_type: EdgeType::Calls,
-                            });
-                        }
-                }
-            }
+    fn report(&self, event: SliceProgress) {
+        // No subscribers is fine
+        let _ = self.progress_tx.send(event);
+    }
+
+    /// Limit how many LSP requests the slicer keeps in flight at once.
+    /// With a pool, the limit also applies to each server a later slice switches to.
+    pub fn set_max_in_flight(&mut self, max: usize) {
+        self.max_in_flight = Some(max);
+        self.lsp = self.lsp.with_max_in_flight(max);
+    }
+
+    /// Switch to the pooled server owning `file`, if slicing through a pool
+    async fn use_pooled_client(&mut self, file: &Path) -> Result<()> {
+        let Some(pool) = &self.pool else {
+            return Ok(());
+        };
+        self.lsp = pool.client_for(file).await?;
+        if let Some(max) = self.max_in_flight {
+            self.lsp = self.lsp.with_max_in_flight(max);
         }
+        Ok(())
+    }
 
-        Ok(graph)
+    /// Configure which references are followed (declaration, path allowlist, cap)
+    pub fn set_reference_options(&mut self, options: ReferenceOptions) {
+        self.reference_options = options;
+    }
+
+    /// Replace the slicing tunables (see `SlicerConfig`)
+    pub fn set_config(&mut self, config: SlicerConfig) {
+        self.config = config;
+    }
+
+    pub fn config(&self) -> &SlicerConfig {
+        &self.config
+    }
+
+    /// Keep only byte-range locators for node code and read it when rendering,
+    /// trading disk reads for a smaller in-memory graph
+    pub fn set_lazy_code(&mut self, lazy: bool) {
+        self.lazy_code = lazy;
+    }
+
+    /// Trace LSP traffic issued while slicing (see `TraceLevel`)
+    pub fn set_lsp_trace(&self, level: TraceLevel) {
+        self.lsp.set_trace(level);
+    }
+
+    /// Slice against in-memory `text` for `file` instead of the file on disk
+    pub async fn open_overlay(&self, file: &PathBuf, text: String) -> Result<()> {
+        self.lsp.open_overlay(file, text).await
+    }
+
+    /// Drop an overlay opened with `open_overlay`
+    pub async fn close_overlay(&self, file: &PathBuf) -> Result<()> {
+        self.lsp.close_overlay(file).await
+    }
+
+    /// File content as the slicer sees it: overlay text if present, else disk
+    fn read_source(&self, file: &PathBuf) -> Result<Arc<str>> {
+        match self.lsp.overlay_text(file) {
+            Some(text) => Ok(Arc::from(text)),
+            None => self.sources.read(file),
+        }
     }
 
     /// Read a single line from file
     fn read_location(&self, file: &PathBuf, line: u32) -> Result<String> {
-        let content = fs::read_to_string(file)?;
+        let content = self.read_source(file)?;
         let lines: Vec<&str> = content.lines().collect();
 
         if (line as usize) < lines.len() {
@@ -252,17 +1900,31 @@ impl Slicer {
     }
 
     /// Read implementation block using Tree-sitter
-    fn read_implementation(&mut self, file: &PathBuf, start_line: u32) -> Result<String> {
-        let content = fs::read_to_string(file)?;
+    async fn read_implementation(&mut self, file: &PathBuf, start_line: u32) -> Result<String> {
+        let content = self.read_source(file)?;
+
+        // Items generated by a macro call: show the expansion instead of the opaque invocation
+        if let Some(expanded) = self.read_macro_expansion(file, &content, start_line).await {
+            return Ok(expanded);
+        }
 
         // Try to extract the block using tree-sitter
         if let Some(block) = self.extractor.extract_block(&content, start_line as usize, 0) {
             return Ok(block);
         }
 
-        // Fallback: read single line if extraction fails
-        // This can happen for non-block items or if the position is not inside a supported node
         let lines: Vec<&str> = content.lines().collect();
+
+        // Fallback: let the server delimit the item (macros, unusual formatting)
+        if let Some((start, end)) = self.folding_range_at(file, start_line).await {
+            let end = (end as usize).min(lines.len().saturating_sub(1));
+            if (start as usize) <= end {
+                return Ok(lines[start as usize..=end].join("\n"));
+            }
+        }
+
+        // Last resort: read single line
+        // This can happen for non-block items or if the position is not inside a supported node
         if (start_line as usize) < lines.len() {
             Ok(lines[start_line as usize].to_string())
         } else {
@@ -270,33 +1932,362 @@ impl Slicer {
         }
     }
 
-    /// Extract minimal context from graph
-    pub fn extract_context(&self, graph: &DependencyGraph, max_depth: usize) -> String {
-        let mut context = String::new();
+    /// Expand a macro invocation on `line` through rust-analyzer, if there is one
+    async fn read_macro_expansion(&mut self, file: &PathBuf, content: &str, line: u32) -> Option<String> {
+        let (row, byte_col) = self.extractor.macro_invocation_at(content, line as usize)?;
+        let line_text = content.lines().nth(row)?;
+        let col = position::from_byte_column(line_text, byte_col, self.lsp.position_encoding());
+
+        let expanded = self.lsp.expand_macro(file, row as u32, col).await.ok()??;
+        Some(self.extractor.format_expansion(line_text, &expanded.name, &expanded.expansion))
+    }
 
-        // Safety check for empty graph
-        let root = match graph.nodes.keys().next() {
-            Some(id) => id,
-            None => return String::from("// No context found (graph is empty)"),
+    /// Smallest server folding range starting on `line`, as (start, end) lines
+    async fn folding_range_at(&self, file: &PathBuf, line: u32) -> Option<(u32, u32)> {
+        if !self.lsp.supports(LspFeature::FoldingRange) {
+            return None;
+        }
+
+        let ranges = self.lsp.get_folding_ranges(file).await.ok()?;
+        ranges
+            .into_iter()
+            .filter(|r| r.start_line == line)
+            .filter(|r| !matches!(r.kind, Some(FoldingRangeKind::Comment) | Some(FoldingRangeKind::Imports)))
+            .map(|r| (r.start_line, r.end_line))
+            .min_by_key(|(start, end)| end - start)
+    }
+
+    /// Write `graph` to `path` as a self-contained `SliceBundle`: the graph with its
+    /// provenance, the context rendered within `max_tokens`, snapshots of every file the
+    /// slice touches (overlays as edited) and the settings it was built with
+    pub fn export_bundle(&self, graph: &DependencyGraph, path: &Path) -> Result<()> {
+        let context = match graph.target() {
+            Some(target) => HierarchicalContext::build(graph, &target.id, self.config.max_tokens).render(),
+            None => String::new(),
         };
 
-        for (node_id, depth) in graph.bfs_from(root) {
-            if depth > max_depth {
-                break;
+        let mut files = BTreeMap::new();
+        let touched: BTreeSet<&PathBuf> = graph
+            .nodes
+            .values()
+            .flat_map(|node| std::iter::once(&node.id).chain(&node.positions))
+            .map(|id| &id.file)
+            .collect();
+        for file in touched {
+            match self.read_source(file) {
+                Ok(text) => {
+                    files.insert(file.clone(), text.to_string());
+                }
+                Err(e) => eprintln!("⚠️  Leaving {} out of the bundle: {}", file.display(), e),
             }
+        }
+
+        // The bundle owns its graph; rebuild one from copies of the nodes and edges
+        let mut copy = DependencyGraph::from_parts(graph.sorted_nodes().into_iter().cloned().collect(), graph.edges.clone());
+        copy.truncated = graph.truncated.clone();
+        let rules = self.extractor.rules().sources();
+        let bundle = SliceBundle {
+            workspace_root: self.workspace_root.clone(),
+            settings: json!({
+                "config": self.config,
+                "reference_options": self.reference_options,
+                "lazy_code": self.lazy_code,
+                "rules": { "blocks": rules.0, "symbols": rules.1 },
+            }),
+            context,
+            files,
+            graph: copy,
+        };
+        bundle.save(path)?;
+        eprintln!("📦 Bundled {} nodes and {} files into {}", bundle.graph.nodes.len(), bundle.files.len(), path.display());
+        Ok(())
+    }
+
+    /// Extract minimal context from graph, traversing from its targets. Nodes are printed
+    /// dependencies first, each labelled with its depth from the nearest root.
+    pub fn extract_context(&self, graph: &DependencyGraph, max_depth: usize) -> String {
+        // Start from the targets; graphs without one start from their first node
+        let mut roots: Vec<NodeId> = graph.targets().into_iter().map(|node| node.id.clone()).collect();
+        if roots.is_empty() {
+            roots.extend(graph.sorted_nodes().into_iter().next().map(|node| node.id.clone()));
+        }
+        self.extract_context_from(graph, &roots, max_depth)
+    }
+
+    /// Like `extract_context`, but traversing from `roots` rather than the graph's targets
+    pub fn extract_context_from(&self, graph: &DependencyGraph, roots: &[NodeId], max_depth: usize) -> String {
+        let mut context = String::new();
+
+        let roots: Vec<NodeId> = roots
+            .iter()
+            .map(|root| graph.resolve(root).clone())
+            .filter(|root| graph.contains_node(root))
+            .collect();
+        if roots.is_empty() {
+            return String::from("// No context found (graph is empty)");
+        }
+
+        let depths: HashMap<NodeId, usize> = graph
+            .bfs_from_many(&roots)
+            .into_iter()
+            .take_while(|(_, depth)| *depth <= max_depth)
+            .collect();
+
+        // Emit dependencies before the code that uses them
+        for node_id in graph.strongly_connected_components().into_iter().flatten() {
+            let Some(&depth) = depths.get(&node_id) else { continue };
 
             if let Some(node) = graph.nodes.get(&node_id) {
+                let module = node
+                    .module_path
+                    .as_deref()
+                    .map(|path| format!(" [{}]", path))
+                    .unwrap_or_default();
+                let merged = match node.positions.len() {
+                    0 => String::new(),
+                    n => format!(" (+{} more sites)", n),
+                };
+                let tags = if node.tags.is_empty() {
+                    String::new()
+                } else {
+                    format!(" {{{}}}", format_tags(&node.tags))
+                };
+                let imports: String = node.imports.iter().map(|import| format!("{}\n", import)).collect();
                 context.push_str(&format!(
-                    "// {}:{}:{} (depth {})\n{}\n\n",
+                    "// {}:{}:{}{}{}{} (depth {})\n{}{}\n\n",
                     node_id.file.display(),
                     node_id.line,
                     node_id.column,
+                    module,
+                    merged,
+                    tags,
                     depth,
-                    node.code
+                    imports,
+                    node.text()
                 ));
             }
         }
 
         context
     }
-}
\ No newline at end of file
+}
+
+/// Identifier-like words in `code`
+fn identifiers(code: &str) -> HashSet<&str> {
+    code.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Append `seed` to the comma-separated `seeds` tag, once
+fn add_seed(tags: &mut BTreeMap<String, String>, seed: &str) {
+    let seeds = tags.entry("seeds".to_string()).or_default();
+    if !seeds.split(", ").any(|existing| existing == seed) {
+        if !seeds.is_empty() {
+            seeds.push_str(", ");
+        }
+        seeds.push_str(seed);
+    }
+}
+
+/// Byte range of the occurrence of `code` in `source` that covers 0-based `line`
+fn locate_code(source: &str, line: u32, code: &str) -> Option<CodeLocator> {
+    if code.is_empty() {
+        return None;
+    }
+    let line_start: usize = source.split_inclusive('\n').take(line as usize).map(str::len).sum();
+    let line_end = line_start + source[line_start..].find('\n').unwrap_or(source.len() - line_start);
+    source
+        .match_indices(code)
+        .map(|(start, _)| start)
+        .find(|&start| start <= line_end && start + code.len() >= line_start)
+        .map(|start| CodeLocator { start_byte: start, end_byte: start + code.len() })
+}
+
+/// Whether a line of code starts an assertion (`assert!`, `assert_eq!`,
+/// `debug_assert_ne!` and so on)
+fn is_assertion(line: &str) -> bool {
+    let line = line.trim_start();
+    let name = line.split('!').next().unwrap_or_default();
+    line.contains('!') && (name.starts_with("assert") || name.starts_with("debug_assert")) && !name.contains(' ')
+}
+
+/// Module path a file contributes, from the directories below `src` or `tests`:
+/// `src/parser/mod.rs` gives `["parser"]`, `src/lib.rs` gives nothing
+fn file_modules(file: &Path) -> Vec<String> {
+    let components: Vec<String> =
+        file.with_extension("").components().map(|c| c.as_os_str().to_string_lossy().into_owned()).collect();
+    let start = components.iter().rposition(|c| c == "src" || c == "tests").map_or(components.len(), |i| i + 1);
+    let mut modules = components[start..].to_vec();
+    if matches!(modules.last().map(String::as_str), Some("mod" | "lib" | "main")) {
+        modules.pop();
+    }
+    // Each file directly under tests/ is its own crate
+    if components.get(start.wrapping_sub(1)).is_some_and(|c| c == "tests") && modules.len() == 1 {
+        modules.clear();
+    }
+    modules
+}
+
+/// Whether a requested module path names `modules`. Either may carry extra leading
+/// segments, e.g. the crate name or the file's own modules.
+fn modules_match(requested: &[&str], modules: &[String]) -> bool {
+    requested.iter().rev().zip(modules.iter().rev()).all(|(a, b)| a == b)
+}
+
+/// Name of the `mod` declared on `line` of `content` (`mod foo;` or `pub mod foo {`)
+fn mod_name_at(content: &str, line: u32) -> Option<String> {
+    let text = content.lines().nth(line as usize)?;
+    let mut tokens = text.split_whitespace();
+    tokens.find(|token| *token == "mod")?;
+    let name = tokens.next()?.trim_end_matches([';', '{']);
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Package name from the nearest Cargo.toml above `file`, as used in paths
+fn crate_name_for(file: &Path) -> Option<String> {
+    for dir in file.ancestors().skip(1) {
+        let manifest = dir.join("Cargo.toml");
+        if let Ok(content) = fs::read_to_string(&manifest) {
+            // Virtual workspace manifests have no [package]; keep looking upwards
+            if let Some(name) = package_name(&content) {
+                return Some(name.replace('-', "_"));
+            }
+        }
+    }
+    None
+}
+
+/// Minimal Cargo.toml scan for `[package] name = "..."`
+fn package_name(manifest: &str) -> Option<String> {
+    let mut in_package = false;
+    for line in manifest.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+            continue;
+        }
+        if in_package
+            && let Some(value) = line.strip_prefix("name")
+            && let Some(value) = value.trim_start().strip_prefix('=') {
+                return Some(value.trim().trim_matches('"').to_string());
+            }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identifiers() {
+        let words = identifiers("let map: Map<u32, _> = HashMap::new();");
+        assert!(words.contains("Map") && words.contains("HashMap") && words.contains("new"));
+        assert!(!words.contains("HashMap::new"));
+    }
+
+    #[test]
+    fn test_changed_lines() {
+        let patch = "\
+diff --git a/src/lib.rs b/src/lib.rs
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -2,3 +2,3 @@ fn a() {}
+ fn b() {
+-    old();
++    new();
+ }
+@@ -10,2 +10,1 @@
+ fn c() {}
+-fn d() {}
+--- a/src/gone.rs
++++ /dev/null
+@@ -1 +0,0 @@
+-fn e() {}
+";
+        let changed = changed_lines(patch);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[Path::new("src/lib.rs")], BTreeSet::from([2, 10]));
+    }
+
+    #[test]
+    fn test_mod_name_at() {
+        let content = "use std::fs;\nmod cache;\npub(crate) mod graph {\n}\n";
+        assert_eq!(mod_name_at(content, 1).as_deref(), Some("cache"));
+        assert_eq!(mod_name_at(content, 2).as_deref(), Some("graph"));
+        assert_eq!(mod_name_at(content, 0), None);
+    }
+
+    #[test]
+    fn test_test_path_matching() {
+        let modules = file_modules(Path::new("/ws/src/parser/mod.rs"));
+        assert_eq!(modules, vec!["parser".to_string()]);
+        assert!(file_modules(Path::new("/ws/src/lib.rs")).is_empty());
+        assert!(file_modules(Path::new("/ws/tests/cli.rs")).is_empty());
+
+        let modules = vec!["parser".to_string(), "tests".to_string()];
+        assert!(modules_match(&["parser", "tests"], &modules));
+        assert!(modules_match(&["app", "parser", "tests"], &modules));
+        assert!(modules_match(&[], &modules));
+        assert!(!modules_match(&["lexer", "tests"], &modules));
+
+        assert!(is_assertion("    assert_eq!(x, 1);"));
+        assert!(is_assertion("debug_assert!(ok)"));
+        assert!(!is_assertion("let asserted = check!(x);"));
+    }
+
+    #[test]
+    fn test_backtrace_locations() {
+        let backtrace = "\
+thread 'main' panicked at src/parse.rs:42:9:
+called `Option::unwrap()` on a `None` value
+stack backtrace:
+   0: rust_begin_unwind
+             at /rustc/90b35a62/library/std/src/panicking.rs:665:5
+   1: app::parse::header
+             at ./src/parse.rs:42:9
+   2: app::main
+             at ./src/main.rs:7:5
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+        assert_eq!(
+            backtrace_locations(backtrace),
+            vec![
+                (PathBuf::from("src/parse.rs"), 41),
+                (PathBuf::from("/rustc/90b35a62/library/std/src/panicking.rs"), 664),
+                (PathBuf::from("src/main.rs"), 6),
+            ]
+        );
+
+        let old_style = "thread 'main' panicked at 'index out of bounds', src/lib.rs:3:14";
+        assert_eq!(backtrace_locations(old_style), vec![(PathBuf::from("src/lib.rs"), 2)]);
+    }
+
+    #[test]
+    fn test_add_seed_dedupes() {
+        let mut tags = BTreeMap::new();
+        add_seed(&mut tags, "/a.rs:1:0");
+        add_seed(&mut tags, "/b.rs:4:2");
+        add_seed(&mut tags, "/a.rs:1:0");
+        assert_eq!(tags["seeds"], "/a.rs:1:0, /b.rs:4:2");
+    }
+
+    #[test]
+    fn test_config_admits_paths() {
+        let mut config = SlicerConfig { path_excludes: vec![PathBuf::from("/ws/vendor")], ..SlicerConfig::default() };
+        assert!(config.admits(Path::new("/ws/src/lib.rs")));
+        assert!(!config.admits(Path::new("/ws/vendor/dep/lib.rs")));
+        assert!(config.admits(Path::new("/ws/tests/it.rs")));
+
+        config.exclude_globs = vec!["target/**".to_string(), "**/generated/**".to_string()];
+        assert!(!config.admits(Path::new("/ws/target/debug/build/out.rs")));
+        assert!(!config.admits(Path::new("/ws/src/generated/api.rs")));
+        assert!(config.admits(Path::new("/ws/src/targets.rs")));
+
+        config.include_tests = false;
+        assert!(!config.admits(Path::new("/ws/tests/it.rs")));
+        assert!(!config.admits(Path::new("/ws/src/parser_tests.rs")));
+        assert!(config.admits(Path::new("/ws/src/testsuite.rs")));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::graph::content_hash;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+struct CachedSource {
+    text: Arc<str>,
+    /// Hex sha256 of `text`
+    hash: String,
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+/// File contents read while slicing, shared so each file is read once per change.
+/// A file is re-read when its size or modification time differs from the cached copy;
+/// if the new contents hash the same, the cached text (and anything keyed by its
+/// hash, like parsed trees) stays in use.
+#[derive(Default)]
+pub struct SourceCache {
+    entries: Mutex<HashMap<PathBuf, CachedSource>>,
+}
+
+impl SourceCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Contents of `file`, from the cache unless the file changed on disk
+    pub fn read(&self, file: &Path) -> Result<Arc<str>> {
+        Ok(self.entry(file)?.0)
+    }
+
+    /// Hex sha256 of `file`'s contents
+    pub fn hash(&self, file: &Path) -> Result<String> {
+        Ok(self.entry(file)?.1)
+    }
+
+    fn entry(&self, file: &Path) -> Result<(Arc<str>, String)> {
+        let metadata = fs::metadata(file).with_context(|| format!("Failed to stat {}", file.display()))?;
+        let (modified, len) = (metadata.modified().ok(), metadata.len());
+
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(cached) = entries.get(file)
+            && cached.modified.is_some()
+            && cached.modified == modified
+            && cached.len == len
+        {
+            return Ok((cached.text.clone(), cached.hash.clone()));
+        }
+
+        let text = fs::read_to_string(file).with_context(|| format!("Failed to read {}", file.display()))?;
+        let hash = content_hash(&text);
+        let text = match entries.get(file) {
+            Some(cached) if cached.hash == hash => cached.text.clone(),
+            _ => Arc::from(text),
+        };
+        entries.insert(file.to_path_buf(), CachedSource { text: text.clone(), hash: hash.clone(), modified, len });
+        Ok((text, hash))
+    }
+
+    /// Forget `file`, forcing the next read to go to disk
+    pub fn invalidate(&self, file: &Path) {
+        self.entries.lock().unwrap().remove(file);
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rereads_changed_files() {
+        let file = std::env::temp_dir().join(format!("graphslice_sources_{}.rs", std::process::id()));
+        fs::write(&file, "fn a() {}\n").unwrap();
+        let cache = SourceCache::new();
+
+        let first = cache.read(&file).unwrap();
+        assert!(Arc::ptr_eq(&first, &cache.read(&file).unwrap()));
+
+        fs::write(&file, "fn a() {}\nfn b() {}\n").unwrap();
+        let second = cache.read(&file).unwrap();
+        assert_eq!(&*second, "fn a() {}\nfn b() {}\n");
+        assert_eq!(cache.hash(&file).unwrap(), content_hash(&second));
+
+        fs::remove_file(&file).unwrap();
+        assert!(cache.read(&file).is_err());
+    }
+}
@@ -0,0 +1,82 @@
+use crate::compression::estimate_tokens;
+use crate::graph::{DependencyGraph, NodeId};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Summary numbers for judging a slice, as returned by `DependencyGraph::stats`
+#[derive(Debug, Default, Clone)]
+pub struct GraphStats {
+    pub node_count: usize,
+    pub edge_count: usize,
+    /// Node count per `node_type`
+    pub nodes_by_type: BTreeMap<String, usize>,
+    /// Edge count per edge type name
+    pub edges_by_type: BTreeMap<&'static str, usize>,
+    /// Nodes at each BFS depth from the root; empty without a root
+    pub depth_histogram: BTreeMap<usize, usize>,
+    /// Nodes not reachable from the root; zero without a root
+    pub unreachable: usize,
+    pub code_bytes: usize,
+    pub estimated_tokens: usize,
+    /// Node count per file
+    pub files: BTreeMap<PathBuf, usize>,
+}
+
+impl DependencyGraph {
+    /// Compute counts, sizes and file coverage. With a `root`, also a depth histogram.
+    pub fn stats(&self, root: Option<&NodeId>) -> GraphStats {
+        let mut stats = GraphStats {
+            node_count: self.nodes.len(),
+            edge_count: self.edges.len(),
+            ..GraphStats::default()
+        };
+
+        for node in self.nodes.values() {
+            *stats.nodes_by_type.entry(node.node_type.clone()).or_default() += 1;
+            *stats.files.entry(node.id.file.clone()).or_default() += 1;
+            stats.code_bytes += node.code_len();
+            stats.estimated_tokens += estimate_tokens(&node.text());
+        }
+
+        for edge in &self.edges {
+            *stats.edges_by_type.entry(edge.edge_type.as_str()).or_default() += 1;
+        }
+
+        if let Some(root) = root {
+            let mut reached = 0;
+            for (id, depth) in self.bfs_from(root) {
+                if self.nodes.contains_key(&id) {
+                    *stats.depth_histogram.entry(depth).or_default() += 1;
+                    reached += 1;
+                }
+            }
+            stats.unreachable = self.nodes.len() - reached;
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CodeNode, Edge, EdgeType, test_id};
+
+    #[test]
+    fn test_stats() {
+        let mut graph = DependencyGraph::new();
+        graph.add_node(CodeNode::new(test_id(1), "fn a() { b() }".to_string(), "target"));
+        graph.add_node(CodeNode::new(test_id(5), "fn b() {}".to_string(), "call"));
+        graph.add_node(CodeNode::new(test_id(9), "fn c() {}".to_string(), "call"));
+        graph.add_edge(Edge::new(test_id(1), test_id(5), EdgeType::Calls));
+
+        let stats = graph.stats(Some(&test_id(1)));
+        assert_eq!(stats.node_count, 3);
+        assert_eq!(stats.nodes_by_type["call"], 2);
+        assert_eq!(stats.edges_by_type["calls"], 1);
+        assert_eq!(stats.depth_histogram, BTreeMap::from([(0, 1), (1, 1)]));
+        assert_eq!(stats.unreachable, 1);
+        assert_eq!(stats.code_bytes, 32);
+        assert_eq!(stats.files[&PathBuf::from("/src/lib.rs")], 3);
+    }
+}
@@ -1,6 +1,7 @@
+use crate::extractor::Condition;
 use anyhow::{Result, anyhow};
 use z3::{Solver, SatResult};
-use z3::ast::Int;
+use z3::ast::{Bool, Int};
 
 pub struct Verifier;
 
@@ -115,6 +116,67 @@ impl Verifier {
 
         solver.check() == SatResult::Sat
     }
+
+    /// Like `check_consistency`, with branch conditions that may combine comparisons
+    /// and boolean flags using `&&`, `||` and `!`. Conditions Z3 can't express are left out.
+    pub fn check_conditions(&self, constraints: &[(&str, &str, i64)], conditions: &[Condition]) -> bool {
+        let solver = Solver::new();
+
+        for (name, op, val) in constraints {
+            if let Some(constraint) = comparison(name, op, *val) {
+                solver.assert(&constraint);
+            }
+        }
+        for condition in conditions {
+            if let Some(condition) = condition_ast(condition) {
+                solver.assert(&condition);
+            }
+        }
+
+        solver.check() == SatResult::Sat
+    }
+}
+
+/// `name op val` over integers, or `None` for an unsupported operator
+fn comparison(name: &str, op: &str, val: i64) -> Option<Bool> {
+    let var = Int::new_const(name);
+    let val_ast = Int::from_i64(val);
+    let constraint = match op {
+        ">" => var.gt(&val_ast),
+        "<" => var.lt(&val_ast),
+        ">=" => var.ge(&val_ast),
+        "<=" => var.le(&val_ast),
+        "==" => var.eq(&val_ast),
+        "!=" => var.eq(&val_ast).not(),
+        _ => return None,
+    };
+    Some(constraint)
+}
+
+/// Z3 formula for `condition`. An inexpressible side of `And` (of `Or` under `Not`) is
+/// dropped, which only weakens it; anywhere else it makes the whole inexpressible.
+fn condition_ast(condition: &Condition) -> Option<Bool> {
+    polar_condition_ast(condition, true)
+}
+
+/// `condition_ast` for a condition under an even (`positive`) or odd number of `Not`s:
+/// an unknown side may only be dropped where that weakens the whole
+fn polar_condition_ast(condition: &Condition, positive: bool) -> Option<Bool> {
+    match condition {
+        Condition::Compare(c) => comparison(&c.var, &c.op, c.val),
+        Condition::Flag(name) => Some(Bool::new_const(name.as_str())),
+        Condition::And(left, right) => match (polar_condition_ast(left, positive), polar_condition_ast(right, positive)) {
+            (Some(left), Some(right)) => Some(Bool::and(&[&left, &right])),
+            (left, right) if positive => left.or(right),
+            _ => None,
+        },
+        Condition::Or(left, right) => match (polar_condition_ast(left, positive), polar_condition_ast(right, positive)) {
+            (Some(left), Some(right)) => Some(Bool::or(&[&left, &right])),
+            (left, right) if !positive => left.or(right),
+            _ => None,
+        },
+        Condition::Not(inner) => Some(polar_condition_ast(inner, !positive)?.not()),
+    }
 }
 
 
@@ -148,6 +210,28 @@ mod tests {
         assert!(reachable, "x > 5 should be reachable given x > 10");
     }
 
+    #[test]
+    fn test_compound_conditions() {
+        use crate::extractor::Constraint;
+        let verifier = Verifier::new().expect("Failed to create verifier");
+        let compare = |var: &str, op: &str, val| Condition::Compare(Constraint { var: var.to_string(), op: op.to_string(), val });
+
+        // x == 3: (x > 5 || x < 0) is impossible, (x > 5 || x < 4) is not
+        let constraints = vec![("x", "==", 3)];
+        let impossible = Condition::Or(Box::new(compare("x", ">", 5)), Box::new(compare("x", "<", 0)));
+        let possible = Condition::Or(Box::new(compare("x", ">", 5)), Box::new(compare("x", "<", 4)));
+        assert!(!verifier.check_conditions(&constraints, &[impossible]));
+        assert!(verifier.check_conditions(&constraints, std::slice::from_ref(&possible)));
+        assert!(!verifier.check_conditions(&constraints, &[Condition::Not(Box::new(possible))]));
+
+        let both = Condition::And(Box::new(compare("y", ">", 1)), Box::new(compare("y", "<", 1)));
+        assert!(!verifier.check_conditions(&[], &[both]));
+
+        // x == 10: dropping the inexpressible side of a negated `And` would wrongly leave `!(x > 5)`
+        let unknown = Condition::And(Box::new(compare("x", ">", 5)), Box::new(compare("x", "%", 2)));
+        assert!(verifier.check_conditions(&[("x", "==", 10)], &[Condition::Not(Box::new(unknown))]));
+    }
+
     #[test]
     fn test_equality() {
         let verifier = Verifier::new().expect("Failed to create verifier");